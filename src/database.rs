@@ -97,26 +97,72 @@
 //! To improve packing efficiency, jobs are dispatched in descending order of
 //! estimated runtime.  This requires an additional argument when queueing.
 
+use crate::depgraph::DependencyGraph;
+use crate::depgraph::GraphOptions;
 use crate::diag;
+use crate::diff::DatabaseDiff;
+use crate::duplicates;
+use crate::diag::Diagnostic;
 use crate::diag::DiagnosticClass;
+use crate::diag::Level;
 use crate::diag::Notation;
 use crate::export;
+use crate::extract;
+use crate::export::ExportError;
+use crate::export::LatexOptions;
+use crate::grammar::Grammar;
+use crate::html;
+use crate::lint;
+use crate::lint::LintResult;
+use crate::minimize;
+use crate::minimize::Candidates;
+use crate::minimize::MinimizeOptions;
+use crate::minimize::MinimizeResult;
+use crate::mmp;
 use crate::nameck::Nameset;
+use crate::pretty::PrettyPrinter;
+use crate::pretty::SymbolMap;
+use crate::proof::ProofStyle;
+use crate::prove;
+use crate::prove::ProveError;
+use crate::prove::ProveOptions;
+use crate::proof::ProofTreeArray;
+use crate::rename;
+use crate::rename::RenameError;
+use crate::rename::RenameResult;
+use crate::parser::Comparer;
+use crate::parser::Span;
+use crate::parser::StatementAddress;
+use crate::parser::StatementType;
 use crate::parser::StatementRef;
 use crate::scopeck;
 use crate::scopeck::ScopeResult;
+use crate::search;
+use crate::search::Query;
+use crate::search::QueryError;
+use crate::search::SearchMatch;
 use crate::segment_set::SegmentSet;
+use crate::segment_set::SourceInfo;
+use crate::trace::ProofTrace;
+use crate::typesetting::Typesetting;
 use std::cmp::Ordering;
 use std::collections::BinaryHeap;
 use std::fmt;
 use std::fs::File;
+use std::io;
+use std::io::Write;
+use std::mem;
 use std::panic;
+use std::path::Path;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic;
 use std::sync::Arc;
 use std::sync::Condvar;
 use std::sync::Mutex;
 use std::thread;
 use std::time::Instant;
 use crate::verify;
+use crate::verify::DvReport;
 use crate::verify::VerifyResult;
 
 /// Structure for options that affect database processing, and must be constant
@@ -145,6 +191,9 @@ pub struct DbOptions {
     pub incremental: bool,
     /// Number of jobs to run in parallel at any given time.
     pub jobs: usize,
+    /// True to record the time and work spent on each proof by the verifier;
+    /// see `VerifyResult::proof_profiles`.
+    pub profile: bool,
 }
 
 /// Wraps a heap-allocated closure with a difficulty score which can be used for
@@ -167,6 +216,35 @@ impl Ord for Job {
     }
 }
 
+/// Callback for the progress of an analysis pass, called with the name of the
+/// pass and the number of segments done out of the total.
+pub type ProgressFn = dyn Fn(&str, usize, usize) + Send + Sync;
+
+/// A handle which can stop the analysis passes of a `Database`, typically from
+/// another thread when the database is about to be reparsed.
+///
+/// Passes check for cancellation between jobs and, in the verifier, between
+/// statements.  A cancelled pass returns a partial result which will not be
+/// reused; the flag stays set until the next `Database::parse`.
+#[derive(Clone,Default,Debug)]
+pub struct Canceller(Arc<AtomicBool>);
+
+impl Canceller {
+    /// Asks the passes currently running to stop.
+    pub fn cancel(&self) {
+        self.0.store(true, atomic::Ordering::Relaxed);
+    }
+
+    /// True if `cancel` has been called since the last reset.
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(atomic::Ordering::Relaxed)
+    }
+
+    fn reset(&self) {
+        self.0.store(false, atomic::Ordering::Relaxed);
+    }
+}
+
 /// Object which holds the state of the work queue and allows queueing tasks to
 /// run on the thread pool.
 #[derive(Clone)]
@@ -176,6 +254,9 @@ pub struct Executor {
     mutex: Arc<Mutex<BinaryHeap<Job>>>,
     // Condvar used to notify work threads of new work.
     work_cv: Arc<Condvar>,
+    // Shared by all clones, so that any job can see a cancellation.
+    canceller: Canceller,
+    progress: Arc<Mutex<Option<Arc<ProgressFn>>>>,
 }
 
 /// Debug printing for `Executor` displays the current count of queued but not
@@ -231,6 +312,32 @@ impl Executor {
             concurrency,
             mutex,
             work_cv: cv,
+            canceller: Canceller::default(),
+            progress: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    /// Obtains a handle to cancel the work of this executor.
+    pub fn canceller(&self) -> Canceller {
+        self.canceller.clone()
+    }
+
+    /// True if the current work has been cancelled.  Jobs which can be
+    /// abandoned should check this before starting.
+    pub fn is_cancelled(&self) -> bool {
+        self.canceller.is_cancelled()
+    }
+
+    /// Sets the callback which passes report their progress to.
+    pub fn set_progress(&self, progress: Option<Arc<ProgressFn>>) {
+        *self.progress.lock().unwrap() = progress;
+    }
+
+    /// Reports that `done` of the `total` segments of a pass are finished.
+    pub fn progress(&self, pass: &str, done: usize, total: usize) {
+        let progress = self.progress.lock().unwrap().clone();
+        if let Some(progress) = progress {
+            progress(pass, done, total);
         }
     }
 
@@ -325,6 +432,50 @@ impl<T> Promise<T> {
     }
 }
 
+/// The reasons an operation on the proof of one theorem, such as
+/// `Database::replace_proof` or `Database::minimize`, can fail.
+#[derive(Clone,Debug,PartialEq,Eq)]
+pub enum TheoremError {
+    /// No statement has the given label.
+    NoSuchLabel(String),
+    /// The statement with the given label is not a `$p` statement.
+    NotProvable(String),
+    /// The `$p` statement with the given label has no `$=` proof section.
+    NoProof(String),
+    /// The proof does not verify; the error is located in the theorem.
+    Verify(Diagnostic),
+    /// The proof search of `Database::prove` failed.
+    Prove(ProveError),
+}
+
+impl From<Diagnostic> for TheoremError {
+    fn from(err: Diagnostic) -> TheoremError {
+        TheoremError::Verify(err)
+    }
+}
+
+impl From<ProveError> for TheoremError {
+    fn from(err: ProveError) -> TheoremError {
+        TheoremError::Prove(err)
+    }
+}
+
+impl fmt::Display for TheoremError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            TheoremError::NoSuchLabel(ref label) => write!(f, "{}: no such statement", label),
+            TheoremError::NotProvable(ref label) => write!(f, "{}: not a $p statement", label),
+            TheoremError::NoProof(ref label) => write!(f, "{}: no proof section", label),
+            TheoremError::Verify(ref err) => write!(f, "proof does not verify: {:?}", err),
+            TheoremError::Prove(ref err) => write!(f, "{}", err),
+        }
+    }
+}
+
+/// The result of minimizing one proof of a range; the range as a whole
+/// fails with a `TheoremError`.
+pub type MinimizeOutcome = Result<MinimizeResult, Diagnostic>;
+
 /// Master type of database containers.
 ///
 /// A variable of type `Database` holds a database, i.e. an ordered collection
@@ -340,6 +491,7 @@ impl<T> Promise<T> {
 /// place, such as the hash table of statement labels constructed by nameck,
 /// that table must be duplicated so that it can be updated for one database
 /// without affecting the other.
+#[derive(Clone)]
 pub struct Database {
     options: Arc<DbOptions>,
     segments: Option<Arc<SegmentSet>>,
@@ -353,6 +505,9 @@ pub struct Database {
     scopes: Option<Arc<ScopeResult>>,
     prev_verify: Option<Arc<VerifyResult>>,
     verify: Option<Arc<VerifyResult>>,
+    grammar: Option<Arc<Grammar>>,
+    lint: Option<Arc<LintResult>>,
+    typesetting: Option<Arc<Typesetting>>,
 }
 
 fn time<R, F: FnOnce() -> R>(opts: &DbOptions, name: &str, f: F) -> R {
@@ -367,6 +522,9 @@ fn time<R, F: FnOnce() -> R>(opts: &DbOptions, name: &str, f: F) -> R {
 impl Drop for Database {
     fn drop(&mut self) {
         time(&self.options.clone(), "free", move || {
+            self.grammar = None;
+            self.lint = None;
+            self.typesetting = None;
             self.prev_verify = None;
             self.verify = None;
             self.prev_scopes = None;
@@ -392,6 +550,9 @@ impl Database {
             nameset: None,
             scopes: None,
             verify: None,
+            grammar: None,
+            lint: None,
+            typesetting: None,
             prev_nameset: None,
             prev_scopes: None,
             prev_verify: None,
@@ -428,14 +589,23 @@ impl Database {
     /// current database content and incremental processing will be used as
     /// appropriate.
     pub fn parse(&mut self, start: String, text: Vec<(String, Vec<u8>)>) {
+        self.parse_result().exec.canceller.reset();
         time(&self.options.clone(), "parse", || {
             Arc::make_mut(self.segments.as_mut().unwrap()).read(start, text);
-            self.nameset = None;
-            self.scopes = None;
-            self.verify = None;
+            self.invalidate_analysis();
         });
     }
 
+    // drops the results of every analysis pass after the segments changed
+    fn invalidate_analysis(&mut self) {
+        self.nameset = None;
+        self.scopes = None;
+        self.verify = None;
+        self.grammar = None;
+        self.lint = None;
+        self.typesetting = None;
+    }
+
     /// Obtains a reference to the current parsed data.
     ///
     /// Unlike the other accessors, this is not lazy (subject to change when the
@@ -468,6 +638,9 @@ impl Database {
     ///
     /// All logical properties of the database (as opposed to surface syntactic
     /// properties) can be obtained from this object.
+    ///
+    /// If the pass is cancelled, this returns the previous result, which is
+    /// meaningless until the next `parse`.
     pub fn scope_result(&mut self) -> &Arc<ScopeResult> {
         if self.scopes.is_none() {
             self.name_result();
@@ -482,17 +655,24 @@ impl Database {
                     let ns = Arc::make_mut(self.prev_scopes.as_mut().unwrap());
                     scopeck::scope_check(ns, &parse, &name);
                 }
-                self.scopes = self.prev_scopes.clone();
+                if !parse.exec.is_cancelled() {
+                    self.scopes = self.prev_scopes.clone();
+                }
             });
         }
 
-        self.scopes.as_ref().unwrap()
+        // a cancelled pass leaves the previous result, which is stale but
+        // consistent, for later reuse
+        self.scopes.as_ref().or(self.prev_scopes.as_ref()).unwrap()
     }
 
     /// Calculates and returns verification information for the database.
     ///
     /// This is an optimized verifier which returns no useful information other
     /// than error diagnostics.  It does not save any parsed proof data.
+    ///
+    /// If the pass is cancelled, this returns a partial result, which is
+    /// meaningless until the next `parse`.
     pub fn verify_result(&mut self) -> &Arc<VerifyResult> {
         if self.verify.is_none() {
             self.name_result();
@@ -509,10 +689,154 @@ impl Database {
                     let ver = Arc::make_mut(self.prev_verify.as_mut().unwrap());
                     verify::verify(ver, &parse, &name, &scope);
                 }
-                self.verify = self.prev_verify.clone();
+                if !parse.exec.is_cancelled() {
+                    self.verify = self.prev_verify.clone();
+                }
+            });
+        }
+        // a cancelled pass leaves a partial result, which is not reused
+        self.verify.as_ref().or(self.prev_verify.as_ref()).unwrap()
+    }
+
+    /// Calculates and returns the grammar of the database, as defined by its
+    /// syntax axioms.
+    ///
+    /// This is not incremental; any change to the database causes the grammar
+    /// to be rebuilt from scratch when next requested.
+    pub fn grammar_result(&mut self) -> &Arc<Grammar> {
+        if self.grammar.is_none() {
+            self.scope_result();
+            time(&self.options.clone(), "grammar", || {
+                let parse = self.parse_result().clone();
+                let scope = self.scope_result().clone();
+                let name = self.name_result().clone();
+                self.grammar = Some(Arc::new(Grammar::new(&parse, &name, &scope)));
+            });
+        }
+        self.grammar.as_ref().unwrap()
+    }
+
+    /// Calculates and returns the lints for the database; see the `lint`
+    /// module.
+    ///
+    /// Like the grammar, this is rebuilt from scratch after any change.
+    pub fn lint_result(&mut self) -> &Arc<LintResult> {
+        if self.lint.is_none() {
+            self.scope_result();
+            time(&self.options.clone(), "lint", || {
+                let parse = self.parse_result().clone();
+                let scope = self.scope_result().clone();
+                let name = self.name_result().clone();
+                self.lint = Some(Arc::new(lint::lint(&parse, &name, &scope)));
             });
         }
-        self.verify.as_ref().unwrap()
+        self.lint.as_ref().unwrap()
+    }
+
+    /// Collects the typesetting definitions of the `$t` comments; see the
+    /// `typesetting` module.
+    ///
+    /// This is rebuilt from scratch after any change.
+    pub fn typesetting_result(&mut self) -> &Arc<Typesetting> {
+        if self.typesetting.is_none() {
+            time(&self.options.clone(), "typesetting", || {
+                let parse = self.parse_result().clone();
+                self.typesetting = Some(Arc::new(Typesetting::new(&parse)));
+            });
+        }
+        self.typesetting.as_ref().unwrap()
+    }
+
+    /// Finds the proofs tagged `(Proof modification is discouraged.)` which
+    /// differ from their version in `baseline`; see
+    /// `lint::discouraged_changes`.
+    pub fn discouraged_changes(&mut self, baseline: &mut Database) -> Vec<Notation> {
+        let base_parse = baseline.parse_result().clone();
+        let base_name = baseline.name_result().clone();
+        time(&self.options.clone(), "discouraged", || {
+            let parse = self.parse_result().clone();
+            let diags = lint::discouraged_changes(&parse, &base_parse, &base_name);
+            diag::to_annotations(&parse, diags)
+        })
+    }
+
+    /// Compares the assertions of this database with those of an older
+    /// version; see `diff::DatabaseDiff`.
+    pub fn diff(&mut self, old: &mut Database) -> DatabaseDiff {
+        let old_parse = old.parse_result().clone();
+        let old_scope = old.scope_result().clone();
+        let old_name = old.name_result().clone();
+        time(&self.options.clone(), "diff", || {
+            let parse = self.parse_result().clone();
+            let scope = self.scope_result().clone();
+            let name = self.name_result().clone();
+            DatabaseDiff::new(&old_parse, &old_name, &old_scope, &parse, &name, &scope)
+        })
+    }
+
+    /// Finds the classes of assertions which are the same up to renaming of
+    /// variables; see `duplicates::find_duplicates`.
+    pub fn duplicates(&mut self) -> Vec<Vec<StatementAddress>> {
+        time(&self.options.clone(), "duplicates", || {
+            let parse = self.parse_result().clone();
+            let scope = self.scope_result().clone();
+            duplicates::find_duplicates(&parse, &scope)
+        })
+    }
+
+    /// Reports every assertion which duplicates an earlier one, with a note at
+    /// the earlier one.
+    pub fn duplicate_notations(&mut self) -> Vec<Notation> {
+        let classes = self.duplicates();
+        let diags = duplicates::duplicate_diagnostics(&classes);
+        diag::to_annotations(self.parse_result(), diags)
+    }
+
+    /// Finds the assertions whose conclusion, or optionally one of whose `$e`
+    /// hypotheses, unifies with a query; see the `search` module.
+    pub fn search(&mut self, query: &str, hypotheses: bool) -> Result<Vec<SearchMatch>, QueryError> {
+        time(&self.options.clone(), "search", || {
+            let parse = self.parse_result().clone();
+            let scope = self.scope_result().clone();
+            let name = self.name_result().clone();
+            let grammar = self.grammar_result().clone();
+            let query = Query::parse(&name, &grammar, query)?;
+            Ok(search::search(&parse, &name, &scope, &grammar, &query, hypotheses))
+        })
+    }
+
+    /// Verifies only the proofs of the given statements, reusing the results of
+    /// the last full verification for segments which have not changed since.
+    ///
+    /// Returns the notations for the parse and scope diagnostics of the whole
+    /// database, since those can change the meaning of any statement, followed
+    /// by those for the verify diagnostics of the given statements.  The full
+    /// verification result is not updated.
+    pub fn verify_only(&mut self, addrs: &[StatementAddress]) -> Vec<Notation> {
+        let mut notes = self.diag_notations(vec![DiagnosticClass::Parse, DiagnosticClass::Scope]);
+        let diags = time(&self.options.clone(), "verify", || {
+            let parse = self.parse_result().clone();
+            let scope = self.scope_result().clone();
+            let name = self.name_result().clone();
+            let cache = self.prev_verify.clone().unwrap_or_default();
+            verify::verify_selected(&cache, &parse, &name, &scope, addrs)
+        });
+        notes.extend(diag::to_annotations(self.parse_result(), diags));
+        notes
+    }
+
+    /// Obtains a handle which can stop the passes of this database from another
+    /// thread; see `Canceller`.
+    pub fn canceller(&mut self) -> Canceller {
+        self.parse_result().exec.canceller()
+    }
+
+    /// Sets a callback to be told the progress of each analysis pass, as the
+    /// number of segments done out of the total.
+    pub fn set_progress<F>(&mut self, progress: F)
+        where F: Fn(&str, usize, usize) + Send + Sync + 'static
+    {
+        self.parse_result().exec.set_progress(Some(Arc::new(progress)));
     }
 
     /// Get a statement by label.
@@ -527,17 +851,476 @@ impl Database {
             let parse = self.parse_result().clone();
             let scope = self.scope_result().clone();
             let name = self.name_result().clone();
+            let grammar = self.grammar_result().clone();
             let sref = self.statement(&stmt)
             .unwrap_or_else(|| panic!("Label {} did not correspond to an existing statement",
                 &stmt));
-                
+            let pretty = PrettyPrinter {
+                nset: &name,
+                grammar: Some(&grammar),
+                symbols: &SymbolMap::default(),
+                width: 79,
+                indent: 2,
+            };
+
             File::create(format!("{}.mmp", stmt.clone()))
                 .map_err(export::ExportError::Io)
-                .and_then(|mut file| export::export_mmp(&parse, &name, &scope, sref, &mut file))
+                .and_then(|mut file| {
+                    export::export_mmp(&parse, &name, &scope, &pretty, sref, &mut file)
+                })
                 .unwrap()
         })
     }
 
+    /// Writes the given statements as LaTeX, using the `latexdef` definitions
+    /// of the `$t` comments; see `export::export_latex`.
+    pub fn export_latex<W: Write>(&mut self,
+                                  addrs: &[StatementAddress],
+                                  options: LatexOptions,
+                                  out: &mut W)
+                                  -> Result<(), ExportError> {
+        time(&self.options.clone(), "export_latex", || {
+            let parse = self.parse_result().clone();
+            let scope = self.scope_result().clone();
+            let name = self.name_result().clone();
+            let typesetting = self.typesetting_result().clone();
+            let stmts: Vec<_> = addrs.iter().map(|&addr| parse.statement(addr)).collect();
+            export::export_latex(&parse, &name, &scope, &typesetting, &stmts, options, out)
+        })
+    }
+
+    /// Replaces the proof of a `$p` statement with a rendering of `arr` in the
+    /// given style, and verifies the result.
+    ///
+    /// The new proof is written into the source buffer in place of the text
+    /// from `$=` to `$.`, continuing on the line of the `$=` if the old proof
+    /// did, and indented like the old proof otherwise.  Only the segment
+    /// containing the statement is reparsed, and all analysis passes are
+    /// invalidated.  The buffer is not written to disk.  If the new proof does
+    /// not verify, the edit is undone and the error of the first failing step
+    /// is returned; the other editing operations follow the same policy.
+    pub fn replace_proof(&mut self,
+                         label: &str,
+                         arr: &ProofTreeArray,
+                         style: ProofStyle)
+                         -> Result<(), TheoremError> {
+        time(&self.options.clone(), "replace", || {
+            let addr = self.theorem(label)?;
+            let parse = self.parse_result().clone();
+            let scope = self.scope_result().clone();
+            let name = self.name_result().clone();
+            let (region, text) =
+                export::proof_section_text(&parse, &name, &scope, parse.statement(addr), arr, style)
+                    .ok_or_else(|| TheoremError::NoProof(label.to_owned()))?;
+            let before = self.clone();
+            Arc::make_mut(self.segments.as_mut().unwrap())
+                .splice(addr.segment_id, region, text.as_bytes());
+            self.invalidate_analysis();
+            self.verify_edit(before, label)
+        })
+    }
+
+    // verifies the proof of `label` after an edit, restoring the database
+    // from before the edit if it fails
+    fn verify_edit(&mut self, before: Database, label: &str) -> Result<(), TheoremError> {
+        let parse = self.parse_result().clone();
+        let scope = self.scope_result().clone();
+        let name = self.name_result().clone();
+        let sref = parse.statement(name.lookup_label(label.as_bytes()).unwrap().address);
+        let result = verify::verify_one(&parse, &name, &scope, &mut (), sref);
+        if result.is_err() {
+            *self = before;
+        }
+        Ok(result?)
+    }
+
+    /// Compares the `$d` conditions needed by the proof of a `$p` statement
+    /// with those declared in its scope; see `verify::verify_dv`.
+    pub fn dv_report(&mut self, label: &str) -> Result<DvReport, TheoremError> {
+        time(&self.options.clone(), "dv", || {
+            let addr = self.theorem(label)?;
+            let parse = self.parse_result().clone();
+            let scope = self.scope_result().clone();
+            let name = self.name_result().clone();
+            Ok(verify::verify_dv(&parse, &name, &scope, parse.statement(addr))?)
+        })
+    }
+
+    /// Records each step of the proof of a `$p` statement, with the
+    /// substitutions and `$d` checks made by the verifier; see `trace`.
+    pub fn proof_trace(&mut self, label: &str) -> Result<ProofTrace, TheoremError> {
+        time(&self.options.clone(), "trace", || {
+            let addr = self.theorem(label)?;
+            let parse = self.parse_result().clone();
+            let scope = self.scope_result().clone();
+            let name = self.name_result().clone();
+            Ok(ProofTrace::new(&parse, &name, &scope, parse.statement(addr))?)
+        })
+    }
+
+    /// Adds the `$d` statements missing for the proof of a `$p` statement,
+    /// using `export::dv_insertion_edits`, and verifies the result.
+    ///
+    /// Like `replace_proof`, only the source buffer is changed, and the edits
+    /// are undone if the proof does not verify.  Returns the report the edits
+    /// were made from, or the error that prevented the proof from being
+    /// analyzed or verified.
+    pub fn add_missing_dv(&mut self, label: &str) -> Result<DvReport, TheoremError> {
+        let report = self.dv_report(label)?;
+        if report.missing.is_empty() {
+            return Ok(report);
+        }
+        time(&self.options.clone(), "dv", || {
+            let sref = self.statement(label).unwrap();
+            let addr = sref.address();
+            let edits = export::dv_insertion_edits(sref, &report);
+            let before = self.clone();
+            let segments = Arc::make_mut(self.segments.as_mut().unwrap());
+            for (span, text) in edits.into_iter().rev() {
+                segments.splice(addr.segment_id, span, text.as_bytes());
+            }
+            self.invalidate_analysis();
+            self.verify_edit(before, label)
+        })?;
+        Ok(report)
+    }
+
+    /// Renames a statement label and every reference to it, in proofs and in
+    /// `~ label` comment links; see the `rename` module.
+    ///
+    /// Each changed segment is spliced once and all analysis passes are
+    /// invalidated, then the database is checked again; the diagnostics are
+    /// part of the result.  Only the source buffers are changed, the files
+    /// can be written with `write_sources`.  Nothing is changed if the rename
+    /// is refused, and the rename is undone if the checked database has errors
+    /// which it did not have before.
+    pub fn rename_label(&mut self, old: &str, new: &str) -> Result<RenameResult, RenameError> {
+        time(&self.options.clone(), "rename", || {
+            let parse = self.parse_result().clone();
+            let name = self.name_result().clone();
+            rename::check_rename(&name, old, new)?;
+            let found = rename::find_references(&parse, &name, old);
+            let mut old_errors = self.error_diagnostics();
+            let before = self.clone();
+            let mut references = 0;
+            let mut files: Vec<String> = Vec::new();
+            let segments = Arc::make_mut(self.segments.as_mut().unwrap());
+            for (seg_id, spans) in found {
+                let file = segments.source_info(seg_id).name.clone();
+                if !files.contains(&file) {
+                    files.push(file);
+                }
+                references += spans.len();
+                let region = Span::new(spans[0].start as usize,
+                                       spans[spans.len() - 1].end as usize);
+                let buffer = segments.segment(seg_id).segment.buffer.clone();
+                let mut text = Vec::new();
+                let mut pos = region.start as usize;
+                for span in spans {
+                    text.extend_from_slice(&buffer[pos..span.start as usize]);
+                    text.extend_from_slice(new.as_bytes());
+                    pos = span.end as usize;
+                }
+                segments.splice(seg_id, region, &text);
+            }
+            self.invalidate_analysis();
+
+            // the rename keeps every statement at its address, so errors can be
+            // matched up by address and kind
+            let new_errors: Vec<_> = self.error_diagnostics()
+                .into_iter()
+                .filter(|&(addr, ref diag)| {
+                    match old_errors.iter().position(|&(old_addr, ref old_diag)| {
+                        old_addr == addr && mem::discriminant(old_diag) == mem::discriminant(diag)
+                    }) {
+                        Some(index) => {
+                            old_errors.swap_remove(index);
+                            false
+                        }
+                        None => true,
+                    }
+                })
+                .collect();
+            if !new_errors.is_empty() {
+                *self = before;
+                return Err(RenameError::DoesNotVerify(old.to_owned(), new_errors));
+            }
+            Ok(RenameResult {
+                references,
+                files,
+                diagnostics: self.diag_notations(vec![DiagnosticClass::Parse,
+                                                      DiagnosticClass::Scope,
+                                                      DiagnosticClass::Verify]),
+            })
+        })
+    }
+
+    // the parse, scope, and verify diagnostics which are errors
+    fn error_diagnostics(&mut self) -> Vec<(StatementAddress, Diagnostic)> {
+        let mut diags = self.parse_result().parse_diagnostics();
+        diags.extend(self.scope_result().diagnostics());
+        diags.extend(self.verify_result().diagnostics());
+        let parse = self.parse_result().clone();
+        diags.retain(|&(addr, ref diag)| {
+            diag::to_annotations(&parse, vec![(addr, diag.clone())])
+                .iter()
+                .any(|notation| matches!(notation.level, Level::Error))
+        });
+        diags
+    }
+
+    /// Writes the current source buffers of the named files back to disk,
+    /// including any changes made by `replace_proof`, `add_missing_dv` or
+    /// `rename_label`.
+    pub fn write_sources(&mut self, files: &[String]) -> io::Result<()> {
+        let parse = self.parse_result().clone();
+        for file in files {
+            let text = parse.segments()
+                .iter()
+                .map(|sref| parse.source_info(sref.id))
+                .find(|sinfo| &sinfo.name == file)
+                .map(|sinfo| sinfo.text.clone());
+            if let Some(text) = text {
+                File::create(file)?.write_all(&text)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes an HTML page for every axiom and theorem into the directory
+    /// `dir`; see the `html` module.  Returns the number of pages written.
+    pub fn write_html(&mut self, dir: &str, alt: bool) -> io::Result<usize> {
+        time(&self.options.clone(), "html", || {
+            let parse = self.parse_result().clone();
+            let scope = self.scope_result().clone();
+            let name = self.name_result().clone();
+            let typesetting = self.typesetting_result().clone();
+            html::write_html(&parse, &name, &scope, &typesetting, Path::new(dir), alt)
+        })
+    }
+
+    /// Rewrites every proof of the database in one style, and writes the result
+    /// to `path` as a single file with all inclusions expanded.
+    ///
+    /// Segments are processed in parallel.  The new file is then loaded and
+    /// checked on its own, and the diagnostics of that check are returned,
+    /// located in the new file; they include every proof whose re-rendered form
+    /// fails verification.
+    pub fn restyle_proofs(&mut self, style: ProofStyle, path: String) -> io::Result<Vec<Notation>> {
+        time(&self.options.clone(), "restyle", || {
+            let parse = self.parse_result().clone();
+            let scope = self.scope_result().clone();
+            let name = self.name_result().clone();
+            let mut promises = Vec::new();
+            for sref in parse.segments() {
+                let parse2 = parse.clone();
+                let name = name.clone();
+                let scope = scope.clone();
+                let id = sref.id;
+                promises.push(parse.exec.exec(sref.bytes(), move || {
+                    export::restyle_segment(&parse2, &name, &scope, id, style)
+                }));
+            }
+            let mut text = Vec::new();
+            for promise in promises {
+                text.extend_from_slice(&promise.wait());
+            }
+            File::create(&path)?.write_all(&text)?;
+
+            let mut check = Database::new(DbOptions {
+                autosplit: self.options.autosplit,
+                timing: self.options.timing,
+                trace_recalc: false,
+                incremental: false,
+                jobs: self.options.jobs,
+                profile: false,
+            });
+            check.parse(path.clone(), vec![(path, text)]);
+            Ok(check.diag_notations(vec![DiagnosticClass::Parse,
+                                         DiagnosticClass::Scope,
+                                         DiagnosticClass::Verify]))
+        })
+    }
+
+    /// Searches for a shorter proof of a `$p` statement; see the `minimize`
+    /// module.  The database is not changed.
+    pub fn minimize(&mut self,
+                    label: &str,
+                    options: &MinimizeOptions)
+                    -> Result<MinimizeResult, TheoremError> {
+        time(&self.options.clone(), "minimize", || {
+            let addr = self.theorem(label)?;
+            let parse = self.parse_result().clone();
+            let scope = self.scope_result().clone();
+            let name = self.name_result().clone();
+            let grammar = self.grammar_result().clone();
+            let cands = Candidates::new(&parse, &name, &scope, &grammar, options);
+            Ok(minimize::minimize(&parse,
+                                  &name,
+                                  &scope,
+                                  &grammar,
+                                  &cands,
+                                  parse.statement(addr),
+                                  options)?)
+        })
+    }
+
+    /// Searches for a proof of a goal in the scope of a `$p` statement, or of
+    /// the statement itself; see the `prove` module.  The database is not
+    /// changed.
+    pub fn prove(&mut self,
+                 label: &str,
+                 goal: Option<&str>,
+                 options: &ProveOptions)
+                 -> Result<ProofTreeArray, TheoremError> {
+        time(&self.options.clone(), "prove", || {
+            let addr = self.theorem(label)?;
+            let parse = self.parse_result().clone();
+            let scope = self.scope_result().clone();
+            let name = self.name_result().clone();
+            let grammar = self.grammar_result().clone();
+            Ok(prove::prove(&parse, &name, &scope, &grammar, parse.statement(addr), goal, options)?)
+        })
+    }
+
+    // finds the `$p` statement which an operation on one proof applies to
+    fn theorem(&mut self, label: &str) -> Result<StatementAddress, TheoremError> {
+        match self.statement(label) {
+            Some(stmt) if stmt.statement_type() == StatementType::Provable => Ok(stmt.address()),
+            Some(_) => Err(TheoremError::NotProvable(label.to_owned())),
+            None => Err(TheoremError::NoSuchLabel(label.to_owned())),
+        }
+    }
+
+    /// Lists the `$p` statements from `first` to `last` inclusive, in database
+    /// order.  Both ends must be `$p` statements.
+    pub fn provable_range(&mut self,
+                          first: &str,
+                          last: &str)
+                          -> Result<Vec<StatementAddress>, TheoremError> {
+        let (first, last) = (self.theorem(first)?, self.theorem(last)?);
+        let parse = self.parse_result().clone();
+        let mut out = Vec::new();
+        for sref in parse.segments() {
+            for stmt in sref {
+                let addr = stmt.address();
+                if stmt.statement_type() == StatementType::Provable &&
+                   parse.order.cmp(&addr, &first) != Ordering::Less &&
+                   parse.order.cmp(&addr, &last) != Ordering::Greater {
+                    out.push(addr);
+                }
+            }
+        }
+        Ok(out)
+    }
+
+    /// Lists the `$p` statements in the section of the database outline with
+    /// the given title, including its subsections, or returns `None` if there
+    /// is no such heading; see `StatementRef::heading`.
+    pub fn outline_section(&mut self, title: &str) -> Option<Vec<StatementAddress>> {
+        let parse = self.parse_result().clone();
+        let mut depth = None;
+        let mut out = Vec::new();
+        for sref in parse.segments() {
+            for stmt in sref {
+                if let Some((level, heading)) = stmt.heading() {
+                    match depth {
+                        Some(d) if level <= d => return Some(out),
+                        None if heading == title.as_bytes() => depth = Some(level),
+                        _ => {}
+                    }
+                } else if depth.is_some() && stmt.statement_type() == StatementType::Provable {
+                    out.push(stmt.address());
+                }
+            }
+        }
+        depth.map(|_| out)
+    }
+
+    /// Writes a database containing only what is needed to state and prove
+    /// the given assertions; see `extract::extract`.
+    pub fn extract<W: Write>(&mut self,
+                             roots: &[StatementAddress],
+                             out: &mut W)
+                             -> io::Result<usize> {
+        time(&self.options.clone(), "extract", || {
+            let parse = self.parse_result().clone();
+            let scope = self.scope_result().clone();
+            let name = self.name_result().clone();
+            extract::extract(&parse, &name, &scope, roots, out)
+        })
+    }
+
+    /// Walks the proof references of the given statements; see
+    /// `depgraph::DependencyGraph`.
+    pub fn dependency_graph(&mut self,
+                            roots: &[StatementAddress],
+                            options: &GraphOptions)
+                            -> DependencyGraph {
+        time(&self.options.clone(), "depgraph", || {
+            let parse = self.parse_result().clone();
+            let name = self.name_result().clone();
+            DependencyGraph::new(&parse, &name, roots, options)
+        })
+    }
+
+    /// Searches for shorter proofs of all `$p` statements from `first` to
+    /// `last` inclusive, in database order, running one job per proof.
+    ///
+    /// Fails if either end is not a `$p` statement; otherwise each proof has
+    /// its own result.
+    pub fn minimize_range(&mut self,
+                          first: &str,
+                          last: &str,
+                          options: &MinimizeOptions)
+                          -> Result<Vec<(StatementAddress, MinimizeOutcome)>, TheoremError> {
+        time(&self.options.clone(), "minimize", || {
+            let addrs = self.provable_range(first, last)?;
+            let parse = self.parse_result().clone();
+            let scope = self.scope_result().clone();
+            let name = self.name_result().clone();
+            let grammar = self.grammar_result().clone();
+            let cands = Arc::new(Candidates::new(&parse, &name, &scope, &grammar, options));
+
+            let mut promises = Vec::new();
+            for addr in addrs {
+                let parse2 = parse.clone();
+                let name = name.clone();
+                let scope = scope.clone();
+                let grammar = grammar.clone();
+                let cands = cands.clone();
+                let options = *options;
+                let estimate = parse.statement(addr).proof_len() as usize;
+                promises.push(parse.exec.exec(estimate, move || {
+                    let stmt = parse2.statement(addr);
+                    (addr,
+                     minimize::minimize(&parse2, &name, &scope, &grammar, &cands, stmt, &options))
+                }));
+            }
+            Ok(promises.into_iter().map(|promise| promise.wait()).collect())
+        })
+    }
+
+    /// Reads an mmj2 proof worksheet and checks it against the database.
+    ///
+    /// The worksheet is not added to the database; if it is correct, the
+    /// result contains the reconstructed proof.  Diagnostics are located within
+    /// the worksheet text, which is given the name `name`.
+    pub fn import_mmp(&mut self, name: String, text: Vec<u8>) -> mmp::WorksheetResult {
+        time(&self.options.clone(), "import", || {
+            let parse = self.parse_result().clone();
+            let scope = self.scope_result().clone();
+            let name_res = self.name_result().clone();
+            let grammar = self.grammar_result().clone();
+            let source = Arc::new(SourceInfo {
+                name,
+                span: Span::new(0, text.len()),
+                text: Arc::new(text),
+            });
+            mmp::import_mmp(&parse, &name_res, &scope, &grammar, source)
+        })
+    }
+
     /// Runs one or more passes and collects all errors they generate.
     ///
     /// Passes are identified by the `types` argument and are not inclusive; if
@@ -557,8 +1340,270 @@ impl Database {
         if types.contains(&DiagnosticClass::Verify) {
             diags.extend(self.verify_result().diagnostics());
         }
+        if types.contains(&DiagnosticClass::Lint) {
+            diags.extend(self.lint_result().diagnostics());
+        }
+        if types.contains(&DiagnosticClass::Typesetting) {
+            diags.extend(self.typesetting_result().diagnostics());
+        }
         time(&self.options.clone(),
              "diag",
              || diag::to_annotations(self.parse_result(), diags))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::database::Database;
+    use crate::database::DbOptions;
+    use crate::database::TheoremError;
+    use crate::export::ExportError;
+    use crate::export::LatexOptions;
+    use crate::proof::ListingStyle;
+    use crate::proof::ProofListing;
+    use crate::proof::ProofStyle;
+    use crate::proof::ProofTreeArray;
+    use crate::test_util::database;
+    use crate::test_util::PROP;
+    use crate::test_util::source_text;
+    use crate::test_util::temp_path;
+    use std::fs;
+    use std::sync::Arc;
+    use std::sync::Mutex;
+
+    // two proof layouts of a1i, and an incomplete proof
+    const THEOREMS: &str = "${
+  a1i.1 $e |- ph $.
+  a1i $p |- ( ps -> ph ) $=
+      wph wps wph wi a1i.1 wph wps ax-1 ax-mp $.
+  a1i2 $p |- ( ps -> ph ) $= ( wi ax-1 ax-mp ) ABAD CABEF $.
+$}
+id $p |- ( ph -> ph ) $= ? $.
+";
+
+    fn mkdb() -> Database {
+        database(&[PROP, THEOREMS].concat())
+    }
+
+    fn proof_of(db: &mut Database, label: &str) -> ProofTreeArray {
+        let sset = db.parse_result().clone();
+        let nset = db.name_result().clone();
+        let scope = db.scope_result().clone();
+        let stmt = db.statement(label).unwrap();
+        ProofTreeArray::new(&sset, &nset, &scope, stmt).unwrap()
+    }
+
+    #[test]
+    fn test_replace_proof() {
+        let mut db = mkdb();
+        let arr = proof_of(&mut db, "a1i");
+        assert_eq!(db.replace_proof("a1i", &arr, ProofStyle::Compressed), Ok(()));
+        assert_eq!(db.replace_proof("a1i2", &arr, ProofStyle::Normal), Ok(()));
+        let text = source_text(&mut db);
+        assert!(text.contains("  a1i $p |- ( ps -> ph ) $=
+      ( wi ax-1 ax-mp ) ABADCABEF $.
+  a1i2 $p |- ( ps -> ph ) $= wph wps wph wi a1i.1 wph wps ax-1 ax-mp $.
+$}
+id $p"));
+        // only the incomplete proof of id remains
+        let id = db.statement("id").unwrap().address();
+        assert!(db.verify_result().diagnostics().iter().all(|&(addr, _)| addr == id));
+        // a proof which does not verify is not written
+        let text = source_text(&mut db);
+        assert!(db.replace_proof("id", &arr, ProofStyle::Normal).is_err());
+        assert_eq!(source_text(&mut db), text);
+        assert_eq!(db.replace_proof("ax-1", &arr, ProofStyle::Normal),
+                   Err(TheoremError::NotProvable("ax-1".to_owned())));
+        assert_eq!(db.replace_proof("nosuch", &arr, ProofStyle::Normal),
+                   Err(TheoremError::NoSuchLabel("nosuch".to_owned())));
+    }
+
+    #[test]
+    fn test_restyle_proofs() {
+        let mut db = Database::new(DbOptions::default());
+        db.parse("test.mm".to_owned(),
+                 vec![("test.mm".to_owned(), b"$[ inc.mm $]\n".to_vec()),
+                      ("inc.mm".to_owned(), [PROP, THEOREMS].concat().into_bytes())]);
+        let path = temp_path("restyle.mm");
+        let path = path.to_str().unwrap().to_owned();
+        let notations = db.restyle_proofs(ProofStyle::Normal, path.clone()).unwrap();
+        let text = String::from_utf8(fs::read(&path).unwrap()).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert!(text.starts_with("$c ( ) -> wff |- $."));
+        assert!(text.ends_with("$= ? $.\n\n"));
+        assert!(text.contains("  a1i $p |- ( ps -> ph ) $=
+      wph wps wph wi a1i.1 wph wps ax-1 ax-mp $.
+  a1i2 $p |- ( ps -> ph ) $= wph wps wph wi a1i.1 wph wps ax-1 ax-mp $."));
+        // only the incomplete proof is reported, with its one subgoal
+        assert_eq!(notations.len(), 2);
+        assert_eq!(notations[1].args, vec![("goal", "|- ( ph -> ph )".to_owned())]);
+        assert_eq!(notations[0].source.name, path);
+    }
+
+    #[test]
+    fn test_add_missing_dv() {
+        let source = "${ $d ph ps $. ax-dv $a |- ( ph -> ps ) $. $}
+${
+  $d ph ps $.
+  ex $p wff ( ph -> ps ) $= wph wps wi $.
+$}
+${
+  $( Comment. $)
+  th $p |- ( ps -> ph ) $=
+    wps wph ax-dv $.
+  th3 $p |- ( ph -> ps ) $= wph wps ax-dv $.
+$}
+th2 $p |- ( ph -> ps ) $= wph wps ax-dv $.
+";
+        let mut db = database(&[PROP, source].concat());
+        let pair = (b"ph"[..].into(), b"ps"[..].into());
+        assert_eq!(db.dv_report("ex").unwrap().excess, vec![pair.clone()]);
+        assert_eq!(db.add_missing_dv("th").unwrap().missing, vec![pair.clone()]);
+        assert_eq!(db.add_missing_dv("th2").unwrap().missing, vec![pair.clone()]);
+        assert!(db.dv_report("th").unwrap().missing.is_empty());
+        // the condition added inside the group does not reach th3
+        assert_eq!(db.dv_report("th3").unwrap().missing, vec![pair]);
+        assert!(source_text(&mut db).ends_with("${
+  ${
+    $d ph ps $.
+    $( Comment. $)
+    th $p |- ( ps -> ph ) $=
+      wps wph ax-dv $.
+  $}
+  th3 $p |- ( ph -> ps ) $= wph wps ax-dv $.
+$}
+${
+  $d ph ps $.
+  th2 $p |- ( ph -> ps ) $= wph wps ax-dv $.
+$}
+"));
+    }
+
+    #[test]
+    fn test_verify_only() {
+        let rule = "#*".repeat(39);
+        let source = format!("$c |- wff $. $v ph $. wph $f wff ph $. ax $a |- ph $.
+$(
+{0}
+  First chapter
+{0}
+$)
+th1 $p |- ph $= ax $.
+$(
+{1}
+  A section
+$)
+th2 $p |- ph $= wph ax $.
+$(
+{0}
+  Second chapter
+$)
+th3 $p |- ph $= ax $.
+",
+                             rule,
+                             "=-".repeat(39));
+        let mut db = database(&source);
+        let section = db.outline_section("First chapter").unwrap();
+        assert_eq!(section,
+                   vec![db.statement("th1").unwrap().address(),
+                        db.statement("th2").unwrap().address()]);
+        assert_eq!(db.outline_section("Third chapter"), None);
+        assert_eq!(db.verify_only(&section).len(), 1);
+        let range = db.provable_range("th2", "th3").unwrap();
+        assert_eq!(db.verify_only(&range).len(), 1);
+        assert_eq!(db.provable_range("th1", "ax"),
+                   Err(TheoremError::NotProvable("ax".to_owned())));
+        assert_eq!(db.provable_range("nosuch", "th3"),
+                   Err(TheoremError::NoSuchLabel("nosuch".to_owned())));
+        db.verify_result();
+        assert_eq!(db.verify_only(&section).len(), 1);
+    }
+
+    #[test]
+    fn test_cancel() {
+        let mut db = mkdb();
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let seen2 = seen.clone();
+        db.set_progress(move |pass, done, total| {
+            seen2.lock().unwrap().push((pass.to_owned(), done, total));
+        });
+        db.canceller().cancel();
+        assert!(db.verify_result().diagnostics().is_empty());
+
+        // the next parse clears the cancellation, and nothing partial is reused
+        db.parse("test.mm".to_owned(),
+                 vec![("test.mm".to_owned(), [PROP, THEOREMS].concat().into_bytes())]);
+        seen.lock().unwrap().clear();
+        assert_eq!(db.verify_result().diagnostics().len(), 1);
+        assert_eq!(*seen.lock().unwrap(),
+                   vec![("scopeck".to_owned(), 1, 1), ("verify".to_owned(), 1, 1)]);
+    }
+
+    #[test]
+    fn test_proof_listing() {
+        let mut db = mkdb();
+        let arr = proof_of(&mut db, "a1i");
+        let sset = db.parse_result().clone();
+        let scope = db.scope_result().clone();
+        let listing = |style, essential| {
+            ProofListing {
+                    sset: &sset,
+                    scope: &scope,
+                    arr: &arr,
+                    style,
+                    essential,
+                }
+                .to_string()
+        };
+        assert_eq!(listing(ListingStyle::Lemmon, true),
+                   "1 a1i.1 |- ph
+2 ax-1 |- ( ph -> ( ps -> ph ) )
+3 1,2 ax-mp |- ( ps -> ph )
+");
+        assert_eq!(listing(ListingStyle::Tree, false),
+                   "1   wph wff ph
+2     wps wff ps
+3   2,1 wi wff ( ps -> ph )
+4   a1i.1 |- ph
+5   1,2 ax-1 |- ( ph -> ( ps -> ph ) )
+6 1,3,4,5 ax-mp |- ( ps -> ph )
+");
+    }
+
+    #[test]
+    fn test_export_latex() {
+        let latex = |defs: &str, options| {
+            let mut db = database(&format!("{}{}$( $t {} $)\n", PROP, THEOREMS, defs));
+            let addr = db.statement("a1i").unwrap().address();
+            let mut out = Vec::new();
+            db.export_latex(&[addr], options, &mut out).map(|()| String::from_utf8(out).unwrap())
+        };
+        let defs = r#"latexdef "(" as "(" ; latexdef ")" as ")" ; latexdef "|-" as "\vdash" ;
+            latexdef "ph" as "\varphi" ; latexdef "ps" as "\psi" ;"#;
+        let fragment = LatexOptions {
+            standalone: false,
+            proof: false,
+        };
+        match latex(defs, fragment) {
+            Err(ExportError::MissingLatexDef(symbols)) => assert_eq!(symbols, vec!["->"]),
+            other => panic!("unexpected result {:?}", other.map_err(|err| err.to_string())),
+        }
+
+        let defs = format!(r#"{} latexdef "->" as "\rightarrow" ;"#, defs);
+        assert_eq!(latex(&defs, fragment).unwrap(),
+                   r"
+\medskip\noindent\textbf{Theorem} \texttt{a1i}\par
+\noindent Hypothesis \texttt{a1i.1}: $\vdash \varphi$\par
+\noindent Assertion: $\vdash ( \psi \rightarrow \varphi )$\par
+");
+        let document = latex(&defs,
+                             LatexOptions {
+                                 standalone: true,
+                                 proof: true,
+                             })
+            .unwrap();
+        assert!(document.starts_with("\\documentclass{article}\n"));
+        assert!(document.ends_with("\\end{document}\n"));
+        assert!(document.contains(r"3 & 1, 2 & \texttt{ax-mp} & $\vdash ( \psi \rightarrow \varphi )$ \\"));
+    }
+}
@@ -0,0 +1,386 @@
+//! Dependency graphs of theorems.
+//!
+//! A `DependencyGraph` is built by walking the proof references of one or more
+//! theorems, breadth first, down to the axioms and definitions they rest on.
+//! Each node is an assertion, and each edge goes from a theorem to an
+//! assertion its proof refers to.  References are read from the text of the
+//! proofs with `proof::proof_references`, so proofs need not verify.
+//!
+//! The walk stops at axioms and definitions, which have no proof; they can
+//! also be left out, to show only the theorems above them.  Syntax axioms,
+//! which every proof of a set.mm-style database refers to, can be collapsed
+//! into a single node.  The walk can also be limited in depth, in which case
+//! the theorems at the limit are leaves.
+//!
+//! The graph can be written in Graphviz DOT, GraphML, or JSON.
+
+use crate::nameck::Nameset;
+use crate::parser::as_str;
+use crate::parser::StatementAddress;
+use crate::parser::StatementRef;
+use crate::parser::StatementType;
+use crate::proof::proof_references;
+use crate::segment_set::SegmentSet;
+use crate::util::HashMap;
+use crate::util::HashSet;
+use crate::util::new_map;
+use crate::util::new_set;
+use std::io;
+use std::io::Write;
+use std::str::FromStr;
+
+/// Output formats for a dependency graph.
+#[derive(Copy,Clone,Debug,PartialEq,Eq)]
+pub enum GraphFormat {
+    /// The Graphviz `dot` language.
+    Dot,
+    /// GraphML, an XML format read by tools such as yEd and Gephi.
+    GraphMl,
+    /// A JSON object with `nodes` and `edges` arrays.
+    Json,
+}
+
+impl FromStr for GraphFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<GraphFormat, String> {
+        match s {
+            "dot" => Ok(GraphFormat::Dot),
+            "graphml" => Ok(GraphFormat::GraphMl),
+            "json" => Ok(GraphFormat::Json),
+            _ => Err(format!("unknown graph format {}", s)),
+        }
+    }
+}
+
+/// The kinds of assertions in a dependency graph.
+#[derive(Copy,Clone,Debug,PartialEq,Eq)]
+pub enum NodeKind {
+    /// A `$p` statement.
+    Theorem,
+    /// A logical axiom: a `$a` statement with a provable typecode.
+    Axiom,
+    /// A definition: a logical axiom whose label starts with `df-`.
+    Definition,
+    /// A syntax axiom, or all of them if collapsed.
+    Syntax,
+}
+
+impl NodeKind {
+    /// The name of the kind in the output formats.
+    pub fn name(self) -> &'static str {
+        match self {
+            NodeKind::Theorem => "theorem",
+            NodeKind::Axiom => "axiom",
+            NodeKind::Definition => "definition",
+            NodeKind::Syntax => "syntax",
+        }
+    }
+}
+
+/// Sorts the assertions of a database into the kinds of `NodeKind`.
+///
+/// Logical axioms are told apart from syntax axioms by their typecode, which
+/// is that of some `$e` or `$p` statement; definitions are the logical axioms
+/// whose label starts with `df-`, as in set.mm.
+#[derive(Clone,Debug,Default)]
+pub struct AssertionKinds {
+    provable: HashSet<Vec<u8>>,
+}
+
+impl AssertionKinds {
+    /// Collects the typecodes of the `$e` and `$p` statements.
+    pub fn new(sset: &SegmentSet) -> AssertionKinds {
+        let mut provable = new_set();
+        for sref in sset.segments() {
+            for stmt in sref {
+                let stype = stmt.statement_type();
+                if (stype == StatementType::Essential || stype == StatementType::Provable) &&
+                   stmt.math_len() > 0 {
+                    provable.insert(stmt.math_at(0).slice.to_vec());
+                }
+            }
+        }
+        AssertionKinds { provable }
+    }
+
+    /// The kind of an `$a` or `$p` statement.
+    pub fn kind(&self, stmt: StatementRef) -> NodeKind {
+        if stmt.statement_type() == StatementType::Provable {
+            NodeKind::Theorem
+        } else if stmt.math_len() == 0 || !self.provable.contains(stmt.math_at(0).slice) {
+            NodeKind::Syntax
+        } else if stmt.label().starts_with(b"df-") {
+            NodeKind::Definition
+        } else {
+            NodeKind::Axiom
+        }
+    }
+}
+
+/// Options controlling the walk of `DependencyGraph::new`.
+#[derive(Clone,Debug,Default)]
+pub struct GraphOptions {
+    /// Leave out logical axioms.
+    pub omit_axioms: bool,
+    /// Leave out definitions.
+    pub omit_definitions: bool,
+    /// Represent all syntax axioms by a single node labelled `syntax`.
+    pub collapse_syntax: bool,
+    /// Do not follow the references of theorems at this distance from the
+    /// starting theorems.
+    pub max_depth: Option<usize>,
+}
+
+/// An assertion in a dependency graph.
+#[derive(Clone,Debug,PartialEq,Eq)]
+pub struct GraphNode {
+    /// The statement, or `None` for the collapsed syntax node.
+    pub address: Option<StatementAddress>,
+    /// The label of the statement.
+    pub label: String,
+    /// The kind of assertion.
+    pub kind: NodeKind,
+    /// The length of the shortest path from a starting theorem.
+    pub depth: usize,
+}
+
+/// The assertions used by some theorems, directly or indirectly.
+#[derive(Clone,Debug,Default)]
+pub struct DependencyGraph {
+    nodes: Vec<GraphNode>,
+    edges: Vec<(usize, usize)>,
+}
+
+impl DependencyGraph {
+    /// Walks the proof references from the given statements.
+    pub fn new(sset: &SegmentSet,
+               nset: &Nameset,
+               roots: &[StatementAddress],
+               options: &GraphOptions)
+               -> DependencyGraph {
+        let kinds = AssertionKinds::new(sset);
+        let kind = |addr: StatementAddress| kinds.kind(sset.statement(addr));
+
+        let mut graph = DependencyGraph::default();
+        let mut index: HashMap<Option<StatementAddress>, usize> = new_map();
+        let mut edges = new_set();
+        let mut add_node = |graph: &mut DependencyGraph, addr, depth| -> Option<usize> {
+            let kind = kind(addr);
+            let key = match kind {
+                NodeKind::Axiom if options.omit_axioms => return None,
+                NodeKind::Definition if options.omit_definitions => return None,
+                NodeKind::Syntax if options.collapse_syntax => None,
+                _ => Some(addr),
+            };
+            if let Some(&ix) = index.get(&key) {
+                return Some(ix);
+            }
+            index.insert(key, graph.nodes.len());
+            graph.nodes.push(GraphNode {
+                address: key,
+                label: match key {
+                    Some(addr) => as_str(sset.statement(addr).label()).to_owned(),
+                    None => "syntax".to_owned(),
+                },
+                kind,
+                depth,
+            });
+            Some(graph.nodes.len() - 1)
+        };
+
+        for &root in roots {
+            add_node(&mut graph, root, 0);
+        }
+        // nodes are added in breadth-first order, so they can be visited in
+        // the order of the list
+        let mut from = 0;
+        while from < graph.nodes.len() {
+            let depth = graph.nodes[from].depth;
+            if graph.nodes[from].kind != NodeKind::Theorem ||
+               options.max_depth.is_some_and(|max| depth >= max) {
+                from += 1;
+                continue;
+            }
+            let stmt = sset.statement(graph.nodes[from].address.unwrap());
+            for used in proof_references(sset, nset, stmt) {
+                if let Some(to) = add_node(&mut graph, used, depth + 1) {
+                    if edges.insert((from, to)) {
+                        graph.edges.push((from, to));
+                    }
+                }
+            }
+            from += 1;
+        }
+        graph
+    }
+
+    /// The assertions of the graph; the starting theorems come first, and the
+    /// others are in breadth-first order.
+    pub fn nodes(&self) -> &[GraphNode] {
+        &self.nodes
+    }
+
+    /// The references, from a theorem to an assertion used in its proof, as
+    /// indexes into `nodes`.
+    pub fn edges(&self) -> &[(usize, usize)] {
+        &self.edges
+    }
+
+    /// Writes the graph in one of the supported formats.
+    pub fn write<W: Write>(&self, format: GraphFormat, out: &mut W) -> io::Result<()> {
+        match format {
+            GraphFormat::Dot => self.write_dot(out),
+            GraphFormat::GraphMl => self.write_graphml(out),
+            GraphFormat::Json => self.write_json(out),
+        }
+    }
+
+    fn write_dot<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        writeln!(out, "digraph dependencies {{")?;
+        for node in &self.nodes {
+            let shape = match node.kind {
+                NodeKind::Theorem => "box",
+                NodeKind::Axiom => "ellipse",
+                NodeKind::Definition => "diamond",
+                NodeKind::Syntax => "plaintext",
+            };
+            writeln!(out, "  \"{}\" [shape={}];", quote(&node.label), shape)?;
+        }
+        for &(from, to) in &self.edges {
+            writeln!(out,
+                     "  \"{}\" -> \"{}\";",
+                     quote(&self.nodes[from].label),
+                     quote(&self.nodes[to].label))?;
+        }
+        writeln!(out, "}}")
+    }
+
+    fn write_graphml<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        writeln!(out, "<?xml version=\"1.0\" encoding=\"UTF-8\"?>")?;
+        writeln!(out, "<graphml xmlns=\"http://graphml.graphdrawing.org/xmlns\">")?;
+        writeln!(out,
+                 "  <key id=\"label\" for=\"node\" attr.name=\"label\" attr.type=\"string\"/>")?;
+        writeln!(out,
+                 "  <key id=\"kind\" for=\"node\" attr.name=\"kind\" attr.type=\"string\"/>")?;
+        writeln!(out,
+                 "  <key id=\"depth\" for=\"node\" attr.name=\"depth\" attr.type=\"int\"/>")?;
+        writeln!(out, "  <graph id=\"dependencies\" edgedefault=\"directed\">")?;
+        for (ix, node) in self.nodes.iter().enumerate() {
+            writeln!(out, "    <node id=\"n{}\">", ix)?;
+            writeln!(out, "      <data key=\"label\">{}</data>", xml_escape(&node.label))?;
+            writeln!(out, "      <data key=\"kind\">{}</data>", node.kind.name())?;
+            writeln!(out, "      <data key=\"depth\">{}</data>", node.depth)?;
+            writeln!(out, "    </node>")?;
+        }
+        for &(from, to) in &self.edges {
+            writeln!(out, "    <edge source=\"n{}\" target=\"n{}\"/>", from, to)?;
+        }
+        writeln!(out, "  </graph>")?;
+        writeln!(out, "</graphml>")
+    }
+
+    fn write_json<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        writeln!(out, "{{")?;
+        writeln!(out, "  \"nodes\": [")?;
+        for (ix, node) in self.nodes.iter().enumerate() {
+            writeln!(out,
+                     "    {{\"id\": {}, \"label\": \"{}\", \"kind\": \"{}\", \"depth\": {}}}{}",
+                     ix,
+                     quote(&node.label),
+                     node.kind.name(),
+                     node.depth,
+                     if ix + 1 < self.nodes.len() { "," } else { "" })?;
+        }
+        writeln!(out, "  ],")?;
+        writeln!(out, "  \"edges\": [")?;
+        for (ix, &(from, to)) in self.edges.iter().enumerate() {
+            writeln!(out,
+                     "    [{}, {}]{}",
+                     from,
+                     to,
+                     if ix + 1 < self.edges.len() { "," } else { "" })?;
+        }
+        writeln!(out, "  ]")?;
+        writeln!(out, "}}")
+    }
+}
+
+// escape a label for a double-quoted DOT or JSON string
+fn quote(label: &str) -> String {
+    label.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+fn xml_escape(label: &str) -> String {
+    label.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::database::Database;
+    use crate::depgraph::GraphFormat;
+    use crate::depgraph::GraphOptions;
+    use crate::depgraph::NodeKind;
+    use crate::test_util::database;
+    use crate::test_util::A1I;
+    use crate::test_util::PROP;
+
+    #[test]
+    fn test_dependency_graph() {
+        let text = "df-id $a |- ( ph -> ph ) $.
+            ${ th.1 $e |- ps $.
+               th $p |- ( ph -> ps ) $= wps wph th.1 a1i $. $}
+            th2 $p |- ( ph -> ph ) $= wph df-id $.";
+        let mut db = database(&[PROP, A1I, text].concat());
+        let th = db.statement("th").unwrap().address();
+        let th2 = db.statement("th2").unwrap().address();
+        let labels = |db: &mut Database, options: &GraphOptions| {
+            let graph = db.dependency_graph(&[th, th2], options);
+            let nodes: Vec<_> = graph.nodes().iter().map(|node| node.label.clone()).collect();
+            let edges: Vec<_> = graph.edges()
+                .iter()
+                .map(|&(from, to)| format!("{}>{}", nodes[from], nodes[to]))
+                .collect();
+            (nodes, edges)
+        };
+
+        let (nodes, edges) = labels(&mut db, &GraphOptions::default());
+        assert_eq!(nodes, vec!["th", "th2", "a1i", "df-id", "wi", "ax-1", "ax-mp"]);
+        assert_eq!(edges,
+                   vec!["th>a1i", "th2>df-id", "a1i>wi", "a1i>ax-1", "a1i>ax-mp"]);
+
+        let (nodes, edges) = labels(&mut db,
+                                    &GraphOptions {
+                                        omit_axioms: true,
+                                        omit_definitions: true,
+                                        collapse_syntax: true,
+                                        max_depth: None,
+                                    });
+        assert_eq!(nodes, vec!["th", "th2", "a1i", "syntax"]);
+        assert_eq!(edges, vec!["th>a1i", "a1i>syntax"]);
+
+        let graph = db.dependency_graph(&[th],
+                                        &GraphOptions {
+                                            max_depth: Some(1),
+                                            ..GraphOptions::default()
+                                        });
+        assert_eq!(graph.nodes().len(), 2);
+        assert_eq!(graph.nodes()[1].kind, NodeKind::Theorem);
+        assert_eq!(graph.nodes()[1].depth, 1);
+
+        let mut out = Vec::new();
+        graph.write(GraphFormat::Dot, &mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(),
+                   "digraph dependencies {
+  \"th\" [shape=box];
+  \"a1i\" [shape=box];
+  \"th\" -> \"a1i\";
+}
+");
+        let mut out = Vec::new();
+        graph.write(GraphFormat::Json, &mut out).unwrap();
+        assert!(String::from_utf8(out)
+            .unwrap()
+            .contains("{\"id\": 1, \"label\": \"a1i\", \"kind\": \"theorem\", \"depth\": 1}\n"));
+    }
+}
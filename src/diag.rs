@@ -34,6 +34,20 @@ pub enum DiagnosticClass {
     /// Verify errors do not invalidate the interpretation of statements, but
     /// affect only proofs.
     Verify,
+    /// Lints are warnings about valid constructs which are probably mistakes,
+    /// such as unused hypotheses.
+    Lint,
+    /// Malformed commands in `$t` typesetting comments.
+    Typesetting,
+}
+
+/// Identifies the proof step at which the verifier found a problem.
+#[derive(Debug,Clone,Copy,Eq,PartialEq)]
+pub struct ProofStep {
+    /// Position of the step in execution order, counting from 1.
+    pub index: usize,
+    /// The assertion the step applies.
+    pub assertion: StatementAddress,
 }
 
 /// List of all diagnostic codes.  For a description of each, see the source of
@@ -54,8 +68,11 @@ pub enum Diagnostic {
     CommentMarkerNotStart(Span),
     ConstantNotTopLevel,
     DisjointSingle,
+    DiscouragedProofChange,
+    DiscouragedUsage(StatementAddress),
     DjNotVariable(TokenIndex),
     DjRepeatedVariable(TokenIndex, TokenIndex),
+    DuplicateAssertion(StatementAddress),
     DuplicateExplicitLabel(Token),
     DuplicateLabel(StatementAddress),
     EmptyFilename,
@@ -73,28 +90,46 @@ pub enum Diagnostic {
     MidStatementCommentMarker(Span),
     MissingLabel,
     MissingProof(Span),
+    MmpBadHypothesis,
+    MmpBadLine,
+    MmpBadSymbol(Token),
+    MmpDuplicateStep(Token),
+    MmpDvMissing(Token, Token),
+    MmpDvViolation(Token, Token),
+    MmpHypCount(Token, usize, usize),
+    MmpHypNotFound(Token, usize),
+    MmpMissingQed,
+    MmpMissingTheorem,
+    MmpNoJustification,
+    MmpNotAssertion(Token),
+    MmpQedMismatch,
+    MmpStepMismatch(Token),
+    MmpUnknownStep(Token),
+    MmpUnparsable,
+    MmpUnresolved,
     NestedComment(Span, Span),
     NotActiveSymbol(TokenIndex),
-    ProofDvViolation,
+    ProofDvViolation(ProofStep, Token, Token, Token, Token),
     ProofExcessEnd,
-    ProofIncomplete,
+    ProofIncomplete(Box<[Token]>),
     ProofInvalidSave,
     ProofMalformedVarint,
     ProofNoSteps,
     ProofUnderflow,
     ProofUnterminatedRoster,
-    ProofWrongExprEnd,
+    ProofWrongExprEnd(Token, Token),
     ProofWrongTypeEnd,
     RepeatedLabel(Span, Span),
     SpuriousLabel(Span),
     SpuriousProof(Span),
-    StepEssenWrong,
+    StepEssenWrong(ProofStep, Token, Token),
     StepEssenWrongType,
-    StepFloatWrongType,
+    StepFloatWrongType(ProofStep, Token, Token),
     StepMissing(Token),
     StepOutOfRange,
     StepUsedAfterScope(Token),
     StepUsedBeforeDefinition(Token),
+    TypesettingSyntax(Span),
     SymbolDuplicatesLabel(TokenIndex, StatementAddress),
     SymbolRedeclared(TokenIndex, TokenAddress),
     UnclosedBeforeEof,
@@ -105,26 +140,35 @@ pub enum Diagnostic {
     UnclosedProof,
     UnknownKeyword(Span),
     UnmatchedCloseGroup,
+    UnusedEssential(StatementAddress),
+    UnusedFloating,
+    UnusedMandatoryVar(Token),
+    UnusedVariable(TokenIndex),
     VariableMissingFloat(TokenIndex),
     VariableRedeclaredAsConstant(TokenIndex, TokenAddress),
 }
 
 use self::Diagnostic::{BadCharacter, BadCommentEnd, BadExplicitLabel, BadFloating,
      BadLabel, ChainBackref, CommentMarkerNotStart, ConstantNotTopLevel,
-     DisjointSingle, DjNotVariable, DjRepeatedVariable, DuplicateExplicitLabel,
+     DisjointSingle, DiscouragedProofChange, DiscouragedUsage, DjNotVariable,
+     DjRepeatedVariable, DuplicateAssertion, DuplicateExplicitLabel,
      DuplicateLabel, EmptyFilename, EmptyMathString, EssentialAtTopLevel,
      ExprNotConstantPrefix, FilenameDollar, FilenameSpaces, FloatNotConstant,
      FloatNotVariable, FloatRedeclared, IoError, LocalLabelAmbiguous,
      LocalLabelDuplicate, MidStatementCommentMarker, MissingLabel, MissingProof,
-     NestedComment, NotActiveSymbol, ProofDvViolation, ProofExcessEnd,
+     MmpBadHypothesis, MmpBadLine, MmpBadSymbol, MmpDuplicateStep, MmpDvMissing,
+     MmpDvViolation, MmpHypCount, MmpHypNotFound, MmpMissingQed, MmpMissingTheorem,
+     MmpNoJustification, MmpNotAssertion, MmpQedMismatch, MmpStepMismatch,
+     MmpUnknownStep, MmpUnparsable, MmpUnresolved, NestedComment, NotActiveSymbol, ProofDvViolation, ProofExcessEnd,
      ProofIncomplete, ProofInvalidSave, ProofMalformedVarint, ProofNoSteps,
      ProofUnderflow, ProofUnterminatedRoster, ProofWrongExprEnd, ProofWrongTypeEnd,
      RepeatedLabel, SpuriousLabel, SpuriousProof, StepEssenWrong,
      StepEssenWrongType, StepFloatWrongType, StepMissing, StepOutOfRange,
      StepUsedAfterScope, StepUsedBeforeDefinition, SymbolDuplicatesLabel,
-     SymbolRedeclared, UnclosedBeforeEof, UnclosedBeforeInclude, UnclosedComment,
+     SymbolRedeclared, TypesettingSyntax, UnclosedBeforeEof, UnclosedBeforeInclude, UnclosedComment,
      UnclosedInclude, UnclosedMath, UnclosedProof, UnknownKeyword,
-     UnmatchedCloseGroup, VariableMissingFloat, VariableRedeclaredAsConstant};
+     UnmatchedCloseGroup, UnusedEssential, UnusedFloating, UnusedMandatoryVar,
+     UnusedVariable, VariableMissingFloat, VariableRedeclaredAsConstant};
 
 
 impl From<io::Error> for Diagnostic {
@@ -139,6 +183,9 @@ pub enum Level {
     /// Notes indicate other statements relevant to an error which is primarily
     /// elsewhere.
     Note,
+    /// Incomplete proofs are not errors in the database, but work in progress;
+    /// they are reported separately from warnings so they can be filtered.
+    Incomplete,
     /// Warnings indicate constructs which are defined by the spec but also
     /// forbidden by the spec, as well as issues with non-spec extensions.
     Warning,
@@ -214,6 +261,10 @@ fn annotate_diagnostic(notes: &mut Vec<Notation>,
         as_str(v).to_owned()
     }
 
+    fn l(sset: &SegmentSet, addr: StatementAddress) -> String {
+        as_str(sset.statement(addr).label()).to_owned()
+    }
+
     let mut info = AnnInfo {
         notes,
         sset,
@@ -269,6 +320,23 @@ fn annotate_diagnostic(notes: &mut Vec<Notation>,
             info.level = Warning;
             ann(&mut info, stmt.span());
         }
+        DiscouragedProofChange => {
+            info.s = "Proof modification of {label} is discouraged, but the proof differs from \
+                      the baseline";
+            info.level = Warning;
+            info.args.push(("label", as_str(stmt.label()).to_owned()));
+            ann(&mut info, stmt.span());
+        }
+        DiscouragedUsage(used) => {
+            info.s = "New usage of {used} is discouraged";
+            info.level = Warning;
+            info.args.push(("used", l(sset, used)));
+            ann(&mut info, stmt.span());
+            info.stmt = sset.statement(used);
+            info.s = "Usage is discouraged by the comment of this statement";
+            info.level = Note;
+            ann(&mut info, Span::null());
+        }
         DjNotVariable(index) => {
             info.s = "$d constraints are not applicable to constants";
             ann(&mut info, stmt.math_span(index));
@@ -280,6 +348,16 @@ fn annotate_diagnostic(notes: &mut Vec<Notation>,
             info.level = Note;
             ann(&mut info, stmt.math_span(index2));
         }
+        DuplicateAssertion(first) => {
+            info.s = "Assertion is the same as {first} up to renaming of variables";
+            info.level = Warning;
+            info.args.push(("first", l(sset, first)));
+            ann(&mut info, stmt.span());
+            info.stmt = sset.statement(first);
+            info.s = "Equivalent assertion is here";
+            info.level = Note;
+            ann(&mut info, Span::null());
+        }
         DuplicateExplicitLabel(ref tok) => {
             info.s = "Explicit label {label} is used twice in the same step";
             info.args.push(("label", t(tok)));
@@ -362,6 +440,14 @@ fn annotate_diagnostic(notes: &mut Vec<Notation>,
                      if you do not have a proof yet";
             ann(&mut info, math_end);
         }
+        MmpBadHypothesis | MmpBadLine | MmpBadSymbol(_) | MmpDuplicateStep(_) |
+        MmpDvMissing(..) | MmpDvViolation(..) | MmpHypCount(..) | MmpHypNotFound(..) |
+        MmpMissingQed | MmpMissingTheorem | MmpNoJustification | MmpNotAssertion(_) |
+        MmpQedMismatch | MmpStepMismatch(_) | MmpUnknownStep(_) | MmpUnparsable |
+        MmpUnresolved => {
+            worksheet_message(&mut info.s, &mut info.args, diag);
+            ann(&mut info, stmt.span());
+        }
         NestedComment(tok, opener) => {
             info.s = "Nested comments are not supported - comment will end at the first $)";
             info.level = Warning;
@@ -374,26 +460,36 @@ fn annotate_diagnostic(notes: &mut Vec<Notation>,
             info.s = "Token used here must be active in the current scope";
             ann(&mut info, stmt.math_span(index));
         }
-        ProofDvViolation => {
-            info.s = "Disjoint variable constraint violated";
-            info.args.push(("label", match str::from_utf8(stmt.label()) {
-                Ok(v) => v.to_string(),
-                Err(e) => panic!("Invalid UTF-8 sequence: {}", e),
-            }));
+        ProofDvViolation(step, ref var1, ref var2, ref expr1, ref expr2) => {
+            info.s = "Disjoint variable constraint of {assertion} violated in step {step}: \
+                      {var1} and {var2} occur in substitutions {expr1} and {expr2}";
+            info.args.push(("label", as_str(stmt.label()).to_owned()));
+            info.args.push(("step", d(step.index)));
+            info.args.push(("assertion", l(sset, step.assertion)));
+            info.args.push(("var1", t(var1)));
+            info.args.push(("var2", t(var2)));
+            info.args.push(("expr1", t(expr1)));
+            info.args.push(("expr2", t(expr2)));
             ann(&mut info, stmt.span());
         }
         ProofExcessEnd => {
             info.s = "Must be exactly one statement on stack at end of proof";
             ann(&mut info, stmt.span());
         }
-        ProofIncomplete => {
+        ProofIncomplete(ref goals) => {
             info.s = "Proof is incomplete";
-            info.level = Warning;
+            info.level = Incomplete;
             info.args.push(("label", match str::from_utf8(stmt.label()) {
                 Ok(v) => v.to_string(),
                 Err(e) => panic!("Invalid UTF-8 sequence: {}", e),
             }));
             ann(&mut info, stmt.span());
+            for goal in goals.iter() {
+                info.s = "Unproved subgoal {goal}";
+                info.level = Note;
+                info.args.push(("goal", t(goal)));
+                ann(&mut info, stmt.span());
+            }
         }
         ProofInvalidSave => {
             info.s = "Z must appear immediately after a complete step integer";
@@ -415,8 +511,11 @@ fn annotate_diagnostic(notes: &mut Vec<Notation>,
             info.s = "List of referenced assertions in a compressed proof must be terminated by )";
             ann(&mut info, stmt.span());
         }
-        ProofWrongExprEnd => {
-            info.s = "Final step statement does not match assertion";
+        ProofWrongExprEnd(ref expected, ref actual) => {
+            info.s = "Final step statement does not match assertion: expected {expected}, \
+                      found {actual}";
+            info.args.push(("expected", t(expected)));
+            info.args.push(("actual", t(actual)));
             ann(&mut info, stmt.span());
         }
         ProofWrongTypeEnd => {
@@ -438,16 +537,26 @@ fn annotate_diagnostic(notes: &mut Vec<Notation>,
             info.s = "Proofs are only allowed on $p assertions";
             ann(&mut info, math_end);
         }
-        StepEssenWrong => {
-            info.s = "Step used for $e hypothesis does not match statement";
+        StepEssenWrong(step, ref expected, ref actual) => {
+            info.s = "Step {step} ({assertion}) used for $e hypothesis does not match \
+                      statement: expected {expected}, found {actual}";
+            info.args.push(("step", d(step.index)));
+            info.args.push(("assertion", l(sset, step.assertion)));
+            info.args.push(("expected", t(expected)));
+            info.args.push(("actual", t(actual)));
             ann(&mut info, stmt.span());
         }
         StepEssenWrongType => {
             info.s = "Step used for $e hypothesis does not match typecode";
             ann(&mut info, stmt.span());
         }
-        StepFloatWrongType => {
-            info.s = "Step used for $f hypothesis does not match typecode";
+        StepFloatWrongType(step, ref expected, ref actual) => {
+            info.s = "Step {step} ({assertion}) used for $f hypothesis {expected} does not \
+                      match typecode: found {actual}";
+            info.args.push(("step", d(step.index)));
+            info.args.push(("assertion", l(sset, step.assertion)));
+            info.args.push(("expected", t(expected)));
+            info.args.push(("actual", t(actual)));
             ann(&mut info, stmt.span());
         }
         StepMissing(ref tok) => {
@@ -470,6 +579,11 @@ fn annotate_diagnostic(notes: &mut Vec<Notation>,
             info.args.push(("step", t(tok)));
             ann(&mut info, stmt.span());
         }
+        TypesettingSyntax(span) => {
+            info.s = "Malformed typesetting command; it is ignored up to the next ;";
+            info.level = Warning;
+            ann(&mut info, span);
+        }
         SymbolDuplicatesLabel(index, saddr) => {
             info.s = "Metamath spec forbids symbols which are the same as labels in the same \
                      database";
@@ -525,6 +639,33 @@ fn annotate_diagnostic(notes: &mut Vec<Notation>,
             info.s = "This $} does not match any open ${";
             ann(&mut info, stmt.span());
         }
+        UnusedEssential(hyp) => {
+            info.s = "Hypothesis {hyp} is not used by the proof";
+            info.level = Warning;
+            info.args.push(("hyp", l(sset, hyp)));
+            ann(&mut info, stmt.span());
+            info.stmt = sset.statement(hyp);
+            info.s = "Hypothesis is declared here";
+            info.level = Note;
+            ann(&mut info, Span::null());
+        }
+        UnusedFloating => {
+            info.s = "Variable type declaration is not used by any assertion in its scope";
+            info.level = Warning;
+            ann(&mut info, stmt.span());
+        }
+        UnusedMandatoryVar(ref var) => {
+            info.s = "Variable {var} is mandatory only because of hypotheses which the proof \
+                      does not use";
+            info.level = Warning;
+            info.args.push(("var", t(var)));
+            ann(&mut info, stmt.span());
+        }
+        UnusedVariable(index) => {
+            info.s = "Variable is not used by any assertion in its scope";
+            info.level = Warning;
+            ann(&mut info, stmt.math_span(index));
+        }
         VariableMissingFloat(index) => {
             info.s = "Variable token used in statement must have an active $f";
             ann(&mut info, stmt.math_span(index));
@@ -540,3 +681,103 @@ fn annotate_diagnostic(notes: &mut Vec<Notation>,
         }
     }
 }
+
+/// Converts the diagnostics of a proof worksheet, which are located by span
+/// within the worksheet rather than by statement, to a notation list.
+pub fn to_worksheet_annotations(source: &Arc<SourceInfo>,
+                                mut diags: Vec<(Span, Diagnostic)>)
+                                -> Vec<Notation> {
+    diags.sort_by_key(|&(span, _)| span.start);
+    diags.into_iter()
+        .map(|(span, diag)| {
+            let mut message = "";
+            let mut args = Vec::new();
+            worksheet_message(&mut message, &mut args, &diag);
+            Notation {
+                source: source.clone(),
+                message,
+                span,
+                level: Error,
+                args,
+            }
+        })
+        .collect()
+}
+
+fn worksheet_message(s: &mut &'static str,
+                     args: &mut Vec<(&'static str, String)>,
+                     diag: &Diagnostic) {
+    fn t(v: &Token) -> String {
+        as_str(v).to_owned()
+    }
+
+    match *diag {
+        MmpBadHypothesis => {
+            *s = "Hypothesis step does not match any $e hypothesis of the theorem";
+        }
+        MmpBadLine => {
+            *s = "Worksheet step must start with a field of the form step:hyps:ref";
+        }
+        MmpBadSymbol(ref tok) => {
+            *s = "Token {symbol} is not an active constant, variable, or work variable";
+            args.push(("symbol", t(tok)));
+        }
+        MmpDuplicateStep(ref tok) => {
+            *s = "Step {step} is defined more than once";
+            args.push(("step", t(tok)));
+        }
+        MmpDvMissing(ref var1, ref var2) => {
+            *s = "Proof requires a disjoint variable restriction on {var1} and {var2}";
+            args.push(("var1", t(var1)));
+            args.push(("var2", t(var2)));
+        }
+        MmpDvViolation(ref label, ref var) => {
+            *s = "Disjoint variable restriction of {label} is violated by {var} appearing in \
+                  both substitutions";
+            args.push(("label", t(label)));
+            args.push(("var", t(var)));
+        }
+        MmpHypCount(ref label, expected, actual) => {
+            *s = "{label} has {expected} $e hypotheses but the step lists {actual}";
+            args.push(("label", t(label)));
+            args.push(("expected", expected.to_string()));
+            args.push(("actual", actual.to_string()));
+        }
+        MmpHypNotFound(ref label, index) => {
+            *s = "No earlier step matches hypothesis {index} of {label}";
+            args.push(("label", t(label)));
+            args.push(("index", index.to_string()));
+        }
+        MmpMissingQed => {
+            *s = "Worksheet must have a qed step";
+        }
+        MmpMissingTheorem => {
+            *s = "Worksheet header must name an existing $p statement with THEOREM=";
+        }
+        MmpNoJustification => {
+            *s = "No assertion could be found which justifies this step";
+        }
+        MmpNotAssertion(ref label) => {
+            *s = "{label} is not an $a or $p statement available before the theorem";
+            args.push(("label", t(label)));
+        }
+        MmpQedMismatch => {
+            *s = "qed step does not match the statement of the theorem";
+        }
+        MmpStepMismatch(ref label) => {
+            *s = "Step and its hypotheses do not match the assertion {label}";
+            args.push(("label", t(label)));
+        }
+        MmpUnknownStep(ref step) => {
+            *s = "Hypothesis {step} does not refer to an earlier step";
+            args.push(("step", t(step)));
+        }
+        MmpUnparsable => {
+            *s = "Formula could not be parsed using the syntax axioms of the database";
+        }
+        MmpUnresolved => {
+            *s = "Work variables in this step could not be resolved";
+        }
+        _ => unreachable!("not a worksheet diagnostic"),
+    }
+}
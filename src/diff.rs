@@ -0,0 +1,418 @@
+//! Semantic comparison of two versions of a database.
+//!
+//! Rather than comparing text, `DatabaseDiff` compares the `$a` and `$p`
+//! statements of two databases by label, and reports:
+//!
+//! * assertions which were added or removed;
+//! * assertions which were renamed: a removed and an added assertion with
+//!   the same frame content;
+//! * assertions whose statement changed, including a change between `$a` and
+//!   `$p`;
+//! * assertions whose hypotheses changed: the math strings of their `$e`
+//!   hypotheses, in order, or their mandatory `$d` conditions; the `$f`
+//!   hypotheses follow from the statement;
+//! * theorems whose proof changed.
+//!
+//! The frame content of an assertion is compared as text, since the two
+//! databases have separate namesets; the labels of hypotheses do not matter.
+//! Proofs are compared as trees of labels, with the labels of the old
+//! database mapped through the renames found, so that neither a renamed
+//! reference nor a change between compressed and normal style counts as a
+//! change.  Proofs which cannot be read into a tree, such as incomplete ones,
+//! are compared as sequences of tokens instead.
+
+use crate::nameck::Nameset;
+use crate::parser::as_str;
+use crate::parser::StatementRef;
+use crate::parser::StatementType;
+use crate::proof::proof_text;
+use crate::proof::ProofTreeArray;
+use crate::scopeck::Hyp::Essential;
+use crate::scopeck::ScopeResult;
+use crate::segment_set::SegmentSet;
+use crate::util::HashMap;
+use crate::util::new_map;
+use crate::util::new_set;
+use std::fmt;
+use std::io;
+use std::io::Write;
+
+/// The categories of changes, in the order they are reported.
+#[derive(Copy,Clone,Debug,PartialEq,Eq)]
+pub enum ChangeKind {
+    /// An assertion which is only in the new database.
+    Added,
+    /// An assertion which is only in the old database.
+    Removed,
+    /// An assertion which has a new label but the same frame content.
+    Renamed,
+    /// An assertion whose math string or type changed.
+    StatementChanged,
+    /// An assertion whose `$e` hypotheses or mandatory `$d` conditions
+    /// changed.
+    HypothesesChanged,
+    /// A theorem whose proof changed.
+    ProofChanged,
+}
+
+impl ChangeKind {
+    const ALL: [ChangeKind; 6] = [ChangeKind::Added,
+                                  ChangeKind::Removed,
+                                  ChangeKind::Renamed,
+                                  ChangeKind::StatementChanged,
+                                  ChangeKind::HypothesesChanged,
+                                  ChangeKind::ProofChanged];
+
+    /// A heading for the category in a text report.
+    pub fn title(self) -> &'static str {
+        match self {
+            ChangeKind::Added => "Added",
+            ChangeKind::Removed => "Removed",
+            ChangeKind::Renamed => "Renamed",
+            ChangeKind::StatementChanged => "Statement changed",
+            ChangeKind::HypothesesChanged => "Hypotheses changed",
+            ChangeKind::ProofChanged => "Proof changed",
+        }
+    }
+
+    /// The key for the category in a JSON report.
+    pub fn key(self) -> &'static str {
+        match self {
+            ChangeKind::Added => "added",
+            ChangeKind::Removed => "removed",
+            ChangeKind::Renamed => "renamed",
+            ChangeKind::StatementChanged => "statement_changed",
+            ChangeKind::HypothesesChanged => "hypotheses_changed",
+            ChangeKind::ProofChanged => "proof_changed",
+        }
+    }
+}
+
+/// A change to one assertion.  An assertion whose statement and proof both
+/// changed has a change of each kind.
+#[derive(Clone,Debug,PartialEq,Eq)]
+pub struct Change {
+    /// The category of the change.
+    pub kind: ChangeKind,
+    /// The label in the new database, or in the old one if it was removed.
+    pub label: String,
+    /// The label in the old database, for a renamed assertion.
+    pub old_label: Option<String>,
+}
+
+/// The content of an assertion which does not depend on labels.
+#[derive(Clone,Debug,PartialEq,Eq,Hash)]
+struct FrameContent {
+    /// True for `$p`, false for `$a`
+    provable: bool,
+    hypotheses: Vec<String>,
+    dv: Vec<(String, String)>,
+    statement: String,
+}
+
+fn math_string(stmt: StatementRef) -> String {
+    stmt.math_iter().map(|tok| as_str(tok.slice)).collect::<Vec<_>>().join(" ")
+}
+
+fn frame_content(sset: &SegmentSet,
+                 nset: &Nameset,
+                 scope: &ScopeResult,
+                 stmt: StatementRef)
+                 -> FrameContent {
+    let mut content = FrameContent {
+        provable: stmt.statement_type() == StatementType::Provable,
+        hypotheses: Vec::new(),
+        dv: Vec::new(),
+        statement: math_string(stmt),
+    };
+    if let Some(frame) = scope.get(stmt.label()) {
+        for hyp in &*frame.hypotheses {
+            if let Essential(addr, _) = *hyp {
+                content.hypotheses.push(math_string(sset.statement(addr)));
+            }
+        }
+        for &(ix1, ix2) in &*frame.mandatory_dv {
+            let var1 = as_str(nset.atom_name(frame.var_list[ix1])).to_owned();
+            let var2 = as_str(nset.atom_name(frame.var_list[ix2])).to_owned();
+            content.dv.push(if var1 < var2 { (var1, var2) } else { (var2, var1) });
+        }
+        content.dv.sort();
+    }
+    content
+}
+
+// the assertions of a database with their frame content, in database order
+fn assertions<'a>(sset: &'a SegmentSet,
+                  nset: &Nameset,
+                  scope: &ScopeResult)
+                  -> Vec<(StatementRef<'a>, FrameContent)> {
+    let mut out = Vec::new();
+    for sref in sset.segments() {
+        for stmt in sref {
+            let stype = stmt.statement_type();
+            if stype == StatementType::Axiom || stype == StatementType::Provable {
+                out.push((stmt, frame_content(sset, nset, scope, stmt)));
+            }
+        }
+    }
+    out
+}
+
+// compares two proof trees step by step, mapping the labels of the old one
+// through `renames`; each pair of shared subtrees is compared once
+fn same_tree(old_sset: &SegmentSet,
+             old_arr: &ProofTreeArray,
+             sset: &SegmentSet,
+             arr: &ProofTreeArray,
+             renames: &HashMap<&[u8], &[u8]>)
+             -> bool {
+    let mut seen = new_set();
+    let mut stack = vec![(old_arr.qed, arr.qed)];
+    while let Some((old_ix, ix)) = stack.pop() {
+        if !seen.insert((old_ix, ix)) {
+            continue;
+        }
+        let (old_tree, tree) = (&old_arr.trees[old_ix], &arr.trees[ix]);
+        let old_label = old_sset.statement(old_tree.address).label();
+        let old_label = renames.get(old_label).map_or(old_label, |&new| new);
+        if old_label != sset.statement(tree.address).label() ||
+           old_tree.children.len() != tree.children.len() {
+            return false;
+        }
+        stack.extend(old_tree.children.iter().cloned().zip(tree.children.iter().cloned()));
+    }
+    true
+}
+
+/// The changes between two versions of a database.
+#[derive(Clone,Debug,Default)]
+pub struct DatabaseDiff {
+    changes: Vec<Change>,
+}
+
+impl DatabaseDiff {
+    /// Compares the assertions of an old and a new database.
+    pub fn new(old_sset: &SegmentSet,
+               old_nset: &Nameset,
+               old_scope: &ScopeResult,
+               sset: &SegmentSet,
+               nset: &Nameset,
+               scope: &ScopeResult)
+               -> DatabaseDiff {
+        let old = assertions(old_sset, old_nset, old_scope);
+        let new = assertions(sset, nset, scope);
+        let old_index: HashMap<&[u8], usize> =
+            old.iter().enumerate().map(|(ix, (stmt, _))| (stmt.label(), ix)).collect();
+        let new_index: HashMap<&[u8], usize> =
+            new.iter().enumerate().map(|(ix, (stmt, _))| (stmt.label(), ix)).collect();
+
+        // removed assertions by content, as candidates for renaming
+        let mut removed: HashMap<&FrameContent, Vec<usize>> = new_map();
+        for (ix, (stmt, content)) in old.iter().enumerate().rev() {
+            if !new_index.contains_key(stmt.label()) {
+                removed.entry(content).or_default().push(ix);
+            }
+        }
+        let mut renamed_from = vec![false; old.len()];
+
+        let mut renames: HashMap<&[u8], &[u8]> = new_map();
+        let mut theorems = Vec::new();
+        let mut changes = Vec::new();
+        let mut push = |kind, stmt: &StatementRef, old_label: Option<String>| {
+            changes.push(Change {
+                kind,
+                label: as_str(stmt.label()).to_owned(),
+                old_label,
+            })
+        };
+        for (stmt, content) in &new {
+            let (old_stmt, old_content) = match old_index.get(stmt.label()) {
+                Some(&ix) => (&old[ix].0, &old[ix].1),
+                None => {
+                    match removed.get_mut(content).and_then(|ixs| ixs.pop()) {
+                        Some(ix) => {
+                            renamed_from[ix] = true;
+                            renames.insert(old[ix].0.label(), stmt.label());
+                            let old_label = as_str(old[ix].0.label()).to_owned();
+                            push(ChangeKind::Renamed, stmt, Some(old_label));
+                            (&old[ix].0, &old[ix].1)
+                        }
+                        None => {
+                            push(ChangeKind::Added, stmt, None);
+                            continue;
+                        }
+                    }
+                }
+            };
+            if content.statement != old_content.statement || content.provable != old_content.provable {
+                push(ChangeKind::StatementChanged, stmt, None);
+            }
+            if content.hypotheses != old_content.hypotheses || content.dv != old_content.dv {
+                push(ChangeKind::HypothesesChanged, stmt, None);
+            }
+            if content.provable && old_content.provable {
+                theorems.push((*old_stmt, *stmt));
+            }
+        }
+        // after all renames are known
+        for (old_stmt, stmt) in theorems {
+            let same = match (ProofTreeArray::new(old_sset, old_nset, old_scope, old_stmt),
+                              ProofTreeArray::new(sset, nset, scope, stmt)) {
+                (Ok(old_arr), Ok(arr)) => {
+                    same_tree(old_sset, &old_arr, sset, &arr, &renames)
+                }
+                _ => {
+                    let tokens = |text: Vec<u8>| -> Vec<Vec<u8>> {
+                        text.split(|&c| c == b' ')
+                            .filter(|tok| !tok.is_empty())
+                            .map(|tok| renames.get(tok).map_or(tok, |&new| new).to_vec())
+                            .collect()
+                    };
+                    tokens(proof_text(old_stmt)) == tokens(proof_text(stmt))
+                }
+            };
+            if !same {
+                push(ChangeKind::ProofChanged, &stmt, None);
+            }
+        }
+        for (ix, (stmt, _)) in old.iter().enumerate() {
+            if !new_index.contains_key(stmt.label()) && !renamed_from[ix] {
+                push(ChangeKind::Removed, stmt, None);
+            }
+        }
+        // stable, so each category stays in database order
+        changes.sort_by_key(|change| ChangeKind::ALL.iter().position(|&k| k == change.kind));
+        DatabaseDiff { changes }
+    }
+
+    /// All changes, by category and then in database order.
+    pub fn changes(&self) -> &[Change] {
+        &self.changes
+    }
+
+    /// The changes of one category.
+    pub fn of_kind(&self, kind: ChangeKind) -> impl Iterator<Item = &Change> {
+        self.changes.iter().filter(move |change| change.kind == kind)
+    }
+
+    /// Writes the report as a JSON object with a list of labels for each
+    /// category; renamed assertions are objects with `old` and `new` labels.
+    pub fn write_json<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        writeln!(out, "{{")?;
+        for (index, &kind) in ChangeKind::ALL.iter().enumerate() {
+            let items: Vec<String> = self.of_kind(kind)
+                .map(|change| match change.old_label {
+                    Some(ref old) => format!("{{\"old\": \"{}\", \"new\": \"{}\"}}", old, change.label),
+                    None => format!("\"{}\"", change.label),
+                })
+                .collect();
+            writeln!(out,
+                     "  \"{}\": [{}]{}",
+                     kind.key(),
+                     items.join(", "),
+                     if index + 1 < ChangeKind::ALL.len() { "," } else { "" })?;
+        }
+        writeln!(out, "}}")
+    }
+}
+
+/// The text report: a heading for each category with changes, followed by
+/// one label per line.
+impl fmt::Display for DatabaseDiff {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.changes.is_empty() {
+            return writeln!(f, "No changes");
+        }
+        for &kind in &ChangeKind::ALL {
+            let changes: Vec<_> = self.of_kind(kind).collect();
+            if changes.is_empty() {
+                continue;
+            }
+            writeln!(f, "{} ({}):", kind.title(), changes.len())?;
+            for change in changes {
+                match change.old_label {
+                    Some(ref old) => writeln!(f, "  {} -> {}", old, change.label)?,
+                    None => writeln!(f, "  {}", change.label)?,
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::database::Database;
+    use crate::test_util::database;
+    use crate::test_util::A1I;
+    use crate::test_util::PROP;
+
+    fn old_db() -> Database {
+        database(&[PROP,
+                   A1I,
+                   "id $p |- ( ph -> ph ) $= ? $.
+                    part $p |- ( ph -> ( ps -> ph ) ) $= ? $.
+                    fmt $p |- ( ph -> ( ps -> ph ) ) $= wph wps ax-1 $.
+                    gone $a |- ( ph -> ps ) $."]
+            .concat())
+    }
+
+    // renames ax-mp, changes the hypothesis of a1i, completes the proof of
+    // part and compresses the proof of fmt
+    fn new_db() -> Database {
+        database(&[&PROP.replace("ax-mp", "ax-modus"),
+                   "${ a1i.1 $e |- ( ph -> ph ) $.
+                       a1i $p |- ( ps -> ph ) $=
+                         wph wps wph wi a1i.1 wph wps ax-1 ax-modus $. $}
+                    id $p |- ( ps -> ps ) $= ? $.
+                    part $p |- ( ph -> ( ps -> ph ) ) $= wph wps ax-1 $.
+                    fmt $p |- ( ph -> ( ps -> ph ) ) $= ( ax-1 ) ABC $.
+                    new $p |- ( ph -> ( ph -> ph ) ) $= wph wph ax-1 $."]
+            .concat())
+    }
+
+    #[test]
+    fn test_diff() {
+        let mut old = old_db();
+        let mut new = new_db();
+        let diff = new.diff(&mut old);
+        assert_eq!(diff.to_string(),
+                   "Added (1):
+  new
+Removed (1):
+  gone
+Renamed (1):
+  ax-mp -> ax-modus
+Statement changed (1):
+  id
+Hypotheses changed (1):
+  a1i
+Proof changed (1):
+  part
+");
+        let mut out = Vec::new();
+        diff.write_json(&mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(),
+                   "{
+  \"added\": [\"new\"],
+  \"removed\": [\"gone\"],
+  \"renamed\": [{\"old\": \"ax-mp\", \"new\": \"ax-modus\"}],
+  \"statement_changed\": [\"id\"],
+  \"hypotheses_changed\": [\"a1i\"],
+  \"proof_changed\": [\"part\"]
+}
+");
+        let mut same = old_db();
+        assert_eq!(same.diff(&mut old).to_string(), "No changes\n");
+    }
+
+    #[test]
+    fn test_diff_renamed_reference() {
+        let mut old = database(&[PROP, A1I].concat());
+        let mut new = database(&[PROP, A1I].concat().replace("ax-mp", "ax-modus"));
+        assert_eq!(new.diff(&mut old).to_string(),
+                   "Renamed (1):
+  ax-mp -> ax-modus
+");
+    }
+}
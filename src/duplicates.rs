@@ -0,0 +1,216 @@
+//! Detection of assertions which are the same up to renaming of variables.
+//!
+//! Each `$a` and `$p` frame is reduced to a canonical form: its `$e`
+//! hypotheses in order, its conclusion, and its mandatory `$d` pairs, with the
+//! variables numbered in order of first occurrence and kept with their
+//! typecodes.  Two assertions with the same canonical form are alpha-equivalent:
+//! one can be obtained from the other by renaming variables to variables of the
+//! same typecode.  The canonical forms are hashed to find the equivalence
+//! classes.
+//!
+//! The frames are built by the scope pass, so hypothesis labels and the
+//! distinction between `$a` and `$p` are ignored, as are `$d` conditions on
+//! variables which do not occur in the assertion.  Frames of each segment are
+//! canonicalized in parallel.
+
+use crate::diag::Diagnostic;
+use crate::nameck::Atom;
+use crate::parser::SegmentId;
+use crate::parser::StatementAddress;
+use crate::parser::StatementType;
+use crate::scopeck::Frame;
+use crate::scopeck::Hyp;
+use crate::scopeck::ScopeResult;
+use crate::scopeck::VarIndex;
+use crate::scopeck::VerifyExpr;
+use crate::segment_set::SegmentSet;
+use crate::util::HashMap;
+use crate::util::new_map;
+use std::sync::Arc;
+
+/// An expression with variables numbered in order of first occurrence in the
+/// frame.
+#[derive(PartialEq,Eq,Hash)]
+struct CanonicalExpr {
+    typecode: Atom,
+    tail: Vec<(Vec<u8>, VarIndex)>,
+    rump: Vec<u8>,
+}
+
+/// The logical content of a frame, independent of the names of its variables.
+#[derive(PartialEq,Eq,Hash)]
+struct CanonicalFrame {
+    hypotheses: Vec<CanonicalExpr>,
+    target: CanonicalExpr,
+    var_types: Vec<Atom>,
+    dv: Vec<(VarIndex, VarIndex)>,
+}
+
+// renumbers variables on first use, recording their typecodes
+fn canonical_expr(frame: &Frame,
+                  expr: &VerifyExpr,
+                  types: &[Atom],
+                  renumber: &mut [Option<VarIndex>],
+                  var_types: &mut Vec<Atom>)
+                  -> CanonicalExpr {
+    let tail = expr.tail
+        .iter()
+        .map(|part| {
+            let var = *renumber[part.var].get_or_insert_with(|| {
+                var_types.push(types[part.var]);
+                var_types.len() - 1
+            });
+            (frame.const_pool[part.prefix.clone()].to_vec(), var)
+        })
+        .collect();
+    CanonicalExpr {
+        typecode: expr.typecode,
+        tail,
+        rump: frame.const_pool[expr.rump.clone()].to_vec(),
+    }
+}
+
+fn canonical_frame(frame: &Frame) -> CanonicalFrame {
+    let mut types = vec![Atom::default(); frame.var_list.len()];
+    for hyp in &*frame.hypotheses {
+        if let Hyp::Floating(_, var, typecode) = *hyp {
+            types[var] = typecode;
+        }
+    }
+    let mut renumber = vec![None; frame.var_list.len()];
+    let mut var_types = Vec::new();
+    let hypotheses = frame.hypotheses
+        .iter()
+        .filter_map(|hyp| match *hyp {
+            Hyp::Essential(_, ref expr) => {
+                Some(canonical_expr(frame, expr, &types, &mut renumber, &mut var_types))
+            }
+            Hyp::Floating(..) => None,
+        })
+        .collect();
+    let target = canonical_expr(frame, &frame.target, &types, &mut renumber, &mut var_types);
+    let mut dv: Vec<_> = frame.mandatory_dv
+        .iter()
+        .filter_map(|&(v1, v2)| {
+            let (v1, v2) = (renumber[v1]?, renumber[v2]?);
+            Some((v1.min(v2), v1.max(v2)))
+        })
+        .collect();
+    dv.sort_unstable();
+    dv.dedup();
+    CanonicalFrame {
+        hypotheses,
+        target,
+        var_types,
+        dv,
+    }
+}
+
+fn canonical_segment(sset: &SegmentSet,
+                     scope: &ScopeResult,
+                     id: SegmentId)
+                     -> Vec<(StatementAddress, CanonicalFrame)> {
+    let mut out = Vec::new();
+    for stmt in sset.segment(id) {
+        if stmt.statement_type() != StatementType::Axiom &&
+           stmt.statement_type() != StatementType::Provable {
+            continue;
+        }
+        if let Some(frame) = scope.get(stmt.label()) {
+            out.push((stmt.address(), canonical_frame(frame)));
+        }
+    }
+    out
+}
+
+/// Finds the classes of two or more alpha-equivalent assertions.  Each class
+/// is in database order, and the classes are ordered by their first member.
+pub fn find_duplicates(segments: &Arc<SegmentSet>,
+                       scope: &Arc<ScopeResult>)
+                       -> Vec<Vec<StatementAddress>> {
+    let mut promises = Vec::new();
+    for sref in segments.segments() {
+        let segments2 = segments.clone();
+        let scope = scope.clone();
+        let id = sref.id;
+        promises.push(segments.exec.exec(sref.bytes(),
+                                         move || canonical_segment(&segments2, &scope, id)));
+    }
+
+    let mut index: HashMap<CanonicalFrame, usize> = new_map();
+    let mut classes: Vec<Vec<StatementAddress>> = Vec::new();
+    for promise in promises {
+        for (addr, canon) in promise.wait() {
+            let next = classes.len();
+            let class = *index.entry(canon).or_insert(next);
+            if class == next {
+                classes.push(Vec::new());
+            }
+            classes[class].push(addr);
+        }
+    }
+    classes.retain(|class| class.len() > 1);
+    classes
+}
+
+/// Reports each assertion of a class after the first as a duplicate of the
+/// first.
+pub fn duplicate_diagnostics(classes: &[Vec<StatementAddress>])
+                             -> Vec<(StatementAddress, Diagnostic)> {
+    let mut out = Vec::new();
+    for class in classes {
+        for &addr in &class[1..] {
+            out.push((addr, Diagnostic::DuplicateAssertion(class[0])));
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_util::database;
+    use crate::test_util::PROP;
+
+    #[test]
+    fn test_duplicates() {
+        let text = "$c set = $.
+            $v x y $.
+            vx $f set x $.
+            vy $f set y $.
+            weq $a wff x = y $.
+            ax-1b $a |- ( ch -> ( ph -> ch ) ) $.
+            ax-1c $a |- ( ph -> ( ph -> ph ) ) $.
+            ${
+                mp.1 $e |- ( ps -> ch ) $.
+                mp.2 $e |- ps $.
+                mp $a |- ch $.
+            $}
+            ${
+                mp2.1 $e |- ps $.
+                mp2.2 $e |- ( ps -> ch ) $.
+                mp2 $a |- ch $.
+            $}
+            ${
+                $d x y $.
+                ax-eq $a |- x = y $.
+            $}
+            ${
+                $d x y $.
+                ax-eq2 $a |- y = x $.
+            $}
+            ax-eq3 $a |- y = x $.";
+        let mut db = database(&[PROP, text].concat());
+        let sset = db.parse_result().clone();
+        let classes: Vec<Vec<&[u8]>> = db.duplicates()
+            .into_iter()
+            .map(|class| class.into_iter().map(|addr| sset.statement(addr).label()).collect())
+            .collect();
+        assert_eq!(classes,
+                   vec![vec![&b"ax-1"[..], b"ax-1b"],
+                        vec![&b"ax-mp"[..], b"mp2"],
+                        vec![&b"ax-eq"[..], b"ax-eq2"]]);
+        let notations = db.duplicate_notations();
+        assert_eq!(notations.len(), 6);
+        assert_eq!(notations[0].args, vec![("first", "ax-1".to_owned())]);
+    }
+}
@@ -1,18 +1,29 @@
 //! Export support for mmj2 proof files.
 
 use crate::diag::Diagnostic;
+use crate::grammar::SymbolScope;
 use crate::nameck::Nameset;
 use crate::parser::as_str;
+use crate::parser::is_mm_space;
+use crate::parser::SegmentId;
+use crate::parser::Span;
 use crate::parser::StatementRef;
 use crate::parser::StatementType;
 use crate::parser::TokenRef;
+use crate::pretty::PrettyPrinter;
+use crate::proof::ListingStyle;
+use crate::proof::ProofListing;
 use crate::proof::ProofStyle;
 use crate::proof::ProofTreeArray;
 use crate::proof::ProofTreePrinter;
 use regex::Regex;
+use crate::scopeck::Hyp::Essential;
 use crate::scopeck::ScopeResult;
 use crate::segment_set::SegmentSet;
+use crate::typesetting::Typesetting;
+use crate::verify::DvReport;
 use std::error;
+use std::cmp::max;
 use std::fmt;
 use std::io;
 use std::io::Write;
@@ -25,6 +36,8 @@ pub enum ExportError {
     Io(io::Error),
     /// Proof verification error
     Verify(Diagnostic),
+    /// Math symbols which have no `latexdef` typesetting definition
+    MissingLatexDef(Vec<String>),
 }
 
 impl From<io::Error> for ExportError {
@@ -43,6 +56,9 @@ impl fmt::Display for ExportError {
         match *self {
             ExportError::Io(ref err) => write!(f, "IO error: {}", err),
             ExportError::Verify(ref err) => write!(f, "{:?}", err),
+            ExportError::MissingLatexDef(ref symbols) => {
+                write!(f, "no latexdef for symbols: {}", symbols.join(" "))
+            }
         }
     }
 }
@@ -58,15 +74,20 @@ impl error::Error for ExportError {
     fn cause(&self) -> Option<&dyn error::Error> {
         match *self {
             ExportError::Io(ref err) => Some(err),
-            ExportError::Verify(_) => None,
+            ExportError::Verify(_) | ExportError::MissingLatexDef(_) => None,
         }
     }
 }
 
 /// Export an mmp file for a given statement.
+///
+/// Step formulas are written with `pretty`, which should use plain ASCII
+/// symbols for the worksheet to remain importable; long formulas are
+/// continued on indented lines.
 pub fn export_mmp<W: Write>(sset: &SegmentSet,
                             nset: &Nameset,
                             scope: &ScopeResult,
+                            pretty: &PrettyPrinter,
                             stmt: StatementRef,
                             out: &mut W)
                             -> Result<(), ExportError> {
@@ -156,13 +177,15 @@ pub fn export_mmp<W: Write>(sset: &SegmentSet,
         .map(|&(cur, _, ref line)| line.len() as i16 - indent[cur] as i16)
         .max()
         .unwrap() as u16;
+    let mut symbols = SymbolScope::new(sset, nset, stmt);
     for &mut (cur, tc, ref mut line) in &mut lines {
         for _ in 0..(spaces + indent[cur] - line.len() as u16) {
             line.push(' ')
         }
-        line.push_str(str::from_utf8(&tc).unwrap());
-        line.push_str(&String::from_utf8_lossy(&arr.exprs[cur]));
-        writeln!(out, "{}", line)?;
+        let mut tokens = vec![&*tc];
+        tokens.extend(arr.exprs[cur].split(|&c| c == b' ').filter(|tok| !tok.is_empty()));
+        let formula = pretty.format(&mut symbols, &tokens, line.len());
+        writeln!(out, "{}{}", line, formula)?;
     }
     writeln!(out,
              "\n$={}",
@@ -181,3 +204,257 @@ pub fn export_mmp<W: Write>(sset: &SegmentSet,
     writeln!(out, "\n$)")?;
     Ok(())
 }
+
+/// Options for `export_latex`.
+#[derive(Copy,Clone,Debug,Default)]
+pub struct LatexOptions {
+    /// Wrap the statements in a complete LaTeX document, instead of writing a
+    /// fragment to be included in one
+    pub standalone: bool,
+    /// Add a table of the essential steps of each proof
+    pub proof: bool,
+}
+
+fn statement_math<'a>(stmt: &StatementRef<'a>) -> Vec<&'a [u8]> {
+    (0..stmt.math_len()).map(|index| stmt.math_at(index).slice).collect()
+}
+
+// escape a label for LaTeX text mode
+fn latex_label(label: &[u8]) -> String {
+    as_str(label).replace('_', "\\_")
+}
+
+/// Write statements and optionally their proofs as LaTeX, typeset with the
+/// `latexdef` definitions of the database.
+///
+/// Each statement is written with its `$e` hypotheses and its assertion; the
+/// proof table lists the essential steps, like the HTML pages.  If any symbol
+/// to be written has no `latexdef`, nothing is written and all such symbols
+/// are reported.
+pub fn export_latex<W: Write>(sset: &SegmentSet,
+                              nset: &Nameset,
+                              scope: &ScopeResult,
+                              typesetting: &Typesetting,
+                              stmts: &[StatementRef],
+                              options: LatexOptions,
+                              out: &mut W)
+                              -> Result<(), ExportError> {
+    // each statement as a list of (label, math) lines for the hypotheses and
+    // assertion, and the steps of its proof as (hyps, label, math)
+    let mut missing: Vec<String> = Vec::new();
+    let mut math = |symbols: Vec<&[u8]>| {
+        let mut defs = Vec::new();
+        for symbol in symbols {
+            match typesetting.latex_def(symbol) {
+                Some(def) => defs.push(def.to_owned()),
+                None => {
+                    let symbol = as_str(symbol).to_owned();
+                    if !missing.contains(&symbol) {
+                        missing.push(symbol);
+                    }
+                }
+            }
+        }
+        defs.join(" ")
+    };
+
+    let mut rendered = Vec::new();
+    for &stmt in stmts {
+        let mut lines = Vec::new();
+        if let Some(frame) = scope.get(stmt.label()) {
+            for hyp in &*frame.hypotheses {
+                if let Essential(addr, _) = *hyp {
+                    let hyp = sset.statement(addr);
+                    lines.push((latex_label(hyp.label()), math(statement_math(&hyp))));
+                }
+            }
+        }
+        lines.push((latex_label(stmt.label()), math(statement_math(&stmt))));
+
+        let mut steps = Vec::new();
+        if options.proof && stmt.statement_type() == StatementType::Provable {
+            let arr = ProofTreeArray::new(sset, nset, scope, stmt)?;
+            let listing = ProofListing {
+                sset,
+                scope,
+                arr: &arr,
+                style: ListingStyle::Tree,
+                essential: true,
+            };
+            for (ix, hyps) in listing.steps() {
+                let refs: Vec<String> = hyps.iter().map(|hyp| hyp.to_string()).collect();
+                let used = sset.statement(arr.trees[ix].address);
+                let mut symbols = vec![used.math_at(0).slice];
+                symbols.extend(arr.exprs[ix].split(|&c| c == b' ').filter(|s| !s.is_empty()));
+                steps.push((refs.join(", "), latex_label(used.label()), math(symbols)));
+            }
+        }
+        let kind = if stmt.statement_type() == StatementType::Provable {
+            "Theorem"
+        } else {
+            "Axiom"
+        };
+        rendered.push((kind, lines, steps));
+    }
+    if !missing.is_empty() {
+        return Err(ExportError::MissingLatexDef(missing));
+    }
+
+    if options.standalone {
+        writeln!(out, "\\documentclass{{article}}")?;
+        writeln!(out, "\\usepackage{{amssymb}}")?;
+        writeln!(out, "\\begin{{document}}")?;
+    }
+    for (kind, lines, steps) in rendered {
+        let (assertion, hyps) = lines.split_last().unwrap();
+        writeln!(out, "\n\\medskip\\noindent\\textbf{{{}}} \\texttt{{{}}}\\par",
+                 kind, assertion.0)?;
+        for (label, math) in hyps {
+            writeln!(out, "\\noindent Hypothesis \\texttt{{{}}}: ${}$\\par", label, math)?;
+        }
+        writeln!(out, "\\noindent Assertion: ${}$\\par", assertion.1)?;
+        if !steps.is_empty() {
+            writeln!(out, "\\begin{{tabular}}{{rlll}}")?;
+            writeln!(out, "Step & Hyp & Ref & Expression \\\\ \\hline")?;
+            for (n, (refs, label, math)) in steps.into_iter().enumerate() {
+                writeln!(out, "{} & {} & \\texttt{{{}}} & ${}$ \\\\", n + 1, refs, label, math)?;
+            }
+            writeln!(out, "\\end{{tabular}}")?;
+        }
+    }
+    if options.standalone {
+        writeln!(out, "\\end{{document}}")?;
+    }
+    Ok(())
+}
+
+/// Renders a proof to replace the proof section of a `$p` statement, keeping
+/// the layout of the existing proof.
+///
+/// Returns the span of the existing proof section, from `$=` to `$.`, and its
+/// replacement, or `None` if the statement has no proof section.  The new
+/// proof continues on the line of the `$=` unless the old proof did not, and
+/// continuation lines are indented like those of the old proof; a proof which
+/// fit on one line is indented four columns past the statement label.
+pub fn proof_section_text(sset: &SegmentSet,
+                          nset: &Nameset,
+                          scope: &ScopeResult,
+                          stmt: StatementRef,
+                          arr: &ProofTreeArray,
+                          style: ProofStyle)
+                          -> Option<(Span, String)> {
+    let region = stmt.proof_section()?;
+    let buf = &stmt.segment().segment.buffer;
+    let column = |pos: usize| {
+        pos - buf[..pos].iter().rposition(|&c| c == b'\n').map_or(0, |nl| nl + 1)
+    };
+    let eq_col = column(region.start as usize);
+    let proof = region.as_ref(buf);
+    let new_line = proof[2..]
+        .iter()
+        .take_while(|&&c| is_mm_space(c))
+        .any(|&c| c == b'\n');
+    let indent = proof.iter()
+        .enumerate()
+        .filter(|&(_, &c)| c == b'\n')
+        .map(|(nl, _)| nl + 1 + proof[nl + 1..].iter().take_while(|&&c| c == b' ').count())
+        .find(|&start| start < proof.len() && !is_mm_space(proof[start]))
+        .map_or_else(|| column(stmt.span().start as usize) + 4,
+                     |start| column(region.start as usize + start));
+
+    let printer = ProofTreePrinter {
+        sset,
+        nset,
+        scope,
+        thm_label: stmt.label(),
+        style,
+        arr,
+        initial_chr: if new_line { 0 } else { eq_col as u16 + 2 },
+        indent: max(indent, 1) as u16,
+        line_width: 79,
+    };
+    Some((region, format!("$={}{}", if new_line { "\n" } else { "" }, printer)))
+}
+
+/// Edits which add the `missing` pairs of a `DvReport` as `$d` statements
+/// for a `$p` statement.
+///
+/// Each pair gets its own `$d` statement, inserted before the comment of the
+/// `$p` so that the comment stays attached.  The `$p` and its comment are
+/// wrapped in a new `${ $}` group, even if they are already in one, so that
+/// the new conditions do not apply to later statements; the wrapped lines are
+/// indented by two more spaces.  The `$e` hypotheses stay where they are,
+/// since later statements may use them too.  The edits are returned as spans
+/// to replace, in order.
+pub fn dv_insertion_edits(stmt: StatementRef, report: &DvReport) -> Vec<(Span, String)> {
+    if report.missing.is_empty() {
+        return Vec::new();
+    }
+    let buf = &stmt.segment().segment.buffer;
+    let at = stmt.associated_comment().unwrap_or(stmt).span_full().start as usize;
+    let end = stmt.span().end as usize;
+    let start = at + buf[at..end].iter().take_while(|&&c| is_mm_space(c)).count();
+    let column = start - buf[..start].iter().rposition(|&c| c == b'\n').map_or(0, |nl| nl + 1);
+    let indent = " ".repeat(column);
+    let mut text = String::from_utf8_lossy(&buf[at..start]).into_owned();
+    text.push_str("${");
+    for (var1, var2) in &report.missing {
+        text.push_str(&format!("\n{}  $d {} {} $.", indent, as_str(var1), as_str(var2)));
+    }
+    for (ix, line) in String::from_utf8_lossy(&buf[start..end]).split('\n').enumerate() {
+        text.push('\n');
+        if ix == 0 {
+            text.push_str(&indent);
+        }
+        if !line.trim().is_empty() {
+            text.push_str("  ");
+        }
+        text.push_str(line);
+    }
+    text.push_str(&format!("\n{}$}}", indent));
+    vec![(Span::new(at, end), text)]
+}
+
+/// Rewrites the text of a segment with every proof re-rendered in `style`.
+///
+/// Proofs which cannot be parsed into a `ProofTreeArray`, including incomplete
+/// proofs, are copied unchanged.  A file inclusion which ends the segment is
+/// left out, so concatenating the output for all segments in order gives a
+/// single self-contained database.
+pub fn restyle_segment(sset: &SegmentSet,
+                       nset: &Nameset,
+                       scope: &ScopeResult,
+                       seg_id: SegmentId,
+                       style: ProofStyle)
+                       -> Vec<u8> {
+    let sref = sset.segment(seg_id);
+    let buf = &sref.segment.buffer;
+    let mut out = Vec::with_capacity(buf.len());
+    let mut pos = sref.segment.span().start as usize;
+    for stmt in sref {
+        match stmt.statement_type() {
+            StatementType::Provable => {
+                let rendered = ProofTreeArray::new(sset, nset, scope, stmt)
+                    .ok()
+                    .and_then(|arr| proof_section_text(sset, nset, scope, stmt, &arr, style));
+                if let Some((region, text)) = rendered {
+                    out.extend_from_slice(&buf[pos..region.start as usize]);
+                    out.extend_from_slice(text.as_bytes());
+                    pos = region.end as usize;
+                }
+            }
+            StatementType::FileInclude => {
+                let span = stmt.span();
+                let open = buf[..span.start as usize]
+                    .windows(2)
+                    .rposition(|w| w == b"$[")
+                    .unwrap_or(span.start as usize);
+                out.extend_from_slice(&buf[pos..open]);
+                pos = span.end as usize;
+            }
+            _ => {}
+        }
+    }
+    out.extend_from_slice(&buf[pos..sref.segment.span().end as usize]);
+    out
+}
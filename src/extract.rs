@@ -0,0 +1,213 @@
+//! Extraction of a self-contained database for some theorems.
+//!
+//! Like `write source ... /extract` in metamath-exe, this writes a new
+//! database containing only what is needed to state and prove a set of
+//! assertions: the assertions their proofs use, transitively; the `$e` and
+//! `$f` hypotheses of all of those and the hypotheses their proofs refer to;
+//! the `$c` and `$v` declarations of the symbols which appear in the kept
+//! statements; and the `$d` statements which constrain those variables in the
+//! scope of a kept assertion.
+//!
+//! Statements are written in database order with their original text, and
+//! `${ $}` groups are kept if they contain a kept assertion.  `$c`, `$v` and
+//! `$d` statements are rewritten to list only the symbols which are needed.
+//! The comment before each kept assertion is kept as well; other comments,
+//! including `$t` comments, are dropped.  All included files are written into
+//! the one output file.
+
+use crate::nameck::Nameset;
+use crate::parser::StatementAddress;
+use crate::parser::StatementRef;
+use crate::parser::StatementType;
+use crate::proof::proof_labels;
+use crate::scopeck::ScopeResult;
+use crate::segment_set::SegmentSet;
+use crate::util::HashSet;
+use crate::util::new_set;
+use std::io;
+use std::io::Write;
+
+/// The output of a `${ $}` group, which is discarded if it turns out to
+/// contain no kept assertion.
+#[derive(Default)]
+struct Group {
+    text: Vec<u8>,
+    needed: bool,
+}
+
+// the source text of a statement, without leading white space
+fn source<'a>(stmt: StatementRef<'a>) -> &'a [u8] {
+    let text = stmt.span_full().as_ref(&stmt.segment().segment.buffer);
+    let start = text.iter().position(|chr| !chr.is_ascii_whitespace()).unwrap_or(text.len());
+    &text[start..]
+}
+
+/// Finds the statements needed to state and prove the given assertions: the
+/// assertions, everything their proofs refer to, and the mandatory
+/// hypotheses of every assertion found, transitively.
+pub fn needed_statements(sset: &SegmentSet,
+                         nset: &Nameset,
+                         scope: &ScopeResult,
+                         roots: &[StatementAddress])
+                         -> HashSet<StatementAddress> {
+    let mut keep = new_set();
+    let mut stack = roots.to_vec();
+    while let Some(addr) = stack.pop() {
+        if !keep.insert(addr) {
+            continue;
+        }
+        let stmt = sset.statement(addr);
+        match stmt.statement_type() {
+            StatementType::Axiom | StatementType::Provable => {
+                if let Some(frame) = scope.get(stmt.label()) {
+                    stack.extend(frame.hypotheses.iter().map(|hyp| hyp.address()));
+                }
+                if stmt.statement_type() == StatementType::Provable {
+                    stack.extend(proof_labels(nset, stmt));
+                }
+            }
+            _ => {}
+        }
+    }
+    keep
+}
+
+/// Writes a database with the given assertions and everything needed to
+/// state and prove them.  Returns the number of assertions written.
+pub fn extract<W: Write>(sset: &SegmentSet,
+                         nset: &Nameset,
+                         scope: &ScopeResult,
+                         roots: &[StatementAddress],
+                         out: &mut W)
+                         -> io::Result<usize> {
+    let keep = needed_statements(sset, nset, scope, roots);
+    let mut symbols: HashSet<&[u8]> = new_set();
+    for &addr in &keep {
+        let stmt = sset.statement(addr);
+        symbols.extend(stmt.math_iter().map(|tok| tok.slice));
+    }
+
+    let mut groups = vec![Group::default()];
+    let mut count = 0;
+    for sref in sset.segments() {
+        for stmt in sref {
+            let depth = groups.len() - 1;
+            let group = groups.last_mut().unwrap();
+            let indent = |text: &mut Vec<u8>| text.extend((0..depth * 2).map(|_| b' '));
+            match stmt.statement_type() {
+                StatementType::OpenGroup => groups.push(Group::default()),
+                StatementType::CloseGroup if depth > 0 => {
+                    let inner = groups.pop().unwrap();
+                    if inner.needed {
+                        let outer = groups.last_mut().unwrap();
+                        let outer_indent = vec![b' '; (depth - 1) * 2];
+                        outer.text.extend_from_slice(&outer_indent);
+                        outer.text.extend_from_slice(b"${\n");
+                        outer.text.extend(inner.text);
+                        outer.text.extend_from_slice(&outer_indent);
+                        outer.text.extend_from_slice(b"$}\n");
+                        outer.needed = true;
+                    }
+                }
+                stype @ StatementType::Constant |
+                stype @ StatementType::Variable |
+                stype @ StatementType::Disjoint => {
+                    let used: Vec<&[u8]> = stmt.math_iter()
+                        .map(|tok| tok.slice)
+                        .filter(|tok| symbols.contains(tok))
+                        .collect();
+                    let (keyword, min) = match stype {
+                        StatementType::Constant => (&b"$c"[..], 1),
+                        StatementType::Variable => (&b"$v"[..], 1),
+                        _ => (&b"$d"[..], 2),
+                    };
+                    if used.len() >= min {
+                        indent(&mut group.text);
+                        group.text.extend_from_slice(keyword);
+                        for tok in used {
+                            group.text.push(b' ');
+                            group.text.extend_from_slice(tok);
+                        }
+                        group.text.extend_from_slice(b" $.\n");
+                    }
+                }
+                stype @ StatementType::Axiom |
+                stype @ StatementType::Provable |
+                stype @ StatementType::Essential |
+                stype @ StatementType::Floating if keep.contains(&stmt.address()) => {
+                    if stype == StatementType::Axiom || stype == StatementType::Provable {
+                        if let Some(comment) = stmt.associated_comment() {
+                            indent(&mut group.text);
+                            group.text.extend_from_slice(source(comment));
+                            group.text.push(b'\n');
+                        }
+                        group.needed = true;
+                        count += 1;
+                    }
+                    indent(&mut group.text);
+                    group.text.extend_from_slice(source(stmt));
+                    group.text.push(b'\n');
+                }
+                _ => {}
+            }
+        }
+    }
+    out.write_all(&groups.swap_remove(0).text)?;
+    Ok(count)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::diag::DiagnosticClass;
+    use crate::test_util::database;
+    use crate::test_util::PROP;
+
+    #[test]
+    fn test_extract() {
+        let text = "$c -. $.
+            wn $a wff -. ph $.
+            ax-3 $a |- ( ( -. ph -> -. ps ) -> ( ps -> ph ) ) $.
+            ${
+                $d ph ps ch $.
+                a1i.1 $e |- ph $.
+                $( Inference introducing an antecedent. $)
+                a1i $p |- ( ps -> ph ) $=
+                    ( wi ax-1 ax-mp ) ABADCABEF $.
+            $}
+            ${
+                unused.1 $e |- -. ph $.
+                unused $p |- ( ps -> -. ph ) $= wn wps wph wn unused.1 a1i $.
+            $}";
+        let mut db = database(&[PROP, text].concat());
+        let a1i = db.statement("a1i").unwrap().address();
+        let mut out = Vec::new();
+        assert_eq!(db.extract(&[a1i], &mut out).unwrap(), 4);
+        let extracted = String::from_utf8(out).unwrap();
+        assert_eq!(extracted,
+                   "$c ( ) -> wff |- $.
+$v ph ps $.
+wph $f wff ph $.
+wps $f wff ps $.
+wi $a wff ( ph -> ps ) $.
+ax-1 $a |- ( ph -> ( ps -> ph ) ) $.
+${
+  min $e |- ph $.
+  maj $e |- ( ph -> ps ) $.
+  ax-mp $a |- ps $.
+$}
+${
+  $d ph ps $.
+  a1i.1 $e |- ph $.
+  $( Inference introducing an antecedent. $)
+  a1i $p |- ( ps -> ph ) $=
+                    ( wi ax-1 ax-mp ) ABADCABEF $.
+$}
+");
+
+        let mut check = database(&extracted);
+        let diags = check.diag_notations(vec![DiagnosticClass::Parse,
+                                              DiagnosticClass::Scope,
+                                              DiagnosticClass::Verify]);
+        assert!(diags.is_empty());
+    }
+}
@@ -0,0 +1,646 @@
+//! Parsing of math strings, driven by the syntax axioms of a database.
+//!
+//! The Metamath spec does not require math strings to have any structure, and
+//! the verifier never needs to parse them.  Databases in the style of set.mm do
+//! however define a context-free grammar: every `$a` statement whose typecode
+//! is not the provable typecode, such as `|-`, is a syntax axiom, for instance
+//! `wi $a wff ( ph -> ps ) $.`, and every `$f` statement gives a variable its
+//! syntactic type.  This module collects those into a `Grammar`, which can parse math
+//! strings into `unify::Term`s, render terms back into math strings, and build
+//! the syntax derivations which make up the non-logical steps of a proof.
+//!
+//! The parser is a memoized backtracking recognizer over spans of the input,
+//! with rules indexed by their first constant.  No attempt is made to detect
+//! ambiguity; the first derivation found wins, which is adequate for the
+//! unambiguous grammars used in practice.
+//!
+//! Statements with the provable typecode are parsed as if their typecode were
+//! the logic type, `wff` in set.mm; this mirrors the `syntax '|-' as 'wff'`
+//! declaration of set.mm, which we do not currently read.  Instead both
+//! typecodes are found from the axioms: the provable typecode is the first
+//! typecode of an `$a` statement which is not the type of any variable, and
+//! the logic type is the syntax type as which the first parsable logical axiom
+//! parses.
+
+use crate::nameck::Atom;
+use crate::nameck::NameReader;
+use crate::nameck::Nameset;
+use crate::parser::Comparer;
+use crate::parser::copy_token;
+use crate::parser::StatementAddress;
+use crate::parser::StatementRef;
+use crate::parser::StatementType;
+use crate::parser::Token;
+use crate::parser::TokenPtr;
+use crate::parser::NO_STATEMENT;
+use crate::proof::ProofTreeArray;
+use crate::scopeck::Frame;
+use crate::scopeck::Hyp;
+use crate::scopeck::ScopeResult;
+use crate::scopeck::VerifyExpr;
+use crate::segment_set::SegmentSet;
+use crate::unify::MetaVar;
+use crate::unify::Subst;
+use crate::unify::Term;
+use crate::util::HashMap;
+use crate::util::HashSet;
+use crate::util::new_map;
+use crate::util::new_set;
+use crate::verify::ProofBuilder;
+use std::cmp::Ordering;
+
+/// An element of the math string of a syntax axiom.
+#[derive(Copy,Clone,Debug,PartialEq,Eq)]
+pub enum RuleSym {
+    /// A constant which must appear literally.
+    Const(Atom),
+    /// A variable, given as the index of its hypothesis and its typecode.
+    Var(usize, Atom),
+}
+
+/// A syntax axiom, with its math string broken into constants and variables.
+#[derive(Debug)]
+struct SyntaxRule {
+    address: StatementAddress,
+    typecode: Atom,
+    pattern: Box<[RuleSym]>,
+}
+
+/// A math symbol which has been looked up in some scope, the input of the
+/// parser.
+#[derive(Copy,Clone,Debug,PartialEq,Eq,Hash)]
+pub enum Sym {
+    /// A constant.
+    Const(Atom),
+    /// A variable of the database, with its active `$f` statement and typecode.
+    Var(StatementAddress, Atom),
+    /// A metavariable with a given typecode, which is parsed as `Term::Meta`.
+    Meta(MetaVar, Atom),
+}
+
+/// A parsed math string: a typecode and a syntax tree for the remainder.
+#[derive(Clone,Debug,PartialEq,Eq,Hash)]
+pub struct Formula {
+    /// The first symbol of the math string.
+    pub typecode: Atom,
+    /// The syntax tree of the rest of the math string.
+    pub term: Term,
+}
+
+/// The parsed hypotheses and conclusion of an assertion.
+#[derive(Clone,Debug)]
+pub struct FramePattern {
+    /// Typecodes of the mandatory variables, by frame variable index.
+    pub var_types: Vec<Atom>,
+    /// One entry for each hypothesis of the frame, in order; `None` for `$f`
+    /// hypotheses.
+    pub hyps: Vec<Option<Formula>>,
+    /// The assertion itself.
+    pub target: Formula,
+}
+
+impl FramePattern {
+    /// Iterates over the `$e` hypotheses, with their positions in the frame's
+    /// hypothesis list.
+    pub fn essentials(&self) -> impl Iterator<Item = (usize, &Formula)> {
+        self.hyps.iter().enumerate().filter_map(|(ix, h)| h.as_ref().map(|f| (ix, f)))
+    }
+}
+
+/// The syntax axioms and variable types of a database.
+#[derive(Debug,Default)]
+pub struct Grammar {
+    provable: Atom,
+    logic: Atom,
+    rules: Vec<SyntaxRule>,
+    rule_index: HashMap<StatementAddress, usize>,
+    /// Rules by typecode and first symbol, for rules which start with a
+    /// constant.
+    by_head: HashMap<(Atom, Atom), Vec<usize>>,
+    /// Rules by typecode, for rules which start with a variable.
+    by_type: HashMap<Atom, Vec<usize>>,
+    /// Variable and typecode for each `$f` statement.
+    floats: HashMap<StatementAddress, (Atom, Atom)>,
+    types: HashSet<Atom>,
+}
+
+// split a compressed constant string into atoms
+fn push_consts(nset: &Nameset, pool: &[u8], out: &mut Vec<Atom>) -> Option<()> {
+    let mut start = 0;
+    for (ix, &chr) in pool.iter().enumerate() {
+        if chr & 0x80 != 0 {
+            let mut tok = pool[start..=ix].to_owned();
+            *tok.last_mut().unwrap() &= 0x7F;
+            out.push(nset.lookup_symbol(&tok)?.atom);
+            start = ix + 1;
+        }
+    }
+    Some(())
+}
+
+// expand a frame expression into symbols, given a mapping for the variables
+fn expr_syms<F>(nset: &Nameset, frame: &Frame, expr: &VerifyExpr, var: F) -> Option<Vec<Sym>>
+    where F: Fn(usize) -> Sym
+{
+    let mut consts = Vec::new();
+    let mut out = Vec::new();
+    for part in &*expr.tail {
+        consts.clear();
+        push_consts(nset, &frame.const_pool[part.prefix.clone()], &mut consts)?;
+        out.extend(consts.iter().map(|&c| Sym::Const(c)));
+        out.push(var(part.var));
+    }
+    consts.clear();
+    push_consts(nset, &frame.const_pool[expr.rump.clone()], &mut consts)?;
+    out.extend(consts.iter().map(|&c| Sym::Const(c)));
+    Some(out)
+}
+
+impl Grammar {
+    /// Collects the syntax axioms and `$f` statements of a database.
+    pub fn new(sset: &SegmentSet, nset: &Nameset, scope: &ScopeResult) -> Grammar {
+        let mut grammar = Grammar {
+            provable: Atom::default(),
+            logic: Atom::default(),
+            rules: Vec::new(),
+            rule_index: new_map(),
+            by_head: new_map(),
+            by_type: new_map(),
+            floats: new_map(),
+            types: new_set(),
+        };
+
+        let mut axioms = Vec::new();
+        for sref in sset.segments() {
+            for stmt in sref {
+                let frame = match stmt.statement_type() {
+                    StatementType::Axiom | StatementType::Floating => {
+                        match scope.get(stmt.label()) {
+                            Some(frame) if frame.valid.start == stmt.address() => frame,
+                            _ => continue,
+                        }
+                    }
+                    _ => continue,
+                };
+                if stmt.statement_type() == StatementType::Floating {
+                    grammar.floats.insert(stmt.address(), (frame.var_list[0], frame.target.typecode));
+                    grammar.types.insert(frame.target.typecode);
+                } else {
+                    axioms.push(frame);
+                }
+            }
+        }
+
+        grammar.provable = axioms.iter()
+            .map(|frame| frame.target.typecode)
+            .find(|typecode| !grammar.types.contains(typecode))
+            .unwrap_or_default();
+        for &frame in &axioms {
+            if frame.target.typecode != grammar.provable {
+                grammar.add_rule(nset, frame);
+            }
+        }
+        grammar.logic = axioms.iter()
+            .filter(|frame| frame.target.typecode == grammar.provable)
+            .find_map(|frame| grammar.assertion_type(nset, frame))
+            .unwrap_or_default();
+        grammar
+    }
+
+    // the syntax type as which the math string of a logical axiom parses
+    fn assertion_type(&self, nset: &Nameset, frame: &Frame) -> Option<Atom> {
+        let mut var_types = vec![Atom::default(); frame.mandatory_count];
+        for hyp in &*frame.hypotheses {
+            if let Hyp::Floating(_, index, typecode) = *hyp {
+                var_types[index] = typecode;
+            }
+        }
+        let syms = expr_syms(nset, frame, &frame.target, |index| Sym::Meta(index, var_types[index]))?;
+        self.rules
+            .iter()
+            .map(|rule| rule.typecode)
+            .find(|&typecode| self.parse(typecode, &syms).is_some())
+    }
+
+    fn add_rule(&mut self, nset: &Nameset, frame: &Frame) {
+        let mut var_hyps = vec![None; frame.mandatory_count];
+        for (ix, hyp) in frame.hypotheses.iter().enumerate() {
+            match *hyp {
+                Hyp::Floating(_, var, typecode) => var_hyps[var] = Some((ix, typecode)),
+                // syntax axioms with logical hypotheses are not grammar rules
+                Hyp::Essential(..) => return,
+            }
+        }
+        let pattern = match expr_syms(nset, frame, &frame.target, |var| {
+            let (ix, typecode) = var_hyps[var].expect("mandatory variables have $f hyps");
+            Sym::Meta(ix, typecode)
+        }) {
+            Some(syms) if !syms.is_empty() => syms,
+            _ => return,
+        };
+        let pattern: Box<[RuleSym]> = pattern.iter()
+            .map(|&sym| match sym {
+                Sym::Const(atom) => RuleSym::Const(atom),
+                Sym::Meta(ix, typecode) => RuleSym::Var(ix, typecode),
+                Sym::Var(..) => unreachable!(),
+            })
+            .collect();
+
+        let typecode = frame.target.typecode;
+        let index = self.rules.len();
+        match pattern[0] {
+            RuleSym::Const(head) => self.by_head.entry((typecode, head)).or_default().push(index),
+            RuleSym::Var(..) => self.by_type.entry(typecode).or_default().push(index),
+        }
+        self.rule_index.insert(frame.valid.start, index);
+        self.types.insert(typecode);
+        self.rules.push(SyntaxRule {
+            address: frame.valid.start,
+            typecode,
+            pattern,
+        });
+    }
+
+    /// The typecode of provable statements, `|-` in set.mm.
+    pub fn provable_type(&self) -> Atom {
+        self.provable
+    }
+
+    /// The typecode used to parse provable statements, `wff` in set.mm.
+    pub fn logic_type(&self) -> Atom {
+        self.logic
+    }
+
+    /// Returns true if a typecode is used by syntax axioms or `$f` statements.
+    pub fn is_syntax_type(&self, typecode: Atom) -> bool {
+        self.types.contains(&typecode)
+    }
+
+    /// The typecode used to parse the rest of a math string which starts with
+    /// the given typecode.
+    pub fn body_type(&self, typecode: Atom) -> Atom {
+        if self.is_syntax_type(typecode) {
+            typecode
+        } else {
+            self.logic
+        }
+    }
+
+    /// Returns the typecode produced by a syntax axiom or `$f` statement.
+    pub fn typecode_of(&self, addr: StatementAddress) -> Option<Atom> {
+        match self.rule_index.get(&addr) {
+            Some(&ix) => Some(self.rules[ix].typecode),
+            None => self.floats.get(&addr).map(|&(_, typecode)| typecode),
+        }
+    }
+
+    /// Returns the math string of a syntax axiom after its typecode, if the
+    /// address is one.
+    pub fn rule_pattern(&self, addr: StatementAddress) -> Option<&[RuleSym]> {
+        self.rule_index.get(&addr).map(|&ix| &*self.rules[ix].pattern)
+    }
+
+    /// Returns the variable declared by a `$f` statement, if the address is one.
+    pub fn float_var(&self, addr: StatementAddress) -> Option<Atom> {
+        self.floats.get(&addr).map(|&(var, _)| var)
+    }
+
+    /// Collects the database variables used in a term, in order of first
+    /// appearance.
+    pub fn variables(&self, term: &Term) -> Vec<Atom> {
+        let mut leaves = Vec::new();
+        term.leaves(&mut leaves);
+        leaves.iter().filter_map(|&addr| self.float_var(addr)).collect()
+    }
+
+    /// Finds the syntactic typecode whose name starts with a given letter,
+    /// which is how work variables such as `&W1` or `&C2` get their type.
+    pub fn work_var_type(&self, nset: &Nameset, letter: u8) -> Option<Atom> {
+        let mut types: Vec<Atom> = self.types.iter().cloned().collect();
+        types.sort_by(|&a, &b| nset.atom_name(a).cmp(nset.atom_name(b)));
+        types.into_iter().find(|&tc| {
+            nset.atom_name(tc).first() == Some(&letter.to_ascii_lowercase())
+        })
+    }
+
+    /// Parses a math string, given its typecode and the remaining symbols.
+    pub fn parse(&self, typecode: Atom, syms: &[Sym]) -> Option<Formula> {
+        if syms.is_empty() {
+            return None;
+        }
+        let mut parser = Parser {
+            grammar: self,
+            syms,
+            memo: new_map(),
+        };
+        parser.span(self.body_type(typecode), 0, syms.len()).map(|term| {
+            Formula {
+                typecode,
+                term,
+            }
+        })
+    }
+
+    fn frame_formulas<F>(&self, nset: &Nameset, frame: &Frame, var: F) -> Option<FramePattern>
+        where F: Fn(usize, StatementAddress, Atom) -> Sym
+    {
+        let mut var_types = vec![Atom::default(); frame.mandatory_count];
+        let mut var_addrs = vec![StatementAddress::default(); frame.mandatory_count];
+        for hyp in &*frame.hypotheses {
+            if let Hyp::Floating(addr, index, typecode) = *hyp {
+                var_types[index] = typecode;
+                var_addrs[index] = addr;
+            }
+        }
+        let formula = |expr: &VerifyExpr| {
+            let syms = expr_syms(nset,
+                                 frame,
+                                 expr,
+                                 |index| var(index, var_addrs[index], var_types[index]))?;
+            self.parse(expr.typecode, &syms)
+        };
+        let mut hyps = Vec::with_capacity(frame.hypotheses.len());
+        for hyp in &*frame.hypotheses {
+            hyps.push(match *hyp {
+                Hyp::Essential(_, ref expr) => Some(formula(expr)?),
+                Hyp::Floating(..) => None,
+            });
+        }
+        let target = formula(&frame.target)?;
+        Some(FramePattern {
+            var_types,
+            hyps,
+            target,
+        })
+    }
+
+    /// Parses the hypotheses and conclusion of an assertion for use as a
+    /// pattern; the mandatory variables become metavariables numbered by their
+    /// frame variable index, to be renamed with `Term::shift` before use.
+    pub fn frame_pattern(&self, nset: &Nameset, frame: &Frame) -> Option<FramePattern> {
+        self.frame_formulas(nset, frame, |index, _, typecode| Sym::Meta(index, typecode))
+    }
+
+    /// Parses the hypotheses and conclusion of an assertion as seen from inside
+    /// its own proof, where the variables are fixed.
+    pub fn frame_statement(&self, nset: &Nameset, frame: &Frame) -> Option<FramePattern> {
+        self.frame_formulas(nset, frame, |_, addr, typecode| Sym::Var(addr, typecode))
+    }
+
+    /// Appends the math string of a term, without typecode, in the compressed
+    /// format used by the verifier: each token is terminated by setting the
+    /// high bit of its last byte.
+    ///
+    /// Bound metavariables are replaced by their values; unbound ones are
+    /// written as work variables such as `&W3`.
+    pub fn render(&self, nset: &Nameset, subst: &Subst, term: &Term, out: &mut Vec<u8>) {
+        fn push_token(out: &mut Vec<u8>, tok: &[u8]) {
+            out.extend_from_slice(tok);
+            *out.last_mut().unwrap() |= 0x80;
+        }
+
+        match *term {
+            Term::Meta(var) => {
+                match subst.binding(var) {
+                    Some(bound) => self.render(nset, subst, bound, out),
+                    None => {
+                        let mut name = vec![b'&'];
+                        name.extend(nset.atom_name(subst.typecode(var))
+                            .first()
+                            .map(|c| c.to_ascii_uppercase()));
+                        name.extend_from_slice((var + 1).to_string().as_bytes());
+                        push_token(out, &name);
+                    }
+                }
+            }
+            Term::App(addr, ref children) => {
+                match self.rule_index.get(&addr) {
+                    Some(&ix) => {
+                        for &sym in &*self.rules[ix].pattern {
+                            match sym {
+                                RuleSym::Const(atom) => push_token(out, nset.atom_name(atom)),
+                                RuleSym::Var(hyp, _) => {
+                                    self.render(nset, subst, &children[hyp], out)
+                                }
+                            }
+                        }
+                    }
+                    None => {
+                        let (var, _) = self.floats[&addr];
+                        push_token(out, nset.atom_name(var));
+                    }
+                }
+            }
+        }
+    }
+
+    /// Renders a formula as a human-readable string, with the typecode and with
+    /// tokens separated by spaces.
+    pub fn formula_string(&self, nset: &Nameset, subst: &Subst, formula: &Formula) -> String {
+        let mut buf = Vec::new();
+        self.render(nset, subst, &formula.term, &mut buf);
+        let mut out = String::from_utf8_lossy(nset.atom_name(formula.typecode)).into_owned();
+        let mut start = true;
+        for &chr in &buf {
+            if start {
+                out.push(' ');
+            }
+            out.push((chr & 0x7F) as char);
+            start = chr & 0x80 != 0;
+        }
+        out
+    }
+
+    /// Adds the syntax derivation of a term to a proof tree array, returning
+    /// the index of its root.  The term must not contain metavariables.
+    pub fn build_tree(&self, nset: &Nameset, arr: &mut ProofTreeArray, term: &Term) -> usize {
+        match *term {
+            Term::App(addr, ref children) => {
+                let hyps = children.iter().map(|c| self.build_tree(nset, arr, c)).collect();
+                let mut pool = Vec::new();
+                self.render(nset, &Subst::new(), term, &mut pool);
+                let len = pool.len();
+                arr.build(addr, hyps, &pool, 0..len)
+            }
+            Term::Meta(_) => panic!("build_tree called with an unresolved metavariable"),
+        }
+    }
+}
+
+struct Parser<'a> {
+    grammar: &'a Grammar,
+    syms: &'a [Sym],
+    memo: HashMap<(Atom, usize, usize), Option<Term>>,
+}
+
+impl<'a> Parser<'a> {
+    /// Parses `syms[start..end]` with a given syntactic typecode.
+    fn span(&mut self, typecode: Atom, start: usize, end: usize) -> Option<Term> {
+        if let Some(result) = self.memo.get(&(typecode, start, end)) {
+            return result.clone();
+        }
+        // a failed entry guards against rule cycles such as `class x` while
+        // the real result is computed
+        self.memo.insert((typecode, start, end), None);
+
+        let mut result = None;
+        if end == start + 1 {
+            match self.syms[start] {
+                Sym::Var(addr, vtc) if vtc == typecode => result = Some(Term::App(addr, Vec::new())),
+                Sym::Meta(var, vtc) if vtc == typecode => result = Some(Term::Meta(var)),
+                _ => {}
+            }
+        }
+
+        if result.is_none() {
+            let grammar = self.grammar;
+            let headed = match self.syms[start] {
+                Sym::Const(head) => grammar.by_head.get(&(typecode, head)),
+                _ => None,
+            };
+            let candidates = headed.into_iter().chain(grammar.by_type.get(&typecode)).flatten();
+            for &rule in candidates {
+                if let Some(children) = self.rule(rule, start, end) {
+                    result = Some(Term::App(grammar.rules[rule].address, children));
+                    break;
+                }
+            }
+        }
+
+        self.memo.insert((typecode, start, end), result.clone());
+        result
+    }
+
+    fn rule(&mut self, rule: usize, start: usize, end: usize) -> Option<Vec<Term>> {
+        let pattern = &self.grammar.rules[rule].pattern;
+        if pattern.len() > end - start {
+            return None;
+        }
+        if let RuleSym::Const(last) = pattern[pattern.len() - 1] {
+            if self.syms[end - 1] != Sym::Const(last) {
+                return None;
+            }
+        }
+        let nvars = pattern.iter().filter(|sym| matches!(sym, RuleSym::Var(..))).count();
+        let mut children = vec![None; nvars];
+        if self.matches(pattern, start, end, &mut children) {
+            Some(children.into_iter().map(|c| c.expect("all variables matched")).collect())
+        } else {
+            None
+        }
+    }
+
+    fn matches(&mut self,
+               pattern: &[RuleSym],
+               pos: usize,
+               end: usize,
+               children: &mut Vec<Option<Term>>)
+               -> bool {
+        let (first, rest) = match pattern.split_first() {
+            None => return pos == end,
+            Some(split) => split,
+        };
+        if pos + pattern.len() > end {
+            return false;
+        }
+        match *first {
+            RuleSym::Const(atom) => {
+                self.syms[pos] == Sym::Const(atom) && self.matches(rest, pos + 1, end, children)
+            }
+            RuleSym::Var(hyp, typecode) => {
+                for split in pos + 1..=end - rest.len() {
+                    if let Some(&RuleSym::Const(next)) = rest.first() {
+                        if self.syms[split] != Sym::Const(next) {
+                            continue;
+                        }
+                    }
+                    if let Some(term) = self.span(typecode, pos, split) {
+                        children[hyp] = Some(term);
+                        if self.matches(rest, split, end, children) {
+                            return true;
+                        }
+                    }
+                }
+                false
+            }
+        }
+    }
+}
+
+/// Resolves math tokens as seen from a particular statement, for parsing math
+/// strings which are not part of the database.
+pub struct SymbolScope<'a> {
+    nset: &'a Nameset,
+    names: NameReader<'a>,
+    order: &'a crate::parser::SegmentOrder,
+    address: StatementAddress,
+    local_floats: HashMap<Token, (StatementAddress, Atom)>,
+}
+
+impl<'a> SymbolScope<'a> {
+    /// Collects the symbols which are active at a statement.
+    pub fn new(sset: &'a SegmentSet, nset: &'a Nameset, stmt: StatementRef<'a>) -> Self {
+        let mut local_floats = new_map();
+        for sref in stmt.segment() {
+            if sref.index() >= stmt.index() {
+                break;
+            }
+            let end = sref.scope_range().end;
+            if sref.statement_type() == StatementType::Floating && sref.in_group() &&
+               (end == NO_STATEMENT || stmt.index() < end) {
+                if let Some(tc) = nset.lookup_symbol(&sref.math_at(0)) {
+                    local_floats.insert(copy_token(&sref.math_at(1)), (sref.address(), tc.atom));
+                }
+            }
+        }
+        SymbolScope {
+            nset,
+            names: NameReader::new(nset),
+            order: &sset.order,
+            address: stmt.address(),
+            local_floats,
+        }
+    }
+
+    /// Looks up a token as a constant or as a variable with an active `$f`.
+    pub fn lookup(&mut self, tok: TokenPtr) -> Option<Sym> {
+        if let Some(&(addr, typecode)) = self.local_floats.get(tok) {
+            return Some(Sym::Var(addr, typecode));
+        }
+        if let Some(float) = self.names.lookup_float(tok) {
+            if self.order.cmp(&float.address, &self.address) == Ordering::Less {
+                return Some(Sym::Var(float.address, float.typecode_atom));
+            }
+        }
+        let symbol = self.nset.lookup_symbol(tok)?;
+        match symbol.const_address {
+            Some(addr) if self.order.cmp(&addr.statement, &self.address) == Ordering::Less => {
+                Some(Sym::Const(symbol.atom))
+            }
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::database::Database;
+    use crate::database::DbOptions;
+
+    #[test]
+    fn test_typecodes() {
+        // the typecodes are found from the axioms, not by name
+        let text = b"$c ( ) -> prop |= $.
+            $v p q $.
+            vp $f prop p $.
+            vq $f prop q $.
+            pimp $a prop ( p -> q ) $.
+            ax-1 $a |= ( p -> ( q -> p ) ) $.";
+        let mut db = Database::new(DbOptions::default());
+        db.parse("test.mm".to_owned(), vec![("test.mm".to_owned(), text.to_vec())]);
+        let nset = db.name_result().clone();
+        let grammar = db.grammar_result().clone();
+        assert_eq!(nset.atom_name(grammar.provable_type()), b"|=");
+        assert_eq!(nset.atom_name(grammar.logic_type()), b"prop");
+    }
+}
@@ -0,0 +1,463 @@
+//! Static HTML pages for the assertions of a database.
+//!
+//! One page is written for each `$a` and `$p` statement, named after its
+//! label, in the style of the pages generated by metamath-exe: the description
+//! from the comment before the statement, its hypotheses and assertion, the
+//! proof as a table of steps, the theorems which refer to it, and the axioms
+//! and definitions its proof ultimately depends on.
+//!
+//! Math strings are rendered with the `htmldef` definitions of the database's
+//! `$t` comments, or the `althtmldef` ones if requested.  Comments use the
+//! markup understood by metamath-exe: `` `math` `` for math strings, `~ label`
+//! for links to other statements, `[name]` for bibliographic references,
+//! `_text_` for italics, `<HTML>...</HTML>` for raw HTML, and blank lines for
+//! paragraph breaks.
+//!
+//! The references and axioms used are computed for the whole database first,
+//! and the pages of each segment are then written in parallel.
+
+use crate::bit_set::Bitset;
+use crate::depgraph::AssertionKinds;
+use crate::depgraph::NodeKind;
+use crate::nameck::Nameset;
+use crate::parser::as_str;
+use crate::parser::SegmentId;
+use crate::parser::StatementAddress;
+use crate::parser::StatementRef;
+use crate::parser::StatementType;
+use crate::proof::proof_references;
+use crate::proof::ListingStyle;
+use crate::proof::ProofListing;
+use crate::proof::ProofTreeArray;
+use crate::scopeck::Hyp::Essential;
+use crate::scopeck::ScopeResult;
+use crate::segment_set::SegmentSet;
+use crate::typesetting::Typesetting;
+use crate::util::HashMap;
+use crate::util::new_map;
+use std::fmt::Write;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+/// The uses of each assertion by the proofs of the database.
+#[derive(Default)]
+struct Usage {
+    /// Theorems whose proofs refer directly to each assertion
+    referenced_by: HashMap<StatementAddress, Vec<StatementAddress>>,
+    /// The kind of each assertion
+    kinds: AssertionKinds,
+    /// `$a` statements for logical axioms and definitions, in database order
+    axioms: Vec<StatementAddress>,
+    /// Axioms each theorem depends on, as indexes into `axioms`
+    depends: HashMap<StatementAddress, Bitset>,
+}
+
+impl Usage {
+    fn new(sset: &SegmentSet, nset: &Nameset) -> Usage {
+        let mut usage = Usage {
+            kinds: AssertionKinds::new(sset),
+            ..Usage::default()
+        };
+        let mut axiom_index = new_map();
+        for sref in sset.segments() {
+            for stmt in sref {
+                match stmt.statement_type() {
+                    StatementType::Axiom if usage.kinds.kind(stmt) != NodeKind::Syntax => {
+                        axiom_index.insert(stmt.address(), usage.axioms.len());
+                        usage.axioms.push(stmt.address());
+                    }
+                    StatementType::Provable => {
+                        let mut depends = Bitset::new();
+                        for used in proof_references(sset, nset, stmt) {
+                            usage.referenced_by.entry(used).or_default().push(stmt.address());
+                            if let Some(&index) = axiom_index.get(&used) {
+                                depends.set_bit(index);
+                            } else if let Some(used_depends) = usage.depends.get(&used) {
+                                depends |= used_depends;
+                            }
+                        }
+                        usage.depends.insert(stmt.address(), depends);
+                    }
+                    _ => {}
+                }
+            }
+        }
+        usage
+    }
+}
+
+/// Data shared by the writers of each segment.
+struct Context {
+    sset: Arc<SegmentSet>,
+    nset: Arc<Nameset>,
+    scope: Arc<ScopeResult>,
+    typesetting: Arc<Typesetting>,
+    usage: Usage,
+    alt: bool,
+    dir: PathBuf,
+}
+
+fn escape(out: &mut String, text: &[u8]) {
+    for chr in String::from_utf8_lossy(text).chars() {
+        match chr {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            _ => out.push(chr),
+        }
+    }
+}
+
+fn is_space(chr: u8) -> bool {
+    chr.is_ascii_whitespace()
+}
+
+impl Context {
+    fn label(&self, addr: StatementAddress) -> &str {
+        as_str(self.sset.statement(addr).label())
+    }
+
+    fn link(&self, out: &mut String, addr: StatementAddress) {
+        let label = self.label(addr);
+        write!(out, "<a href=\"{0}.html\">{0}</a>", label).unwrap();
+    }
+
+    /// Renders a sequence of math symbols with the typesetting definitions.
+    fn typeset<'b, I: IntoIterator<Item = &'b [u8]>>(&self, out: &mut String, symbols: I) {
+        out.push_str("<span class=\"math\">");
+        for symbol in symbols {
+            let def = if self.alt {
+                self.typesetting.alt_html_def(symbol)
+            } else {
+                self.typesetting.html_def(symbol)
+            };
+            match def {
+                Some(def) => out.push_str(def),
+                None => {
+                    escape(out, symbol);
+                    out.push(' ');
+                }
+            }
+        }
+        out.push_str("</span>");
+    }
+
+    fn typeset_statement(&self, out: &mut String, stmt: StatementRef) {
+        self.typeset(out, (0..stmt.math_len()).map(|index| stmt.math_at(index).slice));
+    }
+
+    /// Renders the markup of a comment, without the `$(` and `$)`.
+    fn comment(&self, out: &mut String, text: &[u8]) {
+        let mut pos = 0;
+        let mut italic = false;
+        while pos < text.len() {
+            let rest = &text[pos..];
+            let chr = rest[0];
+            let prev = if pos == 0 { b' ' } else { text[pos - 1] };
+            let next = rest.get(1).cloned().unwrap_or(b' ');
+            // the end of a bibliographic reference, and of a blank line
+            let bracket = rest.iter().position(|&c| c == b']' || is_space(c));
+            let blank = rest.iter()
+                .skip(1)
+                .position(|&c| c != b' ' && c != b'\t' && c != b'\r')
+                .map(|end| end + 1);
+            if rest.starts_with(b"<HTML>") {
+                let end = rest.windows(7)
+                    .position(|w| w == b"</HTML>")
+                    .unwrap_or(rest.len());
+                out.push_str(&String::from_utf8_lossy(&rest[6..end]));
+                pos += (end + 7).min(rest.len());
+            } else if chr == b'`' && next == b'`' || chr == b'~' && next == b'~' ||
+                      chr == b'[' && next == b'[' {
+                out.push(chr as char);
+                pos += 2;
+            } else if chr == b'`' {
+                let end = rest[1..].iter().position(|&c| c == b'`').map_or(rest.len(), |e| e + 1);
+                self.typeset(out,
+                             rest[1..end].split(|&c| is_space(c)).filter(|sym| !sym.is_empty()));
+                pos += (end + 1).min(rest.len());
+            } else if chr == b'~' {
+                let start = 1 + rest[1..].iter().position(|&c| !is_space(c)).unwrap_or(rest.len() - 1);
+                let end = rest[start..]
+                    .iter()
+                    .position(|&c| is_space(c))
+                    .map_or(rest.len(), |e| start + e);
+                let target = &rest[start..end];
+                if target.starts_with(b"http:") || target.starts_with(b"https:") {
+                    out.push_str("<a href=\"");
+                    escape(out, target);
+                    out.push_str("\">");
+                    escape(out, target);
+                    out.push_str("</a>");
+                } else {
+                    match self.nset.lookup_label(target) {
+                        Some(lookup) => self.link(out, lookup.address),
+                        None => escape(out, target),
+                    }
+                }
+                pos += end;
+            } else if chr == b'[' && bracket.map(|end| rest[end]) == Some(b']') {
+                let end = bracket.unwrap();
+                let name = &rest[1..end];
+                out.push('[');
+                match self.typesetting.setting("htmlbibliography") {
+                    Some(bib) => {
+                        write!(out, "<a href=\"{}#", bib).unwrap();
+                        escape(out, name);
+                        out.push_str("\">");
+                        escape(out, name);
+                        out.push_str("</a>");
+                    }
+                    None => escape(out, name),
+                }
+                out.push(']');
+                pos += end + 1;
+            } else if chr == b'_' && !italic && is_space(prev) && next.is_ascii_alphanumeric() {
+                out.push_str("<i>");
+                italic = true;
+                pos += 1;
+            } else if chr == b'_' && italic && prev.is_ascii_alphanumeric() &&
+                      !next.is_ascii_alphanumeric() {
+                out.push_str("</i>");
+                italic = false;
+                pos += 1;
+            } else if chr == b'\n' && blank.map(|end| rest[end]) == Some(b'\n') {
+                out.push_str("\n<p>\n");
+                pos += blank.unwrap() + 1;
+            } else {
+                // a whole UTF-8 sequence, with its continuation bytes
+                let len = 1 + rest[1..].iter().take_while(|&&c| c & 0xC0 == 0x80).count();
+                escape(out, &rest[..len]);
+                pos += len;
+            }
+        }
+        if italic {
+            out.push_str("</i>");
+        }
+    }
+
+    fn proof_table(&self, out: &mut String, stmt: StatementRef) {
+        let arr = match ProofTreeArray::new(&self.sset, &self.nset, &self.scope, stmt) {
+            Ok(arr) => arr,
+            Err(_) => {
+                out.push_str("<p>This proof is incomplete or does not verify.</p>\n");
+                return;
+            }
+        };
+        let listing = ProofListing {
+            sset: &self.sset,
+            scope: &self.scope,
+            arr: &arr,
+            style: ListingStyle::Tree,
+            essential: true,
+        };
+        out.push_str("<table class=\"proof\">\n<tr><th>Step</th><th>Hyp</th><th>Ref</th>\
+                      <th>Expression</th></tr>\n");
+        for (n, (ix, hyps)) in listing.steps().into_iter().enumerate() {
+            let refs: Vec<String> = hyps.iter().map(|hyp| hyp.to_string()).collect();
+            let addr = arr.trees[ix].address;
+            let used = self.sset.statement(addr);
+            write!(out, "<tr><td>{}</td><td>{}</td><td>", n + 1, refs.join(", ")).unwrap();
+            match used.statement_type() {
+                StatementType::Axiom | StatementType::Provable => self.link(out, addr),
+                _ => out.push_str(self.label(addr)),
+            }
+            out.push_str("</td><td>");
+            let expr = &arr.exprs[ix];
+            self.typeset(out,
+                         Some(used.math_at(0).slice)
+                             .into_iter()
+                             .chain(expr.split(|&c| c == b' ').filter(|sym| !sym.is_empty())));
+            out.push_str("</td></tr>\n");
+        }
+        out.push_str("</table>\n");
+    }
+
+    fn page(&self, stmt: StatementRef) -> String {
+        let label = as_str(stmt.label());
+        let title = self.typesetting.setting("htmltitle").unwrap_or("Metamath");
+        let mut out = String::new();
+        out.push_str("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n");
+        writeln!(out, "<title>{} - {}</title>", label, title).unwrap();
+        if let Some(css) = self.typesetting.setting("htmlcss") {
+            out.push_str(css);
+            out.push('\n');
+        }
+        out.push_str("</head>\n<body>\n");
+        if let Some(home) = self.typesetting.setting("htmlhome") {
+            out.push_str(home);
+            out.push('\n');
+        }
+
+        let kind = match self.usage.kinds.kind(stmt) {
+            NodeKind::Theorem => "Theorem",
+            NodeKind::Syntax => "Syntax definition",
+            NodeKind::Definition => "Definition",
+            NodeKind::Axiom => "Axiom",
+        };
+        writeln!(out, "<h1>{} {}</h1>", kind, label).unwrap();
+
+        if let Some(comment) = stmt.associated_comment() {
+            let text = comment.span().as_ref(&comment.segment().segment.buffer);
+            let text = &text[2..text.len().saturating_sub(2).max(2)];
+            out.push_str("<div class=\"description\">");
+            self.comment(&mut out, text);
+            out.push_str("</div>\n");
+        }
+
+        if let Some(frame) = self.scope.get(stmt.label()) {
+            let hyps: Vec<_> = frame.hypotheses
+                .iter()
+                .filter_map(|hyp| match *hyp {
+                    Essential(addr, _) => Some(addr),
+                    _ => None,
+                })
+                .collect();
+            if !hyps.is_empty() {
+                out.push_str("<h2>Hypotheses</h2>\n<table class=\"hypotheses\">\n");
+                for addr in hyps {
+                    write!(out, "<tr><td>{}</td><td>", self.label(addr)).unwrap();
+                    self.typeset_statement(&mut out, self.sset.statement(addr));
+                    out.push_str("</td></tr>\n");
+                }
+                out.push_str("</table>\n");
+            }
+        }
+        write!(out,
+               "<h2>Assertion</h2>\n<table class=\"assertion\">\n<tr><td>{}</td><td>",
+               label)
+            .unwrap();
+        self.typeset_statement(&mut out, stmt);
+        out.push_str("</td></tr>\n</table>\n");
+
+        if stmt.statement_type() == StatementType::Provable {
+            out.push_str("<h2>Proof</h2>\n");
+            self.proof_table(&mut out, stmt);
+        }
+
+        if let Some(users) = self.usage.referenced_by.get(&stmt.address()) {
+            out.push_str("<h2>This theorem is referenced by</h2>\n<p class=\"references\">");
+            for &user in users {
+                self.link(&mut out, user);
+                out.push(' ');
+            }
+            out.push_str("</p>\n");
+        }
+
+        if let Some(depends) = self.usage.depends.get(&stmt.address()) {
+            let (defs, axioms): (Vec<_>, Vec<_>) = depends.into_iter()
+                .map(|index| self.usage.axioms[index])
+                .partition(|&addr| {
+                    self.usage.kinds.kind(self.sset.statement(addr)) == NodeKind::Definition
+                });
+            for (list, heading) in [(axioms, "This theorem was proved from axioms"),
+                                        (defs, "This theorem depends on definitions")] {
+                if !list.is_empty() {
+                    write!(out, "<h2>{}</h2>\n<p class=\"axioms\">", heading).unwrap();
+                    for addr in list {
+                        self.link(&mut out, addr);
+                        out.push(' ');
+                    }
+                    out.push_str("</p>\n");
+                }
+            }
+        }
+        out.push_str("</body>\n</html>\n");
+        out
+    }
+
+    /// Writes the pages for the assertions of one segment, returning how many
+    /// there were.
+    fn write_segment(&self, id: SegmentId) -> io::Result<usize> {
+        let mut count = 0;
+        for stmt in self.sset.segment(id) {
+            let stype = stmt.statement_type();
+            if stype == StatementType::Axiom || stype == StatementType::Provable {
+                let path = self.dir.join(format!("{}.html", as_str(stmt.label())));
+                fs::write(path, self.page(stmt))?;
+                count += 1;
+            }
+        }
+        Ok(count)
+    }
+}
+
+/// Writes a page for every assertion of the database into `dir`, which is
+/// created if needed, and returns the number of pages written.
+///
+/// If `alt` is set, math is rendered with the `althtmldef` definitions.
+pub fn write_html(segments: &Arc<SegmentSet>,
+                  nset: &Arc<Nameset>,
+                  scope: &Arc<ScopeResult>,
+                  typesetting: &Arc<Typesetting>,
+                  dir: &Path,
+                  alt: bool)
+                  -> io::Result<usize> {
+    fs::create_dir_all(dir)?;
+    let context = Arc::new(Context {
+        sset: segments.clone(),
+        nset: nset.clone(),
+        scope: scope.clone(),
+        typesetting: typesetting.clone(),
+        usage: Usage::new(segments, nset),
+        alt,
+        dir: dir.to_owned(),
+    });
+    let mut promises = Vec::new();
+    for sref in segments.segments() {
+        let context = context.clone();
+        let id = sref.id;
+        promises.push(segments.exec.exec(sref.bytes(), move || context.write_segment(id)));
+    }
+    let mut count = 0;
+    for promise in promises {
+        count += promise.wait()?;
+    }
+    Ok(count)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_util::database;
+    use crate::test_util::A1I;
+    use crate::test_util::PROP;
+    use crate::test_util::temp_path;
+    use std::fs;
+
+    #[test]
+    fn test_write_html() {
+        let prop = PROP.replace("ax-1 $a",
+                                "$( Axiom _Simp_ of Łukasiewicz.  See ~ a1i and [Margaris].
+
+                                    Also ` ph -> ps ` . $)
+                                 ax-1 $a");
+        let mut db = database(&[&prop,
+                                "$( $t htmltitle \"Test\"; htmldef \"->\" as \" &rarr; \"; $)\n",
+                                A1I]
+            .concat());
+        let dir = temp_path("html");
+        let count = db.write_html(dir.to_str().unwrap(), false).unwrap();
+        let ax_1 = String::from_utf8(fs::read(dir.join("ax-1.html")).unwrap()).unwrap();
+        let a1i = String::from_utf8(fs::read(dir.join("a1i.html")).unwrap()).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(count, 4);
+
+        assert!(ax_1.contains("<title>ax-1 - Test</title>"));
+        assert!(ax_1.contains("<h1>Axiom ax-1</h1>"));
+        assert!(ax_1.contains("Axiom <i>Simp</i> of Łukasiewicz.  See <a href=\"a1i.html\">a1i</a> and \
+                               [Margaris].\n<p>\n"));
+        assert!(ax_1.contains("<span class=\"math\">ph  &rarr; ps </span>"));
+        assert!(ax_1.contains("<h2>This theorem is referenced by</h2>\n\
+                               <p class=\"references\"><a href=\"a1i.html\">a1i</a> </p>"));
+
+        assert!(a1i.contains("<h2>Hypotheses</h2>"));
+        assert!(a1i.contains("<tr><td>3</td><td>1, 2</td><td><a href=\"ax-mp.html\">ax-mp</a>\
+                              </td><td><span class=\"math\">|- ( ps  &rarr; ph ) </span>"));
+        assert!(a1i.contains("<p class=\"axioms\"><a href=\"ax-1.html\">ax-1</a> \
+                              <a href=\"ax-mp.html\">ax-mp</a> </p>"));
+    }
+}
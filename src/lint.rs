@@ -0,0 +1,281 @@
+//! Checks for constructs which are valid Metamath, but are probably mistakes.
+//!
+//! The lints currently implemented all look for declarations which are never
+//! needed:
+//!
+//! * `$e` hypotheses of a `$p` statement which its proof does not use;
+//! * mandatory variables of a `$p` statement which occur only in such unused
+//!   hypotheses, and which the statement would lose if they were removed;
+//! * `$v` and `$f` statements inside a `${ $}` group which no assertion in
+//!   their scope uses, either as a mandatory variable or in a proof.
+//!
+//! It also enforces the discouragement tags of set.mm: a proof may not refer
+//! to a statement whose comment says `(New usage is discouraged.)`, unless the
+//! proved statement has that tag itself.  `(Proof modification is
+//! discouraged.)` only protects a proof from edits, so it is no exemption.
+//! `discouraged_changes` separately compares proofs tagged `(Proof
+//! modification is discouraged.)` with a baseline copy of the database.
+//!
+//! Uses of hypotheses in proofs are found by replaying each proof with the
+//! verifier into a `ProofTreeArray`, so proofs which fail to verify are not
+//! checked; the verify pass reports those.  Top-level `$v` and `$f`
+//! statements are not checked, since databases generally declare them for
+//! later use.
+//!
+//! This pass is not incremental, and works on each segment in parallel.
+
+use crate::bit_set::Bitset;
+use crate::diag::Diagnostic;
+use crate::nameck::Nameset;
+use crate::parser::SegmentId;
+use crate::parser::StatementAddress;
+use crate::parser::StatementRef;
+use crate::parser::StatementType;
+use crate::proof::proof_references;
+use crate::proof::proof_text;
+use crate::proof::ProofTreeArray;
+use crate::scopeck::Hyp::{Essential, Floating};
+use crate::scopeck::ScopeResult;
+use crate::segment_set::SegmentSet;
+use crate::util::new_set;
+use crate::util::HashSet;
+use std::sync::Arc;
+
+/// Analysis pass result for the lints.
+#[derive(Default,Clone)]
+pub struct LintResult {
+    diagnostics: Vec<(StatementAddress, Diagnostic)>,
+}
+
+impl LintResult {
+    /// Report all lints found in the database.
+    pub fn diagnostics(&self) -> Vec<(StatementAddress, Diagnostic)> {
+        self.diagnostics.clone()
+    }
+}
+
+/// Reports the statements with discouraged usage which a proof refers to.
+fn check_discouraged(nset: &Nameset,
+                     sset: &SegmentSet,
+                     stmt: StatementRef,
+                     out: &mut Vec<(StatementAddress, Diagnostic)>) {
+    if stmt.usage_discouraged() {
+        return;
+    }
+    for used in proof_references(sset, nset, stmt) {
+        if sset.statement(used).usage_discouraged() {
+            out.push((stmt.address(), Diagnostic::DiscouragedUsage(used)));
+        }
+    }
+}
+
+/// Checks the proofs and local declarations of one segment.
+fn lint_segment(sset: &SegmentSet,
+                nset: &Nameset,
+                scope: &ScopeResult,
+                sid: SegmentId)
+                -> Vec<(StatementAddress, Diagnostic)> {
+    let mut out = Vec::new();
+    // $f statements of this segment used by a frame or a proof
+    let mut used_floats = new_set();
+
+    for stmt in sset.segment(sid) {
+        let stype = stmt.statement_type();
+        if stype != StatementType::Axiom && stype != StatementType::Provable {
+            continue;
+        }
+        let frame = match scope.get(stmt.label()) {
+            Some(frame) => frame,
+            None => continue,
+        };
+        for hyp in &*frame.hypotheses {
+            if let Floating(addr, _, _) = *hyp {
+                used_floats.insert(addr);
+            }
+        }
+        if stype != StatementType::Provable {
+            continue;
+        }
+        check_discouraged(nset, sset, stmt, &mut out);
+        let arr = match ProofTreeArray::new(sset, nset, scope, stmt) {
+            Ok(arr) => arr,
+            Err(_) => continue,
+        };
+
+        let mut used: HashSet<StatementAddress> = new_set();
+        let mut stack = vec![arr.qed];
+        while let Some(ix) = stack.pop() {
+            let tree = &arr.trees[ix];
+            if used.insert(tree.address) {
+                stack.extend_from_slice(&tree.children);
+            }
+        }
+        used_floats.extend(used.iter().cloned());
+
+        let mut used_vars = Bitset::new();
+        for part in &*frame.target.tail {
+            used_vars.set_bit(part.var);
+        }
+        for hyp in &*frame.hypotheses {
+            if let Essential(addr, ref expr) = *hyp {
+                if used.contains(&addr) {
+                    for part in &*expr.tail {
+                        used_vars.set_bit(part.var);
+                    }
+                } else {
+                    out.push((stmt.address(), Diagnostic::UnusedEssential(addr)));
+                }
+            }
+        }
+        for (index, &var) in frame.var_list[..frame.mandatory_count].iter().enumerate() {
+            if !used_vars.has_bit(index) {
+                out.push((stmt.address(),
+                          Diagnostic::UnusedMandatoryVar(nset.atom_name(var).into())));
+            }
+        }
+    }
+
+    for stmt in sset.segment(sid) {
+        if !stmt.in_group() {
+            continue;
+        }
+        match stmt.statement_type() {
+            StatementType::Floating if !used_floats.contains(&stmt.address()) => {
+                out.push((stmt.address(), Diagnostic::UnusedFloating));
+            }
+            StatementType::Variable => {
+                let end = stmt.scope_range().end;
+                for index in 0..stmt.math_len() {
+                    let var = stmt.math_at(index).slice;
+                    // a variable is used if one of its $f statements is
+                    let used = (stmt.index() + 1..end).any(|fix| {
+                        let float = stmt.segment().statement(fix);
+                        float.statement_type() == StatementType::Floating &&
+                        float.math_len() == 2 && float.math_at(1).slice == var &&
+                        used_floats.contains(&float.address())
+                    });
+                    if !used {
+                        out.push((stmt.address(), Diagnostic::UnusedVariable(index)));
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    out
+}
+
+/// Finds the `$p` statements with `(Proof modification is discouraged.)` in
+/// their comment, in either database, whose proofs differ from those in the
+/// baseline database.  Theorems which are not in the baseline are not
+/// reported.
+pub fn discouraged_changes(sset: &SegmentSet,
+                           base_sset: &SegmentSet,
+                           base_nset: &Nameset)
+                           -> Vec<(StatementAddress, Diagnostic)> {
+    let mut out = Vec::new();
+    for sref in sset.segments() {
+        for stmt in sref {
+            if stmt.statement_type() != StatementType::Provable {
+                continue;
+            }
+            let base = match base_nset.lookup_label(stmt.label()) {
+                Some(lookup) => base_sset.statement(lookup.address),
+                None => continue,
+            };
+            if base.statement_type() != StatementType::Provable ||
+               !(stmt.proof_modification_discouraged() ||
+                 base.proof_modification_discouraged()) {
+                continue;
+            }
+            if proof_text(stmt) != proof_text(base) {
+                out.push((stmt.address(), Diagnostic::DiscouragedProofChange));
+            }
+        }
+    }
+    out
+}
+
+/// Runs the lints on every segment of a database.
+pub fn lint(segments: &Arc<SegmentSet>,
+            nset: &Arc<Nameset>,
+            scope: &Arc<ScopeResult>)
+            -> LintResult {
+    let mut ssrq = Vec::new();
+    for sref in segments.segments() {
+        let segments2 = segments.clone();
+        let nset = nset.clone();
+        let scope = scope.clone();
+        let id = sref.id;
+        ssrq.push(segments.exec.exec(sref.bytes(),
+                                     move || lint_segment(&segments2, &nset, &scope, id)));
+    }
+
+    let mut result = LintResult::default();
+    for promise in ssrq {
+        result.diagnostics.extend(promise.wait());
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::database::Database;
+    use crate::database::DbOptions;
+    use crate::diag::Diagnostic;
+    use crate::test_util::database;
+    use crate::test_util::PROP;
+
+    #[test]
+    fn test_unused() {
+        let text = "${
+                $v th ta $.
+                wth $f wff th $.
+                wta $f wff ta $.
+                id.1 $e |- ph $.
+                id.2 $e |- th $.
+                id $p |- ph $= id.1 $.
+            $}";
+        let mut db = database(&[PROP, text].concat());
+        let sset = db.parse_result().clone();
+        let mut lints: Vec<_> = db.lint_result()
+            .diagnostics()
+            .into_iter()
+            .map(|(addr, diag)| (sset.statement(addr).label().to_vec(), diag))
+            .collect();
+        lints.sort_by(|a, b| a.0.cmp(&b.0));
+        let id_2 = db.statement("id.2").unwrap().address();
+        assert_eq!(lints,
+                   vec![(b"".to_vec(), Diagnostic::UnusedVariable(1)),
+                        (b"id".to_vec(), Diagnostic::UnusedEssential(id_2)),
+                        (b"id".to_vec(), Diagnostic::UnusedMandatoryVar(b"th"[..].into())),
+                        (b"wta".to_vec(), Diagnostic::UnusedFloating)]);
+    }
+
+    #[test]
+    fn test_discouraged() {
+        let text = "$c |- wff $. $v ph $. wph $f wff ph $.
+            $( (New usage is discouraged.) $)
+            ax $a |- ph $.
+            th $p |- ph $= wph ax $.
+            $( (Proof modification is discouraged.) $)
+            th2 $p |- ph $= wph ax $.
+            $( (New usage is discouraged.) $)
+            th3 $p |- ph $= wph ax $.";
+        let mut db = database(text);
+        let ax = db.statement("ax").unwrap().address();
+        let th = db.statement("th").unwrap().address();
+        let th2 = db.statement("th2").unwrap().address();
+        // only th3 may use ax
+        assert_eq!(db.lint_result().diagnostics(),
+                   vec![(th, Diagnostic::DiscouragedUsage(ax)),
+                        (th2, Diagnostic::DiscouragedUsage(ax))]);
+
+        let mut base = Database::new(DbOptions::default());
+        let changed = text.replace("$= wph ax $.", "$= ( ax ) AB $.");
+        base.parse("base.mm".to_owned(), vec![("base.mm".to_owned(), changed.into_bytes())]);
+        let notes = db.discouraged_changes(&mut base);
+        assert_eq!(notes.len(), 1);
+        assert_eq!(notes[0].args, vec![("label", "th2".to_owned())]);
+    }
+}
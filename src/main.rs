@@ -6,14 +6,32 @@
 
 pub mod bit_set;
 pub mod database;
+pub mod depgraph;
 pub mod diag;
+pub mod diff;
+pub mod duplicates;
 pub mod export;
+pub mod extract;
+pub mod grammar;
+pub mod html;
 pub mod line_cache;
+pub mod lint;
+pub mod minimize;
+pub mod mmp;
 pub mod nameck;
 pub mod parser;
+pub mod pretty;
 pub mod proof;
+pub mod prove;
+pub mod rename;
 pub mod scopeck;
+pub mod search;
 pub mod segment_set;
+#[cfg(test)]
+mod test_util;
+pub mod trace;
+pub mod typesetting;
+pub mod unify;
 pub mod util;
 pub mod verify;
 
@@ -21,12 +39,35 @@ use clap::Arg;
 use clap::App;
 use clap::crate_version;
 use crate::database::Database;
+use crate::database::TheoremError;
+use crate::depgraph::GraphFormat;
+use crate::depgraph::GraphOptions;
+use crate::diag::Diagnostic;
 use crate::diag::DiagnosticClass;
 use crate::diag::Notation;
+use crate::export::ExportError;
+use crate::export::LatexOptions;
+use crate::grammar::SymbolScope;
 use crate::line_cache::LineCache;
+use crate::minimize::MinimizeOptions;
+use crate::parser::as_str;
+use crate::parser::StatementAddress;
+use crate::parser::StatementType;
+use crate::pretty::PrettyPrinter;
+use crate::pretty::SymbolMap;
+use crate::rename::RenameError;
+use crate::proof::ListingStyle;
+use crate::proof::ProofListing;
+use crate::proof::ProofStyle;
+use crate::proof::ProofTreeArray;
+use crate::proof::ProofTreePrinter;
+use crate::prove::ProveOptions;
+use crate::scopeck::Hyp;
+use std::fs;
 use std::io;
 use std::mem;
 use std::str::FromStr;
+use std::time::Duration;
 
 /// parse unsigned integer
 pub fn positive_integer(val: String) -> Result<(), String> {
@@ -47,6 +88,27 @@ pub fn main() {
             .long("silent"))
         .arg(Arg::with_name("timing").help("Print milliseconds after each stage").long("timing"))
         .arg(Arg::with_name("verify").help("Check proof validity").long("verify").short("v"))
+        .arg(Arg::with_name("profile")
+            .help("Verify, and report the N most expensive proofs and the cost of each segment")
+            .long("profile")
+            .value_name("N")
+            .takes_value(true)
+            .validator(positive_integer))
+        .arg(Arg::with_name("only")
+            .help("Verify only the proof of LABEL, or of each theorem in FIRST:LAST")
+            .long("only")
+            .value_name("LABEL")
+            .multiple(true)
+            .takes_value(true))
+        .arg(Arg::with_name("only-section")
+            .help("Verify only the proofs in the outline section with the title TITLE")
+            .long("only-section")
+            .value_name("TITLE")
+            .multiple(true)
+            .takes_value(true))
+        .arg(Arg::with_name("lint")
+            .help("Warn about unused hypotheses and variables")
+            .long("lint"))
         .arg(Arg::with_name("trace-recalc")
             .help("Print segments as they are recalculated")
             .long("trace-recalc"))
@@ -66,6 +128,184 @@ pub fn main() {
             .short("e")
             .multiple(true)
             .takes_value(true))
+        .arg(Arg::with_name("import-mmp")
+            .help("Check an mmj2 proof worksheet and print the resulting proof")
+            .long("import-mmp")
+            .multiple(true)
+            .takes_value(true))
+        .arg(Arg::with_name("minimize")
+            .help("Search for a shorter proof of LABEL, or of each theorem in FIRST:LAST")
+            .long("minimize")
+            .value_name("LABEL")
+            .multiple(true)
+            .takes_value(true))
+        .arg(Arg::with_name("prove")
+            .help("Search for a proof of the theorem LABEL, or of the --goal in its scope, \
+                   and print it")
+            .long("prove")
+            .value_name("LABEL")
+            .takes_value(true))
+        .arg(Arg::with_name("goal")
+            .help("Math string to prove with --prove instead of the theorem itself")
+            .long("goal")
+            .value_name("MATH")
+            .takes_value(true))
+        .arg(Arg::with_name("prove-depth")
+            .help("Greatest nesting of assertions in a proof found by --prove (default 4)")
+            .long("prove-depth")
+            .value_name("N")
+            .takes_value(true)
+            .validator(positive_integer))
+        .arg(Arg::with_name("prove-steps")
+            .help("Number of assertion applications --prove tries before giving up (default \
+                   100000)")
+            .long("prove-steps")
+            .value_name("N")
+            .takes_value(true)
+            .validator(positive_integer))
+        .arg(Arg::with_name("prove-time")
+            .help("Seconds --prove searches before giving up (default 10)")
+            .long("prove-time")
+            .value_name("SECONDS")
+            .takes_value(true)
+            .validator(positive_integer))
+        .arg(Arg::with_name("dv")
+            .help("Report the $d conditions which the proof of LABEL is missing or does not \
+                   need")
+            .long("dv")
+            .value_name("LABEL")
+            .multiple(true)
+            .takes_value(true))
+        .arg(Arg::with_name("trace")
+            .help("Show each assertion applied by the proof of LABEL, with its substitution")
+            .long("trace")
+            .value_name("LABEL")
+            .multiple(true)
+            .takes_value(true))
+        .arg(Arg::with_name("show-proof")
+            .help("List the steps of the proof of LABEL with their expressions")
+            .long("show-proof")
+            .value_name("LABEL")
+            .multiple(true)
+            .takes_value(true))
+        .arg(Arg::with_name("listing")
+            .help("Layout for --show-proof: tree (default) or lemmon")
+            .long("listing")
+            .value_name("STYLE")
+            .possible_values(&["tree", "lemmon"])
+            .takes_value(true))
+        .arg(Arg::with_name("essential")
+            .help("Hide syntax steps in --show-proof")
+            .long("essential"))
+        .arg(Arg::with_name("html")
+            .help("Write an HTML page for each axiom and theorem into DIR")
+            .long("html")
+            .value_name("DIR")
+            .takes_value(true))
+        .arg(Arg::with_name("alt-html")
+            .help("Typeset the pages of --html with althtmldef instead of htmldef")
+            .long("alt-html"))
+        .arg(Arg::with_name("pretty")
+            .help("Print the statement LABEL and its hypotheses, breaking long formulas")
+            .long("pretty")
+            .value_name("LABEL")
+            .multiple(true)
+            .takes_value(true))
+        .arg(Arg::with_name("search")
+            .help("Print the assertions whose conclusion unifies with QUERY, a math string in \
+                   which variables stand for any expression of their type, best matches first")
+            .long("search")
+            .value_name("QUERY")
+            .takes_value(true))
+        .arg(Arg::with_name("search-hyps")
+            .help("Also match the $e hypotheses of assertions in --search")
+            .long("search-hyps"))
+        .arg(Arg::with_name("unicode")
+            .help("Print math symbols in --pretty and diagnostics using althtmldef")
+            .long("unicode"))
+        .arg(Arg::with_name("symbols")
+            .help("Print math symbols in --pretty and diagnostics using the replacements in \
+                   FILE, one symbol and its replacement per line")
+            .long("symbols")
+            .value_name("FILE")
+            .takes_value(true))
+        .arg(Arg::with_name("width")
+            .help("Line width for --pretty (default 79)")
+            .long("width")
+            .value_name("N")
+            .takes_value(true)
+            .validator(positive_integer))
+        .arg(Arg::with_name("extract")
+            .help("Write to FILE a database with only what is needed to state and prove each \
+                   LABEL")
+            .long("extract")
+            .value_names(&["FILE", "LABEL"])
+            .min_values(2))
+        .arg(Arg::with_name("rename")
+            .help("Rename the statement OLD to NEW with every reference to it, and write back \
+                   the changed files if the result verifies")
+            .long("rename")
+            .value_names(&["OLD", "NEW"]))
+        .arg(Arg::with_name("graph")
+            .help("Write the dependency graph of LABEL, or of the outline section TITLE, to \
+                   FILE")
+            .long("graph")
+            .value_names(&["LABEL|TITLE", "FILE"]))
+        .arg(Arg::with_name("graph-format")
+            .help("Format for --graph: dot (default), graphml or json")
+            .long("graph-format")
+            .value_name("FORMAT")
+            .possible_values(&["dot", "graphml", "json"])
+            .takes_value(true))
+        .arg(Arg::with_name("graph-no-axioms")
+            .help("Leave logical axioms out of --graph")
+            .long("graph-no-axioms"))
+        .arg(Arg::with_name("graph-no-definitions")
+            .help("Leave definitions out of --graph")
+            .long("graph-no-definitions"))
+        .arg(Arg::with_name("graph-collapse-syntax")
+            .help("Show all syntax axioms as a single node in --graph")
+            .long("graph-collapse-syntax"))
+        .arg(Arg::with_name("graph-depth")
+            .help("Follow at most N levels of references in --graph")
+            .long("graph-depth")
+            .value_name("N")
+            .takes_value(true)
+            .validator(positive_integer))
+        .arg(Arg::with_name("latex")
+            .help("Write the statement LABEL, or the theorems FIRST:LAST, as LaTeX to FILE")
+            .long("latex")
+            .value_names(&["LABEL|FIRST:LAST", "FILE"]))
+        .arg(Arg::with_name("latex-document")
+            .help("Make the output of --latex a complete document instead of a fragment")
+            .long("latex-document"))
+        .arg(Arg::with_name("latex-proof")
+            .help("Include a table of the essential proof steps in --latex")
+            .long("latex-proof"))
+        .arg(Arg::with_name("baseline")
+            .help("Report changed proofs whose modification is discouraged, compared with \
+                   the database in FILE")
+            .long("baseline")
+            .value_name("FILE")
+            .takes_value(true))
+        .arg(Arg::with_name("diff")
+            .help("Report the assertions added, removed, renamed or changed since the database \
+                   in FILE")
+            .long("diff")
+            .value_name("FILE")
+            .takes_value(true))
+        .arg(Arg::with_name("diff-json")
+            .help("Print the report of --diff as JSON")
+            .long("diff-json"))
+        .arg(Arg::with_name("duplicates")
+            .help("Report assertions which are the same as an earlier one up to renaming of \
+                   variables")
+            .long("duplicates"))
+        .arg(Arg::with_name("restyle")
+            .help("Rewrite every proof in STYLE (compressed, normal, packed, explicit or \
+                   packed-explicit) and write the database to FILE")
+            .long("restyle")
+            .value_names(&["STYLE", "FILE"]))
         .arg(Arg::with_name("TEXT")
             .long("text")
             .help("Provide raw database content on the command line")
@@ -78,6 +318,7 @@ pub fn main() {
         timing: matches.is_present("timing"),
         trace_recalc: matches.is_present("trace-recalc"),
         incremental: matches.is_present("repeat"),
+        profile: matches.is_present("profile"),
         jobs: usize::from_str(matches.value_of("jobs").unwrap_or("1"))
     .expect("validator should check this") };
 
@@ -105,17 +346,201 @@ pub fn main() {
             types.push(DiagnosticClass::Verify);
         }
 
+        if matches.is_present("lint") {
+            types.push(DiagnosticClass::Lint);
+        }
+
+        if matches.is_present("html") {
+            types.push(DiagnosticClass::Typesetting);
+        }
+
         let mut lc = LineCache::default();
-        for notation in db.diag_notations(types) {
+        let notations = if matches.is_present("only") || matches.is_present("only-section") {
+            let addrs = selected_statements(&mut db,
+                                            matches.values_of_lossy("only").unwrap_or_default(),
+                                            matches.values_of_lossy("only-section")
+                                                .unwrap_or_default());
+            let mut notations = db.verify_only(&addrs);
+            if matches.is_present("lint") {
+                notations.extend(db.diag_notations(vec![DiagnosticClass::Lint]));
+            }
+            notations
+        } else {
+            db.diag_notations(types)
+        };
+        let symbols = symbol_map(&mut db,
+                                 matches.value_of("symbols"),
+                                 matches.is_present("unicode"));
+        let nset = db.name_result().clone();
+        for mut notation in notations {
+            if let Some(ref symbols) = symbols {
+                PrettyPrinter {
+                        nset: &nset,
+                        grammar: None,
+                        symbols,
+                        width: 0,
+                        indent: 0,
+                    }
+                    .notation(&mut notation);
+            }
             print_annotation(&mut lc, notation, matches.is_present("silent"));
         }
 
+        if let Some(labels) = matches.values_of_lossy("pretty") {
+            let width = matches.value_of("width")
+                .map_or(79, |width| usize::from_str(width).expect("validator should check this"));
+            for label in labels {
+                print_pretty(&mut db, &label, &symbols.clone().unwrap_or_default(), width);
+            }
+        }
+
+        if let Some(query) = matches.value_of("search") {
+            print_search(&mut db, query, matches.is_present("search-hyps"));
+        }
+
+        if let Some(count) = matches.value_of("profile") {
+            print_profile(&mut db, usize::from_str(count).expect("validator should check this"));
+        }
+
+        if let Some(file) = matches.value_of("baseline") {
+            let mut baseline = Database::new(database::DbOptions::default());
+            baseline.parse(file.to_owned(), Vec::new());
+            for notation in db.discouraged_changes(&mut baseline) {
+                print_annotation(&mut lc, notation, matches.is_present("silent"));
+            }
+        }
+
+        if matches.is_present("duplicates") {
+            for notation in db.duplicate_notations() {
+                print_annotation(&mut lc, notation, matches.is_present("silent"));
+            }
+        }
+
+        if let Some(file) = matches.value_of("diff") {
+            let mut old = Database::new(database::DbOptions::default());
+            old.parse(file.to_owned(), Vec::new());
+            let diff = db.diff(&mut old);
+            if matches.is_present("diff-json") {
+                diff.write_json(&mut io::stdout()).unwrap();
+            } else {
+                print!("{}", diff);
+            }
+        }
+
         if let Some(exps) = matches.values_of_lossy("export") {
             for file in exps {
                 db.export(file);
             }
         }
 
+        if let Some(sheets) = matches.values_of_lossy("import-mmp") {
+            for file in sheets {
+                import_mmp(&mut db, file, &mut lc, matches.is_present("silent"));
+            }
+        }
+
+        if let Some(ranges) = matches.values_of_lossy("minimize") {
+            for range in ranges {
+                minimize(&mut db, &range, &mut lc, matches.is_present("silent"));
+            }
+        }
+
+        if let Some(label) = matches.value_of("prove") {
+            let number = |name| {
+                matches.value_of(name)
+                    .map(|val| u64::from_str(val).expect("validator should check this"))
+            };
+            let defaults = ProveOptions::default();
+            let options = ProveOptions {
+                max_depth: number("prove-depth").map_or(defaults.max_depth, |n| n as usize),
+                max_steps: number("prove-steps").map_or(defaults.max_steps, |n| n as usize),
+                time_limit: number("prove-time").map(Duration::from_secs).or(defaults.time_limit),
+            };
+            prove(&mut db,
+                  label,
+                  matches.value_of("goal"),
+                  &options,
+                  &mut lc,
+                  matches.is_present("silent"));
+        }
+
+        if let Some(labels) = matches.values_of_lossy("dv") {
+            for label in labels {
+                dv_report(&mut db, &label, &mut lc, matches.is_present("silent"));
+            }
+        }
+
+        if let Some(labels) = matches.values_of_lossy("trace") {
+            for label in labels {
+                print_trace(&mut db, &label, &mut lc, matches.is_present("silent"));
+            }
+        }
+
+        if let Some(labels) = matches.values_of_lossy("show-proof") {
+            let style = ListingStyle::from_str(matches.value_of("listing").unwrap_or("tree"))
+                .expect("clap should check this");
+            for label in labels {
+                show_proof(&mut db,
+                           &label,
+                           style,
+                           matches.is_present("essential"),
+                           &mut lc,
+                           matches.is_present("silent"));
+            }
+        }
+
+        if let Some(dir) = matches.value_of("html") {
+            match db.write_html(dir, matches.is_present("alt-html")) {
+                Ok(count) => println!("{}: wrote {} pages", dir, count),
+                Err(err) => println!("{}: {}", dir, err),
+            }
+        }
+
+        if let Some(args) = matches.values_of_lossy("extract") {
+            extract(&mut db, &args[0], &args[1..]);
+        }
+
+        if let Some(args) = matches.values_of_lossy("rename") {
+            rename(&mut db, &args[0], &args[1], &mut lc, matches.is_present("silent"));
+        }
+
+        if let Some(args) = matches.values_of_lossy("graph") {
+            let options = GraphOptions {
+                omit_axioms: matches.is_present("graph-no-axioms"),
+                omit_definitions: matches.is_present("graph-no-definitions"),
+                collapse_syntax: matches.is_present("graph-collapse-syntax"),
+                max_depth: matches.value_of("graph-depth")
+                    .map(|depth| usize::from_str(depth).expect("validator should check this")),
+            };
+            let format = GraphFormat::from_str(matches.value_of("graph-format").unwrap_or("dot"))
+                .expect("clap should check this");
+            write_graph(&mut db, &args[0], &args[1], format, &options);
+        }
+
+        if let Some(args) = matches.values_of_lossy("latex") {
+            let options = LatexOptions {
+                standalone: matches.is_present("latex-document"),
+                proof: matches.is_present("latex-proof"),
+            };
+            export_latex(&mut db, &args[0], &args[1], options);
+        }
+
+        if let Some(args) = matches.values_of_lossy("restyle") {
+            match ProofStyle::from_str(&args[0]) {
+                Ok(style) => {
+                    match db.restyle_proofs(style, args[1].clone()) {
+                        Ok(notations) => {
+                            for notation in notations {
+                                print_annotation(&mut lc, notation, matches.is_present("silent"));
+                            }
+                        }
+                        Err(err) => println!("{}: {}", args[1], err),
+                    }
+                }
+                Err(err) => println!("{}", err),
+            }
+        }
+
         if matches.is_present("repeat") {
             let mut input = String::new();
             if io::stdin().read_line(&mut input).unwrap() == 0 {
@@ -131,6 +556,474 @@ pub fn main() {
     }
 }
 
+/// Prints the most expensive proofs found by the verifier, and the cost of
+/// each segment next to the estimate which was used to schedule it.
+pub fn print_profile(db: &mut Database, count: usize) {
+    let result = db.verify_result().clone();
+    let sset = db.parse_result().clone();
+    println!("Most expensive proofs:");
+    for proof in result.proof_profiles().into_iter().take(count) {
+        println!("{} {}us steps={} subst_bytes={} max_stack={}",
+                 as_str(sset.statement(proof.address).label()),
+                 proof.time.as_micros(),
+                 proof.steps,
+                 proof.subst_bytes,
+                 proof.max_stack);
+    }
+    println!("Segments:");
+    for segment in result.segment_profiles() {
+        let source = sset.source_info(segment.segment);
+        println!("{}@{} {}us estimate={} proofs={} steps={}",
+                 source.name,
+                 source.span.start,
+                 segment.time.as_micros(),
+                 segment.estimate,
+                 segment.proofs,
+                 segment.steps);
+    }
+}
+
+/// Collects the `$p` statements named by `--only` and `--only-section`,
+/// reporting any which cannot be found.
+pub fn selected_statements(db: &mut Database,
+                           ranges: Vec<String>,
+                           sections: Vec<String>)
+                           -> Vec<StatementAddress> {
+    let mut addrs = Vec::new();
+    for range in ranges {
+        let (first, last) = match range.find(':') {
+            Some(colon) => (&range[..colon], &range[colon + 1..]),
+            None => (&range[..], &range[..]),
+        };
+        match db.provable_range(first, last) {
+            Ok(range) => addrs.extend(range),
+            Err(err) => println!("{}", err),
+        }
+    }
+    for title in sections {
+        match db.outline_section(&title) {
+            Some(section) => addrs.extend(section),
+            None => println!("{}: no such outline section", title),
+        }
+    }
+    addrs
+}
+
+/// Checks a proof worksheet, printing its diagnostics and, if it is correct,
+/// the proof in normal style.
+pub fn import_mmp(db: &mut Database, file: String, lc: &mut LineCache, silent: bool) {
+    let text = match fs::read(&file) {
+        Ok(text) => text,
+        Err(err) => {
+            println!("{}: {}", file, err);
+            return;
+        }
+    };
+    let result = db.import_mmp(file, text);
+    for notation in result.notations() {
+        print_annotation(lc, notation, silent);
+    }
+    if let (Some(arr), Some(thm)) = (result.proof, result.theorem) {
+        let sset = db.parse_result().clone();
+        let nset = db.name_result().clone();
+        let scope = db.scope_result().clone();
+        println!("{} $={}",
+                 as_str(sset.statement(thm).label()),
+                 ProofTreePrinter {
+                     sset: &sset,
+                     nset: &nset,
+                     scope: &scope,
+                     thm_label: sset.statement(thm).label(),
+                     style: ProofStyle::Normal,
+                     arr: &arr,
+                     initial_chr: 2,
+                     indent: 6,
+                     line_width: 79,
+                 });
+    }
+}
+
+/// Minimizes the proofs of one theorem or a range of theorems, and prints the
+/// new proofs which are shorter.
+pub fn minimize(db: &mut Database, range: &str, lc: &mut LineCache, silent: bool) {
+    let options = MinimizeOptions::default();
+    let results = match range.find(':') {
+        Some(colon) => db.minimize_range(&range[..colon], &range[colon + 1..], &options),
+        None => db.minimize_range(range, range, &options),
+    };
+    let results = match results {
+        Ok(results) => results,
+        Err(err) => {
+            print_theorem_error(db, range, err, lc, silent);
+            return;
+        }
+    };
+    let sset = db.parse_result().clone();
+    let nset = db.name_result().clone();
+    let scope = db.scope_result().clone();
+    for (addr, result) in results {
+        let label = sset.statement(addr).label();
+        match result {
+            Ok(result) => {
+                if result.new_length < result.old_length {
+                    println!("{}: proof length decreased from {} to {}",
+                             as_str(label),
+                             result.old_length,
+                             result.new_length);
+                    println!("{} $={}",
+                             as_str(label),
+                             ProofTreePrinter {
+                                 sset: &sset,
+                                 nset: &nset,
+                                 scope: &scope,
+                                 thm_label: label,
+                                 style: ProofStyle::Compressed,
+                                 arr: &result.proof,
+                                 initial_chr: label.len() as u16 + 3,
+                                 indent: 6,
+                                 line_width: 79,
+                             });
+                }
+            }
+            Err(diag) => print_diagnostic(db, addr, diag, lc, silent),
+        }
+    }
+}
+
+/// Prints a diagnostic of the verifier for one statement, located in the
+/// source like those of a full verification.
+fn print_diagnostic(db: &mut Database,
+                    addr: StatementAddress,
+                    diag: Diagnostic,
+                    lc: &mut LineCache,
+                    silent: bool) {
+    let sset = db.parse_result().clone();
+    for notation in diag::to_annotations(&sset, vec![(addr, diag)]) {
+        print_annotation(lc, notation, silent);
+    }
+}
+
+/// Searches for a proof of a theorem, or of a goal in its scope, and prints it
+/// in normal style.
+pub fn prove(db: &mut Database,
+             label: &str,
+             goal: Option<&str>,
+             options: &ProveOptions,
+             lc: &mut LineCache,
+             silent: bool) {
+    let arr = match db.prove(label, goal, options) {
+        Ok(arr) => arr,
+        Err(err) => {
+            print_theorem_error(db, label, err, lc, silent);
+            return;
+        }
+    };
+    let sset = db.parse_result().clone();
+    let nset = db.name_result().clone();
+    let scope = db.scope_result().clone();
+    println!("{} $={}",
+             label,
+             ProofTreePrinter {
+                 sset: &sset,
+                 nset: &nset,
+                 scope: &scope,
+                 thm_label: label.as_bytes(),
+                 style: ProofStyle::Normal,
+                 arr: &arr,
+                 initial_chr: label.len() as u16 + 3,
+                 indent: 6,
+                 line_width: 79,
+             });
+}
+
+/// Prints the `$d` conditions which a proof needs but are not declared, and
+/// those which are declared but not needed.
+pub fn dv_report(db: &mut Database, label: &str, lc: &mut LineCache, silent: bool) {
+    match db.dv_report(label) {
+        Ok(report) => {
+            for (var1, var2) in report.missing {
+                println!("{}: missing $d {} {} $.", label, as_str(&var1), as_str(&var2));
+            }
+            for (var1, var2) in report.excess {
+                println!("{}: unneeded $d {} {} $.", label, as_str(&var1), as_str(&var2));
+            }
+        }
+        Err(err) => print_theorem_error(db, label, err, lc, silent),
+    }
+}
+
+/// Prints why an operation on the proof of `label`, or on a range of proofs,
+/// failed.
+fn print_theorem_error(db: &mut Database,
+                       label: &str,
+                       err: TheoremError,
+                       lc: &mut LineCache,
+                       silent: bool) {
+    match err {
+        TheoremError::NoSuchLabel(ref name) |
+        TheoremError::NotProvable(ref name) => println!("{}: no such theorem", name),
+        TheoremError::Verify(diag) => {
+            let addr = db.statement(label).expect("label was found").address();
+            print_diagnostic(db, addr, diag, lc, silent);
+        }
+        TheoremError::Prove(err) => println!("{}: {}", label, err),
+        err => println!("{}", err),
+    }
+}
+
+/// Prints the steps of a proof as recorded by `trace::Tracer`: the assertion
+/// and result of each step, then its substitution and `$d` checks.
+pub fn print_trace(db: &mut Database, label: &str, lc: &mut LineCache, silent: bool) {
+    let trace = match db.proof_trace(label) {
+        Ok(trace) => trace,
+        Err(err) => {
+            print_theorem_error(db, label, err, lc, silent);
+            return;
+        }
+    };
+    let sset = db.parse_result().clone();
+    for (index, step) in trace.iter().enumerate() {
+        println!("{} {} {}",
+                 index + 1,
+                 as_str(sset.statement(step.assertion).label()),
+                 as_str(&step.result));
+        for (var, expr) in &step.substitution {
+            println!("    {} := {}", as_str(var), as_str(expr));
+        }
+        for check in &step.dv_checks {
+            for (var1, var2) in &check.pairs {
+                println!("    $d {} {}", as_str(var1), as_str(var2));
+            }
+        }
+    }
+}
+
+/// Chooses the symbol replacements for pretty printing: a mapping file, the
+/// `althtmldef` definitions, or none to keep plain ASCII.
+pub fn symbol_map(db: &mut Database, file: Option<&str>, unicode: bool) -> Option<SymbolMap> {
+    match file {
+        Some(file) => {
+            match SymbolMap::read(file) {
+                Ok(symbols) => Some(symbols),
+                Err(err) => {
+                    println!("{}: {}", file, err);
+                    None
+                }
+            }
+        }
+        None if unicode => Some(SymbolMap::from_alt_html(db.typesetting_result())),
+        None => None,
+    }
+}
+
+/// Prints the hypotheses and assertion of a statement with
+/// `pretty::PrettyPrinter`.
+pub fn print_pretty(db: &mut Database, label: &str, symbols: &SymbolMap, width: usize) {
+    let stmt = match db.statement(label) {
+        Some(stmt) => stmt.address(),
+        None => {
+            println!("{}: no such statement", label);
+            return;
+        }
+    };
+    let sset = db.parse_result().clone();
+    let nset = db.name_result().clone();
+    let scope = db.scope_result().clone();
+    let grammar = db.grammar_result().clone();
+    let stmt = sset.statement(stmt);
+    let pretty = PrettyPrinter {
+        nset: &nset,
+        grammar: Some(&grammar),
+        symbols,
+        width,
+        indent: 2,
+    };
+    let mut lines = Vec::new();
+    if let Some(frame) = scope.get(stmt.label()) {
+        for hyp in &*frame.hypotheses {
+            if let Hyp::Essential(addr, _) = *hyp {
+                lines.push(sset.statement(addr));
+            }
+        }
+    }
+    lines.push(stmt);
+    let mut names = SymbolScope::new(&sset, &nset, stmt);
+    for line in lines {
+        let keyword = match line.statement_type() {
+            StatementType::Essential => "$e",
+            StatementType::Floating => "$f",
+            StatementType::Axiom => "$a",
+            _ => "$p",
+        };
+        let prefix = format!("{} {} ", as_str(line.label()), keyword);
+        let tokens: Vec<_> = (0..line.math_len()).map(|ix| line.math_at(ix).slice).collect();
+        println!("{}{}", prefix, pretty.format(&mut names, &tokens, prefix.len()));
+    }
+}
+
+/// Prints the assertions matching a search query, each with the hypothesis
+/// which matched, if any.
+pub fn print_search(db: &mut Database, query: &str, hypotheses: bool) {
+    let matches = match db.search(query, hypotheses) {
+        Ok(matches) => matches,
+        Err(err) => {
+            println!("{}", err);
+            return;
+        }
+    };
+    let sset = db.parse_result().clone();
+    let statement = |addr: StatementAddress| {
+        let stmt = sset.statement(addr);
+        let keyword = match stmt.statement_type() {
+            StatementType::Essential => "$e",
+            StatementType::Axiom => "$a",
+            _ => "$p",
+        };
+        let math: Vec<_> = stmt.math_iter().map(|tok| as_str(tok.slice)).collect();
+        format!("{} {} {}", as_str(stmt.label()), keyword, math.join(" "))
+    };
+    for found in &matches {
+        println!("{}", statement(found.address));
+        if let Some(hyp) = found.hypothesis {
+            println!("  {}", statement(hyp));
+        }
+    }
+    println!("{} matches", matches.len());
+}
+
+/// Prints a listing of the steps of a proof, with `proof::ProofListing`.
+pub fn show_proof(db: &mut Database,
+                  label: &str,
+                  style: ListingStyle,
+                  essential: bool,
+                  lc: &mut LineCache,
+                  silent: bool) {
+    let stmt = match db.statement(label) {
+        Some(stmt) if stmt.statement_type() == StatementType::Provable => stmt.address(),
+        _ => {
+            println!("{}: no such theorem", label);
+            return;
+        }
+    };
+    let sset = db.parse_result().clone();
+    let nset = db.name_result().clone();
+    let scope = db.scope_result().clone();
+    match ProofTreeArray::new(&sset, &nset, &scope, sset.statement(stmt)) {
+        Ok(arr) => {
+            print!("{}",
+                   ProofListing {
+                       sset: &sset,
+                       scope: &scope,
+                       arr: &arr,
+                       style,
+                       essential,
+                   })
+        }
+        Err(diag) => print_diagnostic(db, stmt, diag, lc, silent),
+    }
+}
+
+/// Writes the statements needed by some assertions to `file`.
+pub fn extract(db: &mut Database, file: &str, labels: &[String]) {
+    let mut roots = Vec::new();
+    for label in labels {
+        match db.statement(label) {
+            Some(stmt) if stmt.statement_type() == StatementType::Axiom ||
+                          stmt.statement_type() == StatementType::Provable => {
+                roots.push(stmt.address())
+            }
+            _ => {
+                println!("{}: no such assertion", label);
+                return;
+            }
+        }
+    }
+    match fs::File::create(file).and_then(|mut out| db.extract(&roots, &mut out)) {
+        Ok(count) => println!("{}: wrote {} assertions", file, count),
+        Err(err) => println!("{}: {}", file, err),
+    }
+}
+
+/// Renames a statement and its references, and writes the changed files if
+/// the renamed database has no errors.
+pub fn rename(db: &mut Database, old: &str, new: &str, lc: &mut LineCache, silent: bool) {
+    let result = match db.rename_label(old, new) {
+        Ok(result) => result,
+        Err(err) => {
+            if let RenameError::DoesNotVerify(_, ref errors) = err {
+                for &(addr, ref diag) in errors {
+                    print_diagnostic(db, addr, diag.clone(), lc, silent);
+                }
+            }
+            println!("{}", err);
+            return;
+        }
+    };
+    for notation in result.diagnostics {
+        print_annotation(lc, notation, silent);
+    }
+    match db.write_sources(&result.files) {
+        Ok(()) => {
+            println!("{}: renamed to {}, {} references in {}",
+                     old,
+                     new,
+                     result.references,
+                     result.files.join(", "))
+        }
+        Err(err) => println!("{}: {}", old, err),
+    }
+}
+
+/// Writes the dependency graph of a statement, or of the theorems of an
+/// outline section, to `file`.
+pub fn write_graph(db: &mut Database,
+                   root: &str,
+                   file: &str,
+                   format: GraphFormat,
+                   options: &GraphOptions) {
+    let roots = match db.statement(root) {
+        Some(stmt) => vec![stmt.address()],
+        None => {
+            match db.outline_section(root) {
+                Some(section) => section,
+                None => {
+                    println!("{}: no such statement or outline section", root);
+                    return;
+                }
+            }
+        }
+    };
+    let graph = db.dependency_graph(&roots, options);
+    match fs::File::create(file).and_then(|mut out| graph.write(format, &mut out)) {
+        Ok(()) => {
+            println!("{}: wrote {} nodes and {} edges",
+                     file,
+                     graph.nodes().len(),
+                     graph.edges().len())
+        }
+        Err(err) => println!("{}: {}", file, err),
+    }
+}
+
+/// Writes a statement, or a range of theorems, as LaTeX to `file`.
+pub fn export_latex(db: &mut Database, range: &str, file: &str, options: LatexOptions) {
+    // a single label may also name an axiom
+    let addrs = match db.statement(range) {
+        Some(stmt) => vec![stmt.address()],
+        None => selected_statements(db, vec![range.to_owned()], Vec::new()),
+    };
+    if addrs.is_empty() {
+        return;
+    }
+    // render first, so that a missing latexdef leaves no file behind
+    let mut text = Vec::new();
+    match db.export_latex(&addrs, options, &mut text)
+        .and_then(|()| fs::write(file, text).map_err(ExportError::Io)) {
+        Ok(()) => println!("{}: wrote {} statements", file, addrs.len()),
+        Err(err) => println!("{}: {}", file, err),
+    }
+}
+
 /// todo doc
 pub fn print_annotation(lc: &mut LineCache, ann: Notation, silent: bool) {
     let mut args = String::new();
@@ -0,0 +1,571 @@
+//! Proof shortening, after the `MINIMIZE_WITH` command of metamath.exe.
+//!
+//! The minimizer works on the `ProofTreeArray` of a verified proof.  Each
+//! logical step is the root of a subtree; for each one, in turn, we look for an
+//! earlier assertion whose conclusion matches the step and whose `$e`
+//! hypotheses match steps which are already proved elsewhere in the proof.
+//! Replacing the subtree by a single application of that assertion makes the
+//! steps which only served the old subtree unreachable.  A replacement is kept
+//! if it makes the compressed proof shorter, and the search starts over until
+//! no step can be improved.
+//!
+//! Matching is done on syntax trees: the conclusion of each step is assembled
+//! from the syntax derivations which the proof already contains, so no math
+//! strings need to be parsed except for the frames of the assertions.  A
+//! replacement must satisfy the `$d` conditions of the new assertion using only
+//! the `$d` statements in scope for the theorem, and assertions tagged `(New
+//! usage is discouraged.)` are never used.
+
+use crate::diag::Diagnostic;
+use crate::grammar::Formula;
+use crate::grammar::FramePattern;
+use crate::grammar::Grammar;
+use crate::nameck::Atom;
+use crate::nameck::Nameset;
+use crate::parser::Comparer;
+use crate::parser::StatementAddress;
+use crate::parser::StatementRef;
+use crate::parser::StatementType;
+use crate::proof::ProofStyle;
+use crate::proof::ProofTreeArray;
+use crate::proof::ProofTreePrinter;
+use crate::scopeck::Frame;
+use crate::scopeck::Hyp;
+use crate::scopeck::ScopeResult;
+use crate::segment_set::SegmentSet;
+use crate::unify::Subst;
+use crate::unify::Term;
+use crate::util::HashMap;
+use crate::util::HashSet;
+use crate::util::new_map;
+use crate::util::new_set;
+use crate::verify::ProofBuilder;
+use std::cmp::Ordering;
+
+/// Settings for a minimization run.
+#[derive(Copy,Clone,Debug,Default)]
+pub struct MinimizeOptions {
+    /// Also try `$a` statements, including definitions, as replacements; by
+    /// default only theorems are used, so that a shorter proof never depends
+    /// on a new axiom.
+    pub allow_axioms: bool,
+    /// Minimize theorems tagged `(Proof modification is discouraged.)`, which
+    /// are otherwise left unchanged.
+    pub override_discouraged: bool,
+}
+
+/// The outcome of minimizing one proof.
+#[derive(Debug)]
+pub struct MinimizeResult {
+    /// The theorem whose proof was minimized.
+    pub address: StatementAddress,
+    /// Length of the original proof in compressed form.
+    pub old_length: usize,
+    /// Length of the new proof in compressed form; equal to `old_length` if no
+    /// improvement was found.
+    pub new_length: usize,
+    /// The new proof.
+    pub proof: ProofTreeArray,
+}
+
+#[derive(Debug)]
+struct Candidate {
+    address: StatementAddress,
+    pattern: FramePattern,
+}
+
+/// The assertions which may be used by the minimizer, indexed by the syntax
+/// axiom at the root of their conclusions.
+///
+/// Collecting these requires parsing the frame of every assertion in the
+/// database, so a single `Candidates` should be shared when minimizing many
+/// proofs.
+#[derive(Debug,Default)]
+pub struct Candidates {
+    list: Vec<Candidate>,
+    by_root: HashMap<StatementAddress, Vec<usize>>,
+    /// Candidates whose conclusion is a bare variable, like `ax-mp`, and
+    /// which must be tried for every step.
+    open: Vec<usize>,
+}
+
+impl Candidates {
+    /// Collects the logical assertions of a database which are not tagged as
+    /// discouraged, and which can be parsed by the grammar.
+    pub fn new(sset: &SegmentSet,
+               nset: &Nameset,
+               scope: &ScopeResult,
+               grammar: &Grammar,
+               options: &MinimizeOptions)
+               -> Candidates {
+        let mut cands = Candidates {
+            by_root: new_map(),
+            ..Candidates::default()
+        };
+        for sref in sset.segments() {
+            for stmt in sref {
+                match stmt.statement_type() {
+                    StatementType::Provable => {}
+                    StatementType::Axiom if options.allow_axioms => {}
+                    _ => continue,
+                }
+                let frame = match scope.get(stmt.label()) {
+                    Some(frame) if frame.valid.start == stmt.address() => frame,
+                    _ => continue,
+                };
+                if frame.target.typecode != grammar.provable_type() || stmt.usage_discouraged() {
+                    continue;
+                }
+                let pattern = match grammar.frame_pattern(nset, frame) {
+                    Some(pattern) => pattern,
+                    None => continue,
+                };
+                let index = cands.list.len();
+                match pattern.target.term {
+                    Term::App(root, _) => cands.by_root.entry(root).or_default().push(index),
+                    Term::Meta(_) => cands.open.push(index),
+                }
+                cands.list.push(Candidate {
+                    address: stmt.address(),
+                    pattern,
+                });
+            }
+        }
+        cands
+    }
+
+    /// Iterates over the candidates whose conclusion can unify with a term:
+    /// those with the same syntax axiom at the root, followed by those whose
+    /// conclusion is a bare variable.
+    pub fn matching<'a>(&'a self,
+                        term: &Term)
+                        -> impl Iterator<Item = (StatementAddress, &'a FramePattern)> + 'a {
+        let roots = match *term {
+            Term::App(root, _) => self.by_root.get(&root).map_or(&[][..], |v| &v[..]),
+            Term::Meta(_) => &[][..],
+        };
+        roots.iter().chain(&self.open).map(move |&cix| {
+            let cand = &self.list[cix];
+            (cand.address, &cand.pattern)
+        })
+    }
+}
+
+/// A hypothesis of a replacement step: either the syntax derivation of the
+/// value of a variable, or an existing step of the proof.
+enum Child {
+    Syntax(Term),
+    Step(usize),
+}
+
+struct Minimizer<'a> {
+    sset: &'a SegmentSet,
+    nset: &'a Nameset,
+    scope: &'a ScopeResult,
+    grammar: &'a Grammar,
+    cands: &'a Candidates,
+    stmt: StatementRef<'a>,
+    frame: &'a Frame,
+    statement: FramePattern,
+    /// Pairs of variables which may be treated as disjoint in this proof.
+    allowed_dv: HashSet<(Atom, Atom)>,
+    /// Parsed frames of the syntax theorems used by the proof, which are not
+    /// candidates.
+    patterns: HashMap<StatementAddress, Option<FramePattern>>,
+}
+
+impl<'a> Minimizer<'a> {
+    fn pattern(&mut self, addr: StatementAddress) -> Option<&FramePattern> {
+        let (grammar, nset, scope, sset) = (self.grammar, self.nset, self.scope, self.sset);
+        self.patterns
+            .entry(addr)
+            .or_insert_with(|| {
+                let frame = scope.get(sset.statement(addr).label())?;
+                grammar.frame_pattern(nset, frame)
+            })
+            .as_ref()
+    }
+
+    /// Computes the conclusion of every step as a syntax tree, or `None` where
+    /// a frame could not be parsed.
+    fn step_formulas(&mut self, arr: &ProofTreeArray) -> Vec<Option<Formula>> {
+        let mut out: Vec<Option<Formula>> = Vec::with_capacity(arr.trees.len());
+        for tree in &arr.trees {
+            let stmt = self.sset.statement(tree.address);
+            let typecode = match self.nset.lookup_symbol(&stmt.math_at(0)) {
+                Some(symbol) => symbol.atom,
+                None => {
+                    out.push(None);
+                    continue;
+                }
+            };
+            let term = match stmt.statement_type() {
+                StatementType::Floating => Some(Term::App(tree.address, vec![])),
+                StatementType::Essential => {
+                    self.frame
+                        .hypotheses
+                        .iter()
+                        .position(|hyp| hyp.address() == tree.address)
+                        .and_then(|ix| self.statement.hyps[ix].as_ref())
+                        .map(|formula| formula.term.clone())
+                }
+                _ if self.grammar.typecode_of(tree.address).is_some() => {
+                    tree.children
+                        .iter()
+                        .map(|&child| out[child].as_ref().map(|f| f.term.clone()))
+                        .collect::<Option<Vec<Term>>>()
+                        .map(|children| Term::App(tree.address, children))
+                }
+                _ => self.instantiate(&out, tree.address, &tree.children),
+            };
+            out.push(term.map(|term| Formula { typecode, term }));
+        }
+        out
+    }
+
+    // the conclusion of an assertion applied to the given steps
+    fn instantiate(&mut self,
+                   formulas: &[Option<Formula>],
+                   addr: StatementAddress,
+                   children: &[usize])
+                   -> Option<Term> {
+        let frame = self.scope.get(self.sset.statement(addr).label())?;
+        let mut values = vec![None; frame.mandatory_count];
+        for (hyp, &child) in frame.hypotheses.iter().zip(children) {
+            if let Hyp::Floating(_, index, _) = *hyp {
+                values[index] = Some(formulas[child].as_ref()?.term.clone());
+            }
+        }
+        let values = values.into_iter().collect::<Option<Vec<Term>>>()?;
+        Some(self.pattern(addr)?.target.term.substitute(&values))
+    }
+
+    fn check_dv(&self, frame: &Frame, subst: &Subst) -> bool {
+        frame.mandatory_dv.iter().all(|&(left, right)| {
+            let lvars = self.grammar.variables(&subst.resolve(&Term::Meta(left)));
+            let rvars = self.grammar.variables(&subst.resolve(&Term::Meta(right)));
+            lvars.iter().all(|&x| {
+                rvars.iter().all(|&y| x != y && self.allowed_dv.contains(&(x, y)))
+            })
+        })
+    }
+
+    // match the essential hypotheses, starting with `ess[0]`, against proved steps
+    fn fill_hyps(&self,
+                 ess: &[&Formula],
+                 formulas: &[Option<Formula>],
+                 eligible: &[usize],
+                 subst: &mut Subst,
+                 chosen: &mut Vec<usize>)
+                 -> bool {
+        let hyp = match ess.first() {
+            Some(hyp) => hyp,
+            None => return true,
+        };
+        for &step in eligible {
+            let formula = formulas[step].as_ref().unwrap();
+            if formula.typecode != hyp.typecode {
+                continue;
+            }
+            let mark = subst.mark();
+            if subst.unify(self.grammar, &hyp.term, &formula.term) {
+                chosen.push(step);
+                if self.fill_hyps(&ess[1..], formulas, eligible, subst, chosen) {
+                    return true;
+                }
+                chosen.pop();
+                subst.undo(mark);
+            }
+        }
+        false
+    }
+
+    // copy the proof into a new array, replacing one step
+    fn rebuild(&self,
+               old: &ProofTreeArray,
+               new: &mut ProofTreeArray,
+               built: &mut Vec<Option<usize>>,
+               replace: &(usize, StatementAddress, Vec<Child>),
+               ix: usize)
+               -> usize {
+        if let Some(done) = built[ix] {
+            return done;
+        }
+        let (addr, children) = if ix == replace.0 {
+            (replace.1,
+             replace.2
+                .iter()
+                .map(|child| match *child {
+                    Child::Syntax(ref term) => self.grammar.build_tree(self.nset, new, term),
+                    Child::Step(step) => self.rebuild(old, new, built, replace, step),
+                })
+                .collect())
+        } else {
+            (old.trees[ix].address,
+             old.trees[ix]
+                .children
+                .iter()
+                .map(|&child| self.rebuild(old, new, built, replace, child))
+                .collect())
+        };
+        let mut pool = Vec::new();
+        for tok in old.exprs[ix].split(|&c| c == b' ').filter(|tok| !tok.is_empty()) {
+            pool.extend_from_slice(tok);
+            *pool.last_mut().unwrap() |= 0x80;
+        }
+        let done = new.build(addr, children, &pool, 0..pool.len());
+        built[ix] = Some(done);
+        done
+    }
+
+    /// Finds the best replacement for one step, returning the new proof and
+    /// its length if it is shorter than `length`.
+    fn improve_step(&self,
+                    arr: &ProofTreeArray,
+                    formulas: &[Option<Formula>],
+                    step: usize,
+                    length: usize)
+                    -> Option<(ProofTreeArray, usize)> {
+        let goal = formulas[step].as_ref()?;
+        let current = arr.trees[step].address;
+        match self.sset.statement(current).statement_type() {
+            StatementType::Axiom | StatementType::Provable => {}
+            _ => return None,
+        }
+        if goal.typecode != self.grammar.provable_type() {
+            return None;
+        }
+
+        // steps which do not use this one can prove hypotheses of its
+        // replacement; children always precede their parents
+        let mut depends = vec![false; arr.trees.len()];
+        let mut eligible = Vec::new();
+        for (ix, tree) in arr.trees.iter().enumerate() {
+            depends[ix] = ix == step || tree.children.iter().any(|&child| depends[child]);
+            if !depends[ix] &&
+               formulas[ix].as_ref().is_some_and(|f| f.typecode == goal.typecode) {
+                eligible.push(ix);
+            }
+        }
+
+        let mut best: Option<(ProofTreeArray, usize)> = None;
+        for (address, pattern) in self.cands.matching(&goal.term) {
+            if address == current ||
+               self.sset.order.cmp(&address, &self.stmt.address()) != Ordering::Less {
+                continue;
+            }
+            let mut subst = Subst::new();
+            for &typecode in &pattern.var_types {
+                subst.fresh(typecode);
+            }
+            if pattern.target.typecode != goal.typecode ||
+               !subst.unify(self.grammar, &pattern.target.term, &goal.term) {
+                continue;
+            }
+            let ess: Vec<&Formula> = pattern.essentials().map(|(_, f)| f).collect();
+            let mut chosen = Vec::new();
+            if !self.fill_hyps(&ess, formulas, &eligible, &mut subst, &mut chosen) {
+                continue;
+            }
+            if (0..subst.len()).any(|var| !subst.resolve(&Term::Meta(var)).is_ground()) {
+                continue;
+            }
+            let frame = self.scope.get(self.sset.statement(address).label()).unwrap();
+            if !self.check_dv(frame, &subst) {
+                continue;
+            }
+
+            let mut chosen = chosen.into_iter();
+            let children = frame.hypotheses
+                .iter()
+                .map(|hyp| match *hyp {
+                    Hyp::Floating(_, index, _) => {
+                        Child::Syntax(subst.resolve(&Term::Meta(index)))
+                    }
+                    Hyp::Essential(..) => Child::Step(chosen.next().unwrap()),
+                })
+                .collect();
+            let replace = (step, address, children);
+            let mut new = ProofTreeArray::default();
+            let mut built = vec![None; arr.trees.len()];
+            let qed = self.rebuild(arr, &mut new, &mut built, &replace, arr.qed);
+            new.set_qed(qed);
+            let new_length = compressed_length(self.sset, self.nset, self.scope, self.stmt, &new);
+            if new_length < best.as_ref().map_or(length, |b| b.1) {
+                best = Some((new, new_length));
+            }
+        }
+        best
+    }
+}
+
+/// Measures a proof by the number of non-blank characters in its compressed
+/// form, not counting the closing `$.`.
+pub fn compressed_length(sset: &SegmentSet,
+                         nset: &Nameset,
+                         scope: &ScopeResult,
+                         stmt: StatementRef,
+                         arr: &ProofTreeArray)
+                         -> usize {
+    let text = ProofTreePrinter {
+            sset,
+            nset,
+            scope,
+            thm_label: stmt.label(),
+            style: ProofStyle::Compressed,
+            arr,
+            initial_chr: 0,
+            indent: 1,
+            line_width: 79,
+        }
+        .to_string();
+    text.bytes().filter(|c| !c.is_ascii_whitespace()).count() - 2
+}
+
+/// Shortens the proof of a `$p` statement using the given candidates.
+///
+/// Returns the verification error if the existing proof is not valid.
+pub fn minimize(sset: &SegmentSet,
+                nset: &Nameset,
+                scope: &ScopeResult,
+                grammar: &Grammar,
+                cands: &Candidates,
+                stmt: StatementRef,
+                options: &MinimizeOptions)
+                -> Result<MinimizeResult, Diagnostic> {
+    let mut arr = ProofTreeArray::new(sset, nset, scope, stmt)?;
+    let old_length = compressed_length(sset, nset, scope, stmt, &arr);
+    let mut length = old_length;
+    let frame = scope.get(stmt.label()).unwrap();
+    let statement = match grammar.frame_statement(nset, frame) {
+        Some(statement) if options.override_discouraged ||
+                           !stmt.proof_modification_discouraged() => statement,
+        _ => {
+            return Ok(MinimizeResult {
+                address: stmt.address(),
+                old_length,
+                new_length: length,
+                proof: arr,
+            })
+        }
+    };
+
+    let mut allowed_dv = new_set();
+    for (index, bits) in frame.optional_dv.iter().enumerate() {
+        for other in bits {
+            allowed_dv.insert((frame.var_list[index], frame.var_list[other]));
+        }
+    }
+    let mut min = Minimizer {
+        sset,
+        nset,
+        scope,
+        grammar,
+        cands,
+        stmt,
+        frame,
+        statement,
+        allowed_dv,
+        patterns: new_map(),
+    };
+
+    // every accepted change shortens the proof, so this terminates
+    'improve: loop {
+        let formulas = min.step_formulas(&arr);
+        for step in (0..arr.trees.len()).rev() {
+            if let Some((new, new_length)) = min.improve_step(&arr, &formulas, step, length) {
+                arr = new;
+                length = new_length;
+                continue 'improve;
+            }
+        }
+        break;
+    }
+
+    Ok(MinimizeResult {
+        address: stmt.address(),
+        old_length,
+        new_length: length,
+        proof: arr,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::database::Database;
+    use crate::database::TheoremError;
+    use crate::minimize::MinimizeOptions;
+    use crate::proof::ProofStyle;
+    use crate::proof::ProofTreeArray;
+    use crate::test_util::database;
+    use crate::test_util::A1I;
+    use crate::test_util::PROP;
+
+    // long and kept can be proved by a1i, or by the discouraged a1d
+    const THEOREMS: &str = "
+        ${
+            a1d.1 $e |- ph $.
+            $( (New usage is discouraged.) $)
+            a1d $p |- ( ps -> ph ) $= wph wps wph wi a1d.1 wph wps ax-1 ax-mp $.
+        $}
+        ${
+            long.1 $e |- ch $.
+            long $p |- ( ps -> ch ) $= wch wps wch wi long.1 wch wps ax-1 ax-mp $.
+        $}
+        ${
+            kept.1 $e |- ch $.
+            $( Do not touch.  (Proof modification is
+               discouraged.) $)
+            kept $p |- ( ps -> ch ) $= wch wps wch wi kept.1 wch wps ax-1 ax-mp $.
+        $}
+    ";
+
+    fn mkdb() -> Database {
+        database(&[PROP, A1I, THEOREMS].concat())
+    }
+
+    #[test]
+    fn test_minimize() {
+        let mut db = mkdb();
+        let result = db.minimize("long", &MinimizeOptions::default()).unwrap();
+        assert!(result.new_length < result.old_length);
+        // the whole proof becomes an application of a1i, not of the
+        // discouraged a1d
+        let a1i = db.statement("a1i").unwrap().address();
+        assert_eq!(result.proof.trees[result.proof.qed].address, a1i);
+        assert_eq!(db.replace_proof("long", &result.proof, ProofStyle::Compressed), Ok(()));
+        let sset = db.parse_result().clone();
+        let nset = db.name_result().clone();
+        let scope = db.scope_result().clone();
+        let stmt = db.statement("long").unwrap();
+        let arr = ProofTreeArray::new(&sset, &nset, &scope, stmt).unwrap();
+        assert_eq!(arr.trees.len(), result.proof.trees.len());
+    }
+
+    #[test]
+    fn test_minimize_discouraged() {
+        let mut db = mkdb();
+        let result = db.minimize("kept", &MinimizeOptions::default()).unwrap();
+        assert_eq!(result.new_length, result.old_length);
+        let options = MinimizeOptions {
+            override_discouraged: true,
+            ..MinimizeOptions::default()
+        };
+        let result = db.minimize("kept", &options).unwrap();
+        assert!(result.new_length < result.old_length);
+    }
+
+    #[test]
+    fn test_minimize_not_provable() {
+        let mut db = mkdb();
+        let options = MinimizeOptions::default();
+        assert_eq!(db.minimize("ax-1", &options).err(),
+                   Some(TheoremError::NotProvable("ax-1".to_owned())));
+        assert_eq!(db.minimize("wph", &options).err(),
+                   Some(TheoremError::NotProvable("wph".to_owned())));
+        assert_eq!(db.minimize("nosuch:id", &options).err(),
+                   Some(TheoremError::NoSuchLabel("nosuch:id".to_owned())));
+        assert_eq!(db.minimize_range("a1i", "nosuch", &options).err(),
+                   Some(TheoremError::NoSuchLabel("nosuch".to_owned())));
+    }
+}
@@ -0,0 +1,849 @@
+//! Import of mmj2 proof worksheets.
+//!
+//! A worksheet, in the format written by `export::export_mmp`, lists the
+//! logical steps of a proof one per line:
+//!
+//! ```text
+//! $( <MM> <PROOF_ASST> THEOREM=syl  LOC_AFTER=?
+//!
+//! * Comment, continued on indented lines.
+//!
+//! h1::syl.1          |- ( ph -> ps )
+//! h2::syl.2          |- ( ps -> ch )
+//! 3:2:a1i            |- ( ph -> ( ps -> ch ) )
+//! qed:1,3:mpd        |- ( ph -> ch )
+//!
+//! $= ... $.
+//!
+//! $)
+//! ```
+//!
+//! Each step line starts with a `step:hyps:ref` field naming the step, the
+//! earlier steps which satisfy the `$e` hypotheses of the referenced
+//! assertion, and the assertion itself; lines starting with whitespace
+//! continue the previous line.  The reference may be left empty, and the
+//! hypotheses may be left out or given as `?`; they are then filled in by
+//! searching for an assertion or earlier step which unifies.  The formula may
+//! likewise be omitted, or contain work variables such as `&W1`, to be solved
+//! for.  Syntax steps do not appear in the worksheet; they are recovered by
+//! parsing the formulas with the database grammar (see `grammar`).
+//!
+//! The `$=` section of a worksheet is ignored, since the proof is rebuilt from
+//! the steps.
+
+use crate::diag;
+use crate::diag::Diagnostic;
+use crate::diag::Diagnostic::*;
+use crate::diag::Notation;
+use crate::grammar::Formula;
+use crate::grammar::FramePattern;
+use crate::grammar::Grammar;
+use crate::grammar::Sym;
+use crate::grammar::SymbolScope;
+use crate::nameck::Atom;
+use crate::nameck::Nameset;
+use crate::parser::copy_token;
+use crate::parser::Comparer;
+use crate::parser::Span;
+use crate::parser::StatementAddress;
+use crate::parser::StatementRef;
+use crate::parser::StatementType;
+use crate::parser::Token;
+use crate::proof::ProofTreeArray;
+use crate::scopeck::Frame;
+use crate::scopeck::Hyp;
+use crate::scopeck::ScopeResult;
+use crate::segment_set::SegmentSet;
+use crate::segment_set::SourceInfo;
+use crate::unify::MetaVar;
+use crate::unify::Subst;
+use crate::unify::Term;
+use crate::util::HashMap;
+use crate::util::HashSet;
+use crate::util::new_map;
+use crate::util::new_set;
+use crate::verify::ProofBuilder;
+use std::cmp::Ordering;
+use std::ops::Range;
+use std::rc::Rc;
+use std::sync::Arc;
+
+/// The result of checking a proof worksheet.
+#[derive(Debug)]
+pub struct WorksheetResult {
+    /// The worksheet text, which diagnostic spans refer to.
+    pub source: Arc<SourceInfo>,
+    /// The `$p` statement named in the worksheet header, if it exists.
+    pub theorem: Option<StatementAddress>,
+    /// The reconstructed proof, if every step could be justified.
+    pub proof: Option<ProofTreeArray>,
+    /// Problems found in the worksheet.
+    pub diagnostics: Vec<(Span, Diagnostic)>,
+}
+
+impl WorksheetResult {
+    /// Converts the diagnostics to human-readable form.
+    pub fn notations(&self) -> Vec<Notation> {
+        diag::to_worksheet_annotations(&self.source, self.diagnostics.clone())
+    }
+}
+
+/// A step line, before any lookups.
+#[derive(Debug)]
+struct StepLine {
+    /// Span of the `step:hyps:ref` field.
+    span: Span,
+    name: Token,
+    is_hyp: bool,
+    /// Hypothesis step names; `None` for `?`.
+    hyps: Vec<Option<Token>>,
+    label: Option<Token>,
+    formula: Vec<(Span, Token)>,
+}
+
+#[derive(Debug,Default)]
+struct Worksheet {
+    header: Span,
+    theorem: Option<Token>,
+    steps: Vec<StepLine>,
+    disjoint: Vec<Vec<(Span, Token)>>,
+}
+
+fn is_space(chr: u8) -> bool {
+    matches!(chr, b' ' | b'\t' | b'\r' | b'\n' | b'\x0c')
+}
+
+fn tokenize(text: &[u8], range: Range<usize>) -> Vec<(Span, Token)> {
+    let mut out = Vec::new();
+    let mut pos = range.start;
+    while pos < range.end {
+        if is_space(text[pos]) {
+            pos += 1;
+            continue;
+        }
+        let start = pos;
+        while pos < range.end && !is_space(text[pos]) {
+            pos += 1;
+        }
+        out.push((Span::new(start, pos), copy_token(&text[start..pos])));
+    }
+    out
+}
+
+fn split_field(field: &[u8]) -> Option<(&[u8], &[u8], &[u8])> {
+    let mut parts = field.split(|&c| c == b':');
+    let name = parts.next()?;
+    let hyps = parts.next()?;
+    let label = parts.next()?;
+    if parts.next().is_some() || name.is_empty() {
+        return None;
+    }
+    Some((name, hyps, label))
+}
+
+fn parse_worksheet(text: &[u8], diags: &mut Vec<(Span, Diagnostic)>) -> Worksheet {
+    // group lines into entries; indented lines continue the previous entry
+    let mut entries: Vec<Range<usize>> = Vec::new();
+    let mut pos = 0;
+    while pos < text.len() {
+        let end = text[pos..].iter().position(|&c| c == b'\n').map_or(text.len(), |n| pos + n + 1);
+        if !is_space(text[pos]) {
+            entries.push(pos..end);
+        } else if let Some(last) = entries.last_mut() {
+            last.end = end;
+        }
+        pos = end;
+    }
+
+    let mut sheet = Worksheet::default();
+    for range in entries {
+        let mut tokens = tokenize(text, range);
+        let (span, first) = tokens[0].clone();
+        if first.starts_with(b"$)") {
+            break;
+        } else if first.starts_with(b"$(") {
+            sheet.header = span;
+            sheet.theorem = tokens.iter()
+                .find(|tok| tok.1.starts_with(b"THEOREM="))
+                .map(|tok| copy_token(&tok.1[8..]));
+        } else if &*first == b"$d" {
+            tokens.remove(0);
+            sheet.disjoint.push(tokens);
+        } else if first.starts_with(b"*") || first.starts_with(b"$=") {
+            // comments and the old proof are not needed
+        } else {
+            match split_field(&first) {
+                None => diags.push((span, MmpBadLine)),
+                Some((name, hyps, label)) => {
+                    let is_hyp = name.len() > 1 && (name[0] == b'h' || name[0] == b'H');
+                    let name = if is_hyp { &name[1..] } else { name };
+                    let hyps = if hyps.is_empty() {
+                        Vec::new()
+                    } else {
+                        hyps.split(|&c| c == b',')
+                            .map(|h| if h.is_empty() || h == b"?" { None } else { Some(copy_token(h)) })
+                            .collect()
+                    };
+                    tokens.remove(0);
+                    sheet.steps.push(StepLine {
+                        span,
+                        name: copy_token(name),
+                        is_hyp,
+                        hyps,
+                        label: if label.is_empty() { None } else { Some(copy_token(label)) },
+                        formula: tokens,
+                    });
+                }
+            }
+        }
+    }
+    sheet
+}
+
+/// A step which has been justified, or at least parsed.
+struct Step {
+    formula: Formula,
+    /// The assertion or `$e` hypothesis used.
+    label: StatementAddress,
+    /// First metavariable of the renamed assertion frame.
+    base: MetaVar,
+    /// Steps used for the assertion's `$e` hypotheses, in frame order.
+    hyps: Vec<usize>,
+}
+
+struct Checker<'a> {
+    sset: &'a SegmentSet,
+    nset: &'a Nameset,
+    scope: &'a ScopeResult,
+    grammar: &'a Grammar,
+    sheet: &'a Worksheet,
+    thm: StatementRef<'a>,
+    frame: &'a Frame,
+    statement: FramePattern,
+    symbols: SymbolScope<'a>,
+    subst: Subst,
+    work_vars: HashMap<Token, MetaVar>,
+    patterns: HashMap<StatementAddress, Option<Rc<FramePattern>>>,
+    candidates: Option<Rc<Vec<StatementAddress>>>,
+    names: HashMap<Token, usize>,
+    steps: Vec<Option<Step>>,
+    diags: Vec<(Span, Diagnostic)>,
+}
+
+impl<'a> Checker<'a> {
+    /// Finds the frame of an assertion, if it is usable in this proof.
+    fn assertion(&self, addr: StatementAddress) -> Option<&'a Frame> {
+        let stmt = self.sset.statement(addr);
+        match stmt.statement_type() {
+            StatementType::Axiom | StatementType::Provable => {}
+            _ => return None,
+        }
+        if self.sset.order.cmp(&addr, &self.thm.address()) != Ordering::Less {
+            return None;
+        }
+        self.scope.get(stmt.label()).filter(|frame| frame.valid.start == addr)
+    }
+
+    fn pattern(&mut self, addr: StatementAddress) -> Option<Rc<FramePattern>> {
+        if let Some(pattern) = self.patterns.get(&addr) {
+            return pattern.clone();
+        }
+        let pattern = self.assertion(addr)
+            .and_then(|frame| self.grammar.frame_pattern(self.nset, frame))
+            .map(Rc::new);
+        self.patterns.insert(addr, pattern.clone());
+        pattern
+    }
+
+    /// All logical assertions preceding the theorem, in database order.
+    fn candidates(&mut self) -> Rc<Vec<StatementAddress>> {
+        if self.candidates.is_none() {
+            let mut out = Vec::new();
+            'segs: for sref in self.sset.segments() {
+                for stmt in sref {
+                    if self.sset.order.cmp(&stmt.address(), &self.thm.address()) !=
+                       Ordering::Less {
+                        break 'segs;
+                    }
+                    if let Some(frame) = self.assertion(stmt.address()) {
+                        if frame.target.typecode == self.grammar.provable_type() {
+                            out.push(stmt.address());
+                        }
+                    }
+                }
+            }
+            self.candidates = Some(Rc::new(out));
+        }
+        self.candidates.clone().unwrap()
+    }
+
+    fn work_var(&mut self, tok: &Token) -> Option<Sym> {
+        if let Some(&var) = self.work_vars.get(tok) {
+            return Some(Sym::Meta(var, self.subst.typecode(var)));
+        }
+        let typecode = self.grammar.work_var_type(self.nset, *tok.get(1)?)?;
+        let var = self.subst.fresh(typecode);
+        self.work_vars.insert(tok.clone(), var);
+        Some(Sym::Meta(var, typecode))
+    }
+
+    fn formula(&mut self, line: &StepLine) -> Option<Formula> {
+        let (first, rest) = match line.formula.split_first() {
+            Some(split) => split,
+            None => {
+                // an omitted formula is a wildcard
+                let var = self.subst.fresh(self.grammar.logic_type());
+                return Some(Formula {
+                    typecode: self.grammar.provable_type(),
+                    term: Term::Meta(var),
+                });
+            }
+        };
+        let typecode = match self.symbols.lookup(&first.1) {
+            Some(Sym::Const(atom)) => atom,
+            _ => {
+                self.diags.push((first.0, MmpBadSymbol(first.1.clone())));
+                return None;
+            }
+        };
+        let mut syms = Vec::with_capacity(rest.len());
+        for (span, tok) in rest {
+            let sym = if tok.starts_with(b"&") {
+                self.work_var(tok)
+            } else {
+                self.symbols.lookup(tok)
+            };
+            match sym {
+                Some(sym) => syms.push(sym),
+                None => {
+                    self.diags.push((*span, MmpBadSymbol(tok.clone())));
+                    return None;
+                }
+            }
+        }
+        let formula = self.grammar.parse(typecode, &syms);
+        if formula.is_none() {
+            let last = line.formula.last().unwrap().0;
+            self.diags.push((Span { start: first.0.start, end: last.end }, MmpUnparsable));
+        }
+        formula
+    }
+
+    fn unify_formula(&mut self, formula: &Formula, pattern: &Formula, base: MetaVar) -> bool {
+        formula.typecode == pattern.typecode &&
+        self.subst.unify(self.grammar, &formula.term, &pattern.term.shift(base))
+    }
+
+    /// Matches a step against an assertion, filling in missing hypotheses.
+    fn apply(&mut self,
+             ix: usize,
+             formula: &Formula,
+             hyps: &[Option<usize>],
+             addr: StatementAddress)
+             -> Result<(MetaVar, Vec<usize>), Diagnostic> {
+        let label = copy_token(self.sset.statement(addr).label());
+        let pattern = self.pattern(addr).ok_or(MmpUnparsable)?;
+        let nhyps = pattern.essentials().count();
+        if !hyps.is_empty() && hyps.len() != nhyps {
+            return Err(MmpHypCount(label, nhyps, hyps.len()));
+        }
+
+        let mark = self.subst.mark();
+        let base = self.subst.len();
+        for &typecode in &pattern.var_types {
+            self.subst.fresh(typecode);
+        }
+        let result = self.match_frame(ix, formula, hyps, &pattern, base, label);
+        if result.is_err() {
+            self.subst.undo(mark);
+            self.subst.truncate(base);
+        }
+        result.map(|used| (base, used))
+    }
+
+    fn match_frame(&mut self,
+                   ix: usize,
+                   formula: &Formula,
+                   hyps: &[Option<usize>],
+                   pattern: &FramePattern,
+                   base: MetaVar,
+                   label: Token)
+                   -> Result<Vec<usize>, Diagnostic> {
+        if !self.unify_formula(formula, &pattern.target, base) {
+            return Err(MmpStepMismatch(label));
+        }
+        let essentials: Vec<&Formula> = pattern.essentials().map(|(_, hyp)| hyp).collect();
+        let mut used = vec![None; essentials.len()];
+        // explicitly given hypotheses first, since they constrain the search
+        for (index, hyp) in essentials.iter().enumerate() {
+            if let Some(&Some(step)) = hyps.get(index) {
+                let hformula = self.steps[step].as_ref().unwrap().formula.clone();
+                if !self.unify_formula(&hformula, hyp, base) {
+                    return Err(MmpStepMismatch(label));
+                }
+                used[index] = Some(step);
+            }
+        }
+        let missing: Vec<usize> = (0..used.len()).filter(|&index| used[index].is_none()).collect();
+        if let Err(index) = self.fill_hyps(ix, &essentials, base, &missing, &mut used) {
+            return Err(MmpHypNotFound(label, index + 1));
+        }
+        Ok(used.into_iter().map(Option::unwrap).collect())
+    }
+
+    /// Finds earlier steps for the hypotheses listed in `missing`, backtracking
+    /// over the choices since a greedy match of one hypothesis can make the
+    /// next one fail.  On failure, returns the hypothesis which could not be
+    /// matched.
+    fn fill_hyps(&mut self,
+                 ix: usize,
+                 essentials: &[&Formula],
+                 base: MetaVar,
+                 missing: &[usize],
+                 used: &mut [Option<usize>])
+                 -> Result<(), usize> {
+        let (&index, rest) = match missing.split_first() {
+            Some(split) => split,
+            None => return Ok(()),
+        };
+        let mut failure = index;
+        for step in (0..ix).rev() {
+            let hformula = match self.steps[step] {
+                Some(ref s) => s.formula.clone(),
+                None => continue,
+            };
+            let mark = self.subst.mark();
+            if self.unify_formula(&hformula, essentials[index], base) {
+                match self.fill_hyps(ix, essentials, base, rest, used) {
+                    Ok(()) => {
+                        used[index] = Some(step);
+                        return Ok(());
+                    }
+                    Err(deeper) => failure = deeper,
+                }
+                self.subst.undo(mark);
+            }
+        }
+        Err(failure)
+    }
+
+    /// Matches a hypothesis step against the `$e` hypotheses of the theorem.
+    fn hypothesis(&mut self, line: &StepLine, formula: &Formula) -> Option<StatementAddress> {
+        for (index, hyp) in self.frame.hypotheses.iter().enumerate() {
+            if let Hyp::Essential(addr, _) = *hyp {
+                if let Some(ref label) = line.label {
+                    if self.sset.statement(addr).label() != &label[..] {
+                        continue;
+                    }
+                }
+                let pattern = self.statement.hyps[index].clone().unwrap();
+                if self.unify_formula(formula, &pattern, 0) {
+                    return Some(addr);
+                }
+            }
+        }
+        None
+    }
+
+    fn step(&mut self, ix: usize) -> Option<Step> {
+        let line = &self.sheet.steps[ix];
+        let mut hyps = Vec::with_capacity(line.hyps.len());
+        for hyp in &line.hyps {
+            match *hyp {
+                None => hyps.push(None),
+                Some(ref name) => {
+                    match self.names.get(name) {
+                        // an error was already reported for the hypothesis
+                        Some(&step) if self.steps[step].is_none() => return None,
+                        Some(&step) => hyps.push(Some(step)),
+                        None => {
+                            self.diags.push((line.span, MmpUnknownStep(name.clone())));
+                            return None;
+                        }
+                    }
+                }
+            }
+        }
+        let formula = self.formula(line)?;
+
+        if &*line.name == b"qed" {
+            let target = self.statement.target.clone();
+            if !self.unify_formula(&formula, &target, 0) {
+                self.diags.push((line.span, MmpQedMismatch));
+                return None;
+            }
+        }
+
+        if line.is_hyp {
+            let addr = match self.hypothesis(line, &formula) {
+                Some(addr) => addr,
+                None => {
+                    self.diags.push((line.span, MmpBadHypothesis));
+                    return None;
+                }
+            };
+            return Some(Step {
+                formula,
+                label: addr,
+                base: 0,
+                hyps: Vec::new(),
+            });
+        }
+
+        let found = match line.label {
+            Some(ref label) => {
+                let addr = match self.nset.lookup_label(label) {
+                    Some(lookup) if self.assertion(lookup.address).is_some() => lookup.address,
+                    _ => {
+                        self.diags.push((line.span, MmpNotAssertion(label.clone())));
+                        return None;
+                    }
+                };
+                match self.apply(ix, &formula, &hyps, addr) {
+                    Ok(result) => (addr, result),
+                    Err(diag) => {
+                        self.diags.push((line.span, diag));
+                        return None;
+                    }
+                }
+            }
+            None => {
+                let mut found = None;
+                for &addr in self.candidates().iter() {
+                    if let Ok(result) = self.apply(ix, &formula, &hyps, addr) {
+                        found = Some((addr, result));
+                        break;
+                    }
+                }
+                match found {
+                    Some(found) => found,
+                    None => {
+                        self.diags.push((line.span, MmpNoJustification));
+                        return None;
+                    }
+                }
+            }
+        };
+        let (addr, (base, hyps)) = found;
+        Some(Step {
+            formula,
+            label: addr,
+            base,
+            hyps,
+        })
+    }
+
+    /// Reports substitutions which need disjoint variable restrictions that
+    /// the theorem does not have, and `$d` lines of the worksheet which the
+    /// theorem does not have either; the proof is checked against the
+    /// database, so the worksheet cannot add restrictions.
+    fn check_dv(&mut self) {
+        let frame = self.frame;
+        let mut allowed: HashSet<(Atom, Atom)> = new_set();
+        for (index, bits) in frame.optional_dv.iter().enumerate() {
+            for other in bits {
+                allowed.insert((frame.var_list[index], frame.var_list[other]));
+            }
+        }
+
+        let mut reported = new_set();
+        for vars in &self.sheet.disjoint {
+            let atoms: Vec<Atom> = vars.iter()
+                .filter_map(|(_, tok)| self.nset.lookup_symbol(tok).map(|l| l.atom))
+                .collect();
+            let span = match (vars.first(), vars.last()) {
+                (Some(first), Some(last)) => Span::new(first.0.start as usize, last.0.end as usize),
+                _ => continue,
+            };
+            for (ix, &x) in atoms.iter().enumerate() {
+                for &y in &atoms[ix + 1..] {
+                    if x != y && !allowed.contains(&(x, y)) && !reported.contains(&(y, x)) &&
+                       reported.insert((x, y)) {
+                        let name = |atom| copy_token(self.nset.atom_name(atom));
+                        self.diags.push((span, MmpDvMissing(name(x), name(y))));
+                    }
+                }
+            }
+        }
+
+        for (ix, line) in self.sheet.steps.iter().enumerate() {
+            let (addr, base) = match self.steps[ix] {
+                Some(ref step) if !line.is_hyp => (step.label, step.base),
+                _ => continue,
+            };
+            let sframe = self.assertion(addr).unwrap();
+            let label = self.sset.statement(addr).label();
+            for &(left, right) in &*sframe.mandatory_dv {
+                let lvars = self.grammar.variables(&self.subst.resolve(&Term::Meta(base + left)));
+                let rvars = self.grammar.variables(&self.subst.resolve(&Term::Meta(base + right)));
+                for &x in &lvars {
+                    for &y in &rvars {
+                        let name = |atom| copy_token(self.nset.atom_name(atom));
+                        if x == y {
+                            self.diags.push((line.span, MmpDvViolation(copy_token(label), name(x))));
+                        } else if !allowed.contains(&(x, y)) && !reported.contains(&(y, x)) &&
+                                  reported.insert((x, y)) {
+                            self.diags.push((line.span, MmpDvMissing(name(x), name(y))));
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    fn build_step(&self, arr: &mut ProofTreeArray, built: &mut Vec<Option<usize>>, ix: usize) -> usize {
+        if let Some(tree) = built[ix] {
+            return tree;
+        }
+        let step = self.steps[ix].as_ref().unwrap();
+        let addr = step.label;
+        let mut children = Vec::new();
+        if !self.sheet.steps[ix].is_hyp {
+            let frame = self.assertion(addr).unwrap();
+            let mut essentials = step.hyps.iter();
+            for hyp in &*frame.hypotheses {
+                children.push(match *hyp {
+                    Hyp::Floating(_, var, _) => {
+                        let term = self.subst.resolve(&Term::Meta(step.base + var));
+                        self.grammar.build_tree(self.nset, arr, &term)
+                    }
+                    Hyp::Essential(..) => self.build_step(arr, built, *essentials.next().unwrap()),
+                });
+            }
+        }
+        let mut pool = Vec::new();
+        self.grammar.render(self.nset, &self.subst, &step.formula.term, &mut pool);
+        let len = pool.len();
+        let tree = arr.build(addr, children, &pool, 0..len);
+        built[ix] = Some(tree);
+        tree
+    }
+
+    fn finish(&mut self) -> Option<ProofTreeArray> {
+        let qed = match self.names.get(&b"qed"[..]) {
+            Some(&qed) => qed,
+            None => {
+                self.diags.push((self.sheet.header, MmpMissingQed));
+                return None;
+            }
+        };
+        for (ix, line) in self.sheet.steps.iter().enumerate() {
+            if let Some(ref step) = self.steps[ix] {
+                if !self.subst.resolve(&step.formula.term).is_ground() {
+                    self.diags.push((line.span, MmpUnresolved));
+                }
+            }
+        }
+        if !self.diags.is_empty() {
+            return None;
+        }
+        self.check_dv();
+        if !self.diags.is_empty() {
+            return None;
+        }
+
+        let mut arr = ProofTreeArray::default();
+        let mut built = vec![None; self.steps.len()];
+        let root = self.build_step(&mut arr, &mut built, qed);
+        arr.set_qed(root);
+        Some(arr)
+    }
+}
+
+/// Parses an mmj2 proof worksheet and checks it against a database.
+///
+/// Every step is checked against the frame of the assertion it references,
+/// using unification over the parsed formulas; missing references, hypotheses,
+/// formulas, and work variables are filled in where a match can be found.  If
+/// the worksheet contains no errors, the proof is returned as a
+/// `ProofTreeArray`.
+pub fn import_mmp(sset: &SegmentSet,
+                  nset: &Nameset,
+                  scope: &ScopeResult,
+                  grammar: &Grammar,
+                  source: Arc<SourceInfo>)
+                  -> WorksheetResult {
+    let mut diags = Vec::new();
+    let sheet = parse_worksheet(&source.text, &mut diags);
+    let mut result = WorksheetResult {
+        source: source.clone(),
+        theorem: None,
+        proof: None,
+        diagnostics: Vec::new(),
+    };
+
+    let thm = sheet.theorem
+        .as_ref()
+        .and_then(|label| nset.lookup_label(label))
+        .map(|lookup| sset.statement(lookup.address))
+        .filter(|stmt| stmt.statement_type() == StatementType::Provable);
+    let frame = thm.and_then(|stmt| {
+        scope.get(stmt.label()).filter(|frame| frame.valid.start == stmt.address())
+    });
+    let (thm, frame) = match (thm, frame) {
+        (Some(thm), Some(frame)) => (thm, frame),
+        _ => {
+            diags.push((sheet.header, MmpMissingTheorem));
+            result.diagnostics = diags;
+            return result;
+        }
+    };
+    result.theorem = Some(thm.address());
+    let statement = match grammar.frame_statement(nset, frame) {
+        Some(statement) => statement,
+        None => {
+            diags.push((sheet.header, MmpUnparsable));
+            result.diagnostics = diags;
+            return result;
+        }
+    };
+
+    let mut checker = Checker {
+        sset,
+        nset,
+        scope,
+        grammar,
+        sheet: &sheet,
+        thm,
+        frame,
+        statement,
+        symbols: SymbolScope::new(sset, nset, thm),
+        subst: Subst::new(),
+        work_vars: new_map(),
+        patterns: new_map(),
+        candidates: None,
+        names: new_map(),
+        steps: Vec::new(),
+        diags,
+    };
+    for (ix, line) in sheet.steps.iter().enumerate() {
+        if checker.names.contains_key(&line.name) {
+            checker.diags.push((line.span, MmpDuplicateStep(line.name.clone())));
+            checker.steps.push(None);
+            continue;
+        }
+        let step = checker.step(ix);
+        checker.steps.push(step);
+        checker.names.insert(line.name.clone(), ix);
+    }
+    result.proof = checker.finish();
+    result.diagnostics = checker.diags;
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::database::Database;
+    use crate::diag::Diagnostic;
+    use crate::parser::Span;
+    use crate::proof::ProofTreeArray;
+    use crate::test_util::database;
+    use crate::test_util::A1I;
+    use crate::test_util::PROP;
+
+    fn mkdb() -> Database {
+        database(&[PROP,
+                   "ax-2 $a |- ( ( ph -> ( ps -> ch ) ) -> ( ( ph -> ps ) -> ( ph -> ch ) ) ) $.\n",
+                   A1I,
+                   "id $p |- ( ph -> ph ) $= ? $.\n"]
+            .concat())
+    }
+
+    fn import(text: &str) -> (Option<Vec<u8>>, Vec<(Span, Diagnostic)>) {
+        let result = mkdb().import_mmp("test.mmp".to_owned(), text.as_bytes().to_owned());
+        (result.proof.map(|arr| arr.exprs[arr.qed].clone()), result.diagnostics)
+    }
+
+    #[test]
+    fn test_import_full() {
+        let mut db = mkdb();
+        let result = db.import_mmp("test.mmp".to_owned(),
+                                   b"$( <MM> <PROOF_ASST> THEOREM=a1i  LOC_AFTER=?
+
+* A comment
+  on two lines.
+
+h1::a1i.1        |- ph
+2::ax-1          |- ( ph -> ( ps -> ph ) )
+qed:1,2:ax-mp    |- ( ps -> ph )
+
+$=  wph wps wph wi a1i.1 wph wps ax-1 ax-mp $.
+
+$)
+"
+                                       .to_vec());
+        assert_eq!(result.diagnostics, vec![]);
+        let arr = result.proof.unwrap();
+        let sset = db.parse_result().clone();
+        let nset = db.name_result().clone();
+        let scope = db.scope_result().clone();
+        let stmt = db.statement("a1i").unwrap();
+        let orig = ProofTreeArray::new(&sset, &nset, &scope, stmt).unwrap();
+        assert_eq!(arr.to_rpn(&arr.count_parents(), true),
+                   orig.to_rpn(&orig.count_parents(), true));
+        assert_eq!(arr.exprs[arr.qed], b" ( ps -> ph )".to_vec());
+    }
+
+    #[test]
+    fn test_import_fills_gaps() {
+        // ax-mp references and hypotheses are left to be found
+        let (proof, diags) = import("$( <MM> <PROOF_ASST> THEOREM=id  LOC_AFTER=?
+1::ax-1       |- ( ph -> ( ph -> ph ) )
+2::ax-1       |- ( ph -> ( ( ph -> ph ) -> ph ) )
+3::ax-2       |- ( ( ph -> ( ( ph -> ph ) -> ph ) )
+                   -> ( ( ph -> ( ph -> ph ) ) -> ( ph -> ph ) ) )
+4:2,3:        |- ( ( ph -> ( ph -> ph ) ) -> ( ph -> ph ) )
+qed::ax-mp    |- ( ph -> ph )
+$)
+");
+        assert_eq!(diags, vec![]);
+        assert_eq!(proof, Some(b" ( ph -> ph )".to_vec()));
+    }
+
+    #[test]
+    fn test_import_work_vars() {
+        let (proof, diags) = import("$( <MM> <PROOF_ASST> THEOREM=id  LOC_AFTER=?
+1::ax-1       |- ( ph -> ( ph -> ph ) )
+2::ax-1       |- ( ph -> ( &W1 -> ph ) )
+3::ax-2       |- ( ( ph -> ( &W1 -> ph ) ) -> ( ( ph -> &W1 ) -> ( ph -> ph ) ) )
+4:2,3:ax-mp
+qed:1,4:ax-mp |- ( ph -> ph )
+$)
+");
+        assert_eq!(diags, vec![]);
+        assert!(proof.is_some());
+    }
+
+    #[test]
+    fn test_import_errors() {
+        let (proof, diags) = import("$( <MM> <PROOF_ASST> THEOREM=id  LOC_AFTER=?
+1::ax-1       |- ( ph -> ( ph -> ps ) )
+2:1:ax-mp     |- ( ph -> ph )
+3::ax-1       |- ( ph -> xx )
+qed:9:ax-mp   |- ( ph -> ph )
+$)
+");
+        assert_eq!(proof, None);
+        assert_eq!(diags.iter().map(|d| d.1.clone()).collect::<Vec<_>>(),
+                   vec![Diagnostic::MmpStepMismatch(Box::from(&b"ax-1"[..])),
+                        Diagnostic::MmpBadSymbol(Box::from(&b"xx"[..])),
+                        Diagnostic::MmpUnknownStep(Box::from(&b"9"[..]))]);
+        assert_eq!(diags[0].0, Span::new(45, 52));
+    }
+
+    #[test]
+    fn test_import_dv_not_in_database() {
+        // a $d line of the worksheet does not make the proof acceptable
+        let (proof, diags) = import("$( <MM> <PROOF_ASST> THEOREM=a1i  LOC_AFTER=?
+$d ph ps
+h1::a1i.1        |- ph
+2::ax-1          |- ( ph -> ( ps -> ph ) )
+qed:1,2:ax-mp    |- ( ps -> ph )
+$)
+");
+        assert_eq!(proof, None);
+        assert_eq!(diags,
+                   vec![(Span::new(49, 54),
+                         Diagnostic::MmpDvMissing(Box::from(&b"ph"[..]), Box::from(&b"ps"[..])))]);
+    }
+}
@@ -7,7 +7,8 @@
 //! Scope check needs the ability to look up math symbols and statement labels
 //! to ensure that they are declared exactly once, and to find the float data
 //! for variables.  This pass constructs the hash tables which are used for
-//! that.
+//! that.  It also indexes the segments which mention each label, so that
+//! renaming a label need not scan the whole database.
 //!
 //! The nameset keeps a global generation number and a generation for each
 //! object which can be looked up.  In an analysis pass, you can use
@@ -20,6 +21,7 @@
 use crate::database::DbOptions;
 use crate::parser::Comparer;
 use crate::parser::copy_token;
+use crate::parser::label_mentions;
 use crate::parser::Segment;
 use crate::parser::SegmentId;
 use crate::parser::SegmentOrder;
@@ -146,6 +148,17 @@ fn intern(table: &mut AtomTable, tok: TokenPtr) -> Atom {
     next
 }
 
+// the distinct labels mentioned in a segment
+fn segment_mentions(sref: SegmentRef) -> HashSet<Token> {
+    let mut mentions = new_set();
+    label_mentions(sref, |mention, _| {
+        if !mentions.contains(mention) {
+            mentions.insert(copy_token(mention));
+        }
+    });
+    mentions
+}
+
 /// Calculated index mapping names to definitions in a database.
 ///
 /// To extract data from a nameset object, construct a `NameReader` and use the
@@ -163,6 +176,9 @@ pub struct Nameset {
     dv_info: NameSlot<StatementAddress, Vec<Atom>>,
     labels: HashMap<Token, LabelInfo>,
     symbols: HashMap<Token, SymbolInfo>,
+    // segments mentioning each label, in proofs or comment links; this is
+    // not generation tracked since no analysis pass depends on it
+    mentions: HashMap<Token, NameSlot<SegmentId, ()>>,
 }
 
 impl Nameset {
@@ -267,6 +283,11 @@ impl Nameset {
                         StatementAddress::new(id, dvdef.start),
                         vars);
         }
+
+        for mention in segment_mentions(sref) {
+            let slot = autoviv(&mut self.mentions, mention);
+            slot_insert(slot, &*self.order, id, ());
+        }
     }
 
     fn remove_segment(&mut self, id: SegmentId) {
@@ -307,6 +328,10 @@ impl Nameset {
                 self.dv_gen = gen;
                 slot_remove(&mut self.dv_info, StatementAddress::new(id, dvdef.start));
             }
+
+            for mention in segment_mentions(sref) {
+                deviv(&mut self.mentions, &mention, |slot| slot_remove(slot, id));
+            }
         }
     }
 
@@ -352,6 +377,12 @@ impl Nameset {
         })
     }
 
+    /// Lists the segments which mention a label, in database order: as the
+    /// label of a statement, in a proof, or in a `~ label` comment link.
+    pub fn label_mentions(&self, label: TokenPtr) -> Vec<SegmentId> {
+        self.mentions.get(label).map_or(Vec::new(), |slot| slot.iter().map(|&(id, _)| id).collect())
+    }
+
     /// Looks up the atom from a $f statement.
     pub fn var_atom(&self, stmt: StatementRef) -> Option<Atom> {
         self.lookup_symbol(&stmt.math_at(1)).map(|lookup| lookup.atom)
@@ -457,6 +457,17 @@ impl<'a> Deref for SegmentRef<'a> {
     }
 }
 
+impl Segment {
+    /// The span of the buffer which was parsed to produce this segment, from
+    /// the start of its first statement to the end of its last.
+    pub fn span(&self) -> Span {
+        match (self.statements.first(), self.statements.last()) {
+            (Some(first), Some(last)) => Span::new2(first.span.start, last.span.end),
+            _ => Span::null(),
+        }
+    }
+}
+
 impl<'a> SegmentRef<'a> {
     /// Fetch a single statement from this segment by its local index.
     pub fn statement(self, index: StatementIndex) -> StatementRef<'a> {
@@ -704,6 +715,93 @@ impl<'a> StatementRef<'a> {
         self.proof_span(ix).as_ref(&self.segment.segment.buffer)
     }
 
+    /// The span from the `$=` keyword of a statement through the `$.` which
+    /// ends it, if the statement has a proof section.
+    ///
+    /// Comments between the math string and `$=` are skipped.
+    pub fn proof_section(&self) -> Option<Span> {
+        let buf = &self.segment.segment.buffer;
+        let end = self.span().end as usize;
+        let mut pos = if self.math_len() > 0 {
+            self.math_span(self.math_len() - 1).end as usize
+        } else {
+            self.statement.label.end as usize
+        };
+        loop {
+            while pos < end && is_mm_space(buf[pos]) {
+                pos += 1;
+            }
+            let rest = &buf[pos..end];
+            if rest.starts_with(b"$=") {
+                return Some(Span::new(pos, end));
+            } else if rest.starts_with(b"$(") {
+                pos += 2 + rest[2..].windows(2).position(|w| w == b"$)")? + 2;
+            } else {
+                return None;
+            }
+        }
+    }
+
+    /// Returns true if the comment of this statement carries the tag `(New
+    /// usage is discouraged.)`, which asks proof tools not to use the statement
+    /// in new proofs.
+    pub fn usage_discouraged(&self) -> bool {
+        self.comment_has_tag(b"(New usage is discouraged.)")
+    }
+
+    /// Returns true if the comment of this statement carries the tag `(Proof
+    /// modification is discouraged.)`, which asks proof tools to leave its
+    /// proof alone.
+    pub fn proof_modification_discouraged(&self) -> bool {
+        self.comment_has_tag(b"(Proof modification is discouraged.)")
+    }
+
+    /// If this is a comment which starts a section of the database outline,
+    /// returns the depth of the heading and its title.
+    ///
+    /// Headings follow the conventions of set.mm: the first line of the
+    /// comment is a rule of `#`, `#*`, `=-` or `-.` characters, for depth 0
+    /// (part) through 3 (subsection), and the title is on the next line.
+    pub fn heading(&self) -> Option<(usize, &'a [u8])> {
+        const RULES: [&[u8]; 4] = [b"####", b"#*#*", b"=-=-", b"-.-."];
+        if self.statement_type() != StatementType::Comment {
+            return None;
+        }
+        let text = self.span().as_ref(&self.segment.segment.buffer);
+        let mut lines = text[2..]
+            .split(|&c| c == b'\n')
+            .map(|line| {
+                let start = line.iter().position(|&c| !is_mm_space(c)).unwrap_or(line.len());
+                let end = line.iter().rposition(|&c| !is_mm_space(c)).map_or(start, |e| e + 1);
+                &line[start..end]
+            })
+            .skip_while(|line| line.is_empty());
+        let rule = lines.next()?;
+        let depth = RULES.iter().position(|prefix| rule.starts_with(prefix))?;
+        let title = lines.next()?;
+        if title.is_empty() || title.starts_with(b"$)") {
+            return None;
+        }
+        Some((depth, title))
+    }
+
+    // tags may be broken across lines, so compare with whitespace collapsed
+    fn comment_has_tag(&self, tag: &[u8]) -> bool {
+        let comment = match self.associated_comment() {
+            Some(comment) => comment,
+            None => return false,
+        };
+        let mut text = Vec::new();
+        for word in comment.span()
+            .as_ref(&comment.segment().segment.buffer)
+            .split(|&c| is_mm_space(c))
+            .filter(|word| !word.is_empty()) {
+            text.extend_from_slice(word);
+            text.push(b' ');
+        }
+        text.windows(tag.len()).any(|w| w == tag)
+    }
+
     /// Get the "documentation" comment immediately preceding a $a $p
     /// statement, if it exists.
     pub fn associated_comment(&self) -> Option<StatementRef<'a>> {
@@ -849,7 +947,7 @@ fn is_mm_space_c0(byte: u8) -> bool {
 ///
 /// We generally accept any C0 control as whitespace, with a diagnostic; this
 /// function only tests for fully legal whitespace though.
-fn is_mm_space(byte: u8) -> bool {
+pub fn is_mm_space(byte: u8) -> bool {
     byte <= 32 && is_mm_space_c0(byte)
 }
 
@@ -1470,13 +1568,68 @@ fn collect_definitions(seg: &mut Segment) {
 }
 
 /// Metamath spec valid label characters are `[-._a-zA-Z0-9]`
-fn is_valid_label(label: &[u8]) -> bool {
+pub fn is_valid_label(label: &[u8]) -> bool {
     label.iter().all(|&c| {
         c == b'.' || c == b'-' || c == b'_' || (b'a'..=b'z').contains(&c) ||
         (b'0'..=b'9').contains(&c) || (b'A'..=b'Z').contains(&c)
     })
 }
 
+/// Calls `f` with the text and span of every mention of a statement label in a
+/// segment, in order: the labels of labeled statements, the steps of normal
+/// proofs and the rosters of compressed proofs, and the words after `~` in
+/// comments.  For proof steps written as `step:hyp=label`, each of the parts
+/// which can be a label is reported separately.
+pub fn label_mentions<'a, F>(sref: SegmentRef<'a>, mut f: F)
+    where F: FnMut(TokenPtr<'a>, Span)
+{
+    let buffer = &sref.segment.buffer;
+    for stmt in sref {
+        match stmt.statement_type() {
+            StatementType::Comment => {
+                let span = stmt.span_full();
+                let mut after_tilde = false;
+                let mut pos = span.start as usize;
+                for word in span.as_ref(buffer).split(|c| c.is_ascii_whitespace()) {
+                    if after_tilde && !word.is_empty() {
+                        f(word, Span::new(pos, pos + word.len()));
+                    }
+                    after_tilde = word == b"~" || (after_tilde && word.is_empty());
+                    pos += word.len() + 1;
+                }
+            }
+            StatementType::Axiom |
+            StatementType::Provable |
+            StatementType::Essential |
+            StatementType::Floating => {
+                let start = stmt.span().start as usize;
+                f(stmt.label(), Span::new(start, start + stmt.label().len()));
+                let compressed = stmt.proof_len() > 0 && stmt.proof_slice_at(0) == b"(";
+                for index in compressed as TokenIndex..stmt.proof_len() {
+                    let token = stmt.proof_slice_at(index);
+                    if compressed && token == b")" {
+                        break;
+                    }
+                    let span = stmt.proof_span(index);
+                    // skip the step name of packed proofs, and split the
+                    // hypothesis name of explicit proofs from the label
+                    let skip = token.iter().position(|&c| c == b':').map_or(0, |pos| pos + 1);
+                    let mut parts = vec![(skip, token.len())];
+                    if let Some(eq) = token[skip..].iter().position(|&c| c == b'=') {
+                        parts = vec![(skip, skip + eq), (skip + eq + 1, token.len())];
+                    }
+                    for (part_start, part_end) in parts {
+                        f(&token[part_start..part_end],
+                          Span::new(span.start as usize + part_start,
+                                    span.start as usize + part_end));
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
 /// Slightly set.mm specific hack to extract a section name from a byte buffer.
 ///
 /// This is run before parsing so it can't take advantage of comment extraction;
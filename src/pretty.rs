@@ -0,0 +1,396 @@
+//! Pretty printing of math strings.
+//!
+//! Math strings are normally printed as their ASCII tokens joined by spaces.
+//! A `PrettyPrinter` can instead replace each token using a `SymbolMap`, which
+//! is either derived from the `althtmldef` typesetting definitions (most
+//! databases use these for Unicode renderings) or read from a mapping file.
+//!
+//! When a grammar is available and the math string parses, a formula which
+//! does not fit in the line width is broken at syntactic boundaries: before
+//! each infix constant of a syntax axiom which does not fit, with continuation
+//! lines indented relative to the start of the subformula.  Otherwise lines
+//! are broken between tokens.  Continuation lines always start with
+//! whitespace, as the mmj2 worksheet format requires.
+
+use crate::diag::Notation;
+use crate::grammar::Grammar;
+use crate::grammar::RuleSym;
+use crate::grammar::SymbolScope;
+use crate::nameck::Nameset;
+use crate::parser::as_str;
+use crate::parser::Token;
+use crate::typesetting::Typesetting;
+use crate::unify::Subst;
+use crate::unify::Term;
+use crate::util::HashMap;
+use std::fs;
+use std::io;
+
+/// Replacement text for math symbols.
+#[derive(Default,Debug,Clone)]
+pub struct SymbolMap {
+    map: HashMap<Token, String>,
+}
+
+/// The named character references which are common in `althtmldef`s.
+const NAMED_ENTITIES: &[(&str, char)] = &[("amp", '&'),
+                                          ("lt", '<'),
+                                          ("gt", '>'),
+                                          ("quot", '"'),
+                                          ("apos", '\''),
+                                          ("nbsp", ' '),
+                                          ("not", '\u{ac}'),
+                                          ("and", '\u{2227}'),
+                                          ("or", '\u{2228}'),
+                                          ("rarr", '\u{2192}'),
+                                          ("larr", '\u{2190}'),
+                                          ("harr", '\u{2194}'),
+                                          ("rArr", '\u{21d2}'),
+                                          ("hArr", '\u{21d4}'),
+                                          ("forall", '\u{2200}'),
+                                          ("exist", '\u{2203}'),
+                                          ("isin", '\u{2208}'),
+                                          ("notin", '\u{2209}'),
+                                          ("empty", '\u{2205}'),
+                                          ("sub", '\u{2282}'),
+                                          ("sube", '\u{2286}'),
+                                          ("cap", '\u{2229}'),
+                                          ("cup", '\u{222a}'),
+                                          ("ne", '\u{2260}'),
+                                          ("le", '\u{2264}'),
+                                          ("ge", '\u{2265}'),
+                                          ("times", '\u{d7}'),
+                                          ("middot", '\u{b7}'),
+                                          ("lambda", '\u{3bb}'),
+                                          ("varphi", '\u{3c6}'),
+                                          ("phi", '\u{3c6}'),
+                                          ("psi", '\u{3c8}'),
+                                          ("chi", '\u{3c7}')];
+
+// convert an HTML fragment to plain text by dropping tags and decoding
+// character references
+fn html_to_text(html: &str) -> String {
+    let mut out = String::new();
+    let mut rest = html;
+    while let Some(chr) = rest.chars().next() {
+        match chr {
+            '<' => {
+                match rest.find('>') {
+                    Some(end) => rest = &rest[end + 1..],
+                    None => break,
+                }
+                continue;
+            }
+            '&' => {
+                if let Some(end) = rest.find(';') {
+                    let entity = &rest[1..end];
+                    let decoded = match entity {
+                        _ if entity.starts_with("#x") || entity.starts_with("#X") => {
+                            u32::from_str_radix(&entity[2..], 16).ok().and_then(char::from_u32)
+                        }
+                        _ if entity.starts_with('#') => {
+                            entity[1..].parse().ok().and_then(char::from_u32)
+                        }
+                        _ => NAMED_ENTITIES.iter().find(|e| e.0 == entity).map(|e| e.1),
+                    };
+                    if let Some(decoded) = decoded {
+                        out.push(decoded);
+                        rest = &rest[end + 1..];
+                        continue;
+                    }
+                }
+            }
+            _ => {}
+        }
+        out.push(chr);
+        rest = &rest[chr.len_utf8()..];
+    }
+    out.trim().to_owned()
+}
+
+impl SymbolMap {
+    /// Derives replacements from the `althtmldef` definitions, by dropping
+    /// HTML tags and decoding character references.  Symbols whose definition
+    /// has no text, such as those rendered as images, keep their ASCII form.
+    pub fn from_alt_html(typesetting: &Typesetting) -> SymbolMap {
+        let mut out = SymbolMap::default();
+        for (symbol, html) in typesetting.alt_html_defs() {
+            let text = html_to_text(html);
+            if !text.is_empty() {
+                out.insert(symbol, text);
+            }
+        }
+        out
+    }
+
+    /// Parses a mapping file.  Each line gives a symbol and its replacement,
+    /// separated by whitespace; blank lines and lines starting with `#` are
+    /// ignored.  On failure, returns the line number of the malformed line.
+    pub fn parse(text: &str) -> Result<SymbolMap, usize> {
+        let mut out = SymbolMap::default();
+        for (index, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let mut fields = line.split_whitespace();
+            match (fields.next(), fields.next(), fields.next()) {
+                (Some(symbol), Some(text), None) => out.insert(symbol.as_bytes(), text.to_owned()),
+                _ => return Err(index + 1),
+            }
+        }
+        Ok(out)
+    }
+
+    /// Reads a mapping file in the format of `parse`.
+    pub fn read(path: &str) -> io::Result<SymbolMap> {
+        let text = fs::read_to_string(path)?;
+        SymbolMap::parse(&text).map_err(|line| {
+            io::Error::new(io::ErrorKind::InvalidData,
+                           format!("line {}: expected a symbol and its replacement", line))
+        })
+    }
+
+    /// Sets the replacement for a symbol.
+    pub fn insert(&mut self, symbol: &[u8], text: String) {
+        self.map.insert(symbol.into(), text);
+    }
+
+    /// The replacement for a symbol, if it has one.
+    pub fn get(&self, symbol: &[u8]) -> Option<&str> {
+        self.map.get(symbol).map(|text| &**text)
+    }
+}
+
+/// A formula laid out for printing.
+enum Doc {
+    Text(String),
+    /// A subformula, as the pieces which are put on separate lines if it
+    /// does not fit.
+    Group(Vec<Vec<Doc>>),
+}
+
+impl Doc {
+    fn width(&self) -> usize {
+        match *self {
+            Doc::Text(ref text) => text.chars().count(),
+            Doc::Group(ref pieces) => {
+                let items = pieces.iter().flatten();
+                items.clone().map(Doc::width).sum::<usize>() + items.count().saturating_sub(1)
+            }
+        }
+    }
+}
+
+/// Formats math strings for display.
+#[derive(Copy,Clone)]
+pub struct PrettyPrinter<'a> {
+    /// The database names, for rendering parse trees.
+    pub nset: &'a Nameset,
+    /// The grammar, if formulas should be broken at syntactic boundaries.
+    pub grammar: Option<&'a Grammar>,
+    /// The symbol replacements; an empty map prints plain ASCII.
+    pub symbols: &'a SymbolMap,
+    /// The maximum line width, or 0 to never break lines.
+    pub width: usize,
+    /// The indentation of continuation lines, relative to the subformula
+    /// which is broken.
+    pub indent: usize,
+}
+
+impl<'a> PrettyPrinter<'a> {
+    /// The rendering of a single math symbol.
+    pub fn symbol<'b>(&'b self, symbol: &'b [u8]) -> &'b str {
+        self.symbols.get(symbol).unwrap_or_else(|| as_str(symbol))
+    }
+
+    /// Replaces the symbols of a space-separated math string, without
+    /// breaking lines.
+    pub fn flat(&self, expr: &str) -> String {
+        expr.split(' ')
+            .map(|symbol| self.symbol(symbol.as_bytes()))
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    /// Replaces the symbols in the math arguments of a diagnostic, such as
+    /// the expected and actual expressions of a failed proof step.
+    pub fn notation(&self, notation: &mut Notation) {
+        const MATH_ARGS: &[&str] =
+            &["actual", "expected", "expr1", "expr2", "goal", "symbol", "var", "var1", "var2"];
+        for &mut (name, ref mut value) in &mut notation.args {
+            if MATH_ARGS.contains(&name) {
+                *value = self.flat(value);
+            }
+        }
+    }
+
+    /// Formats a math string, given as its tokens starting with the typecode,
+    /// whose first line starts at `column`.  Variables are resolved in `scope`
+    /// to parse the formula; if it does not parse, it is broken between
+    /// tokens.
+    pub fn format(&self, scope: &mut SymbolScope, tokens: &[&[u8]], column: usize) -> String {
+        let mut out = String::new();
+        match self.parse(scope, tokens) {
+            Some(doc) => {
+                self.layout(&doc, column, &mut out);
+            }
+            None => {
+                let mut col = column;
+                for (index, &token) in tokens.iter().enumerate() {
+                    let text = self.symbol(token);
+                    let len = text.chars().count();
+                    if index > 0 {
+                        if self.width != 0 && col + 1 + len > self.width {
+                            col = self.newline(column, &mut out);
+                        } else {
+                            out.push(' ');
+                            col += 1;
+                        }
+                    }
+                    out.push_str(text);
+                    col += len;
+                }
+            }
+        }
+        out
+    }
+
+    fn parse(&self, scope: &mut SymbolScope, tokens: &[&[u8]]) -> Option<Doc> {
+        let grammar = self.grammar?;
+        let (&typecode, rest) = tokens.split_first()?;
+        let typecode_atom = self.nset.lookup_symbol(typecode)?.atom;
+        let syms = rest.iter().map(|&token| scope.lookup(token)).collect::<Option<Vec<_>>>()?;
+        let formula = grammar.parse(typecode_atom, &syms)?;
+        Some(Doc::Group(vec![vec![Doc::Text(self.symbol(typecode).to_owned()),
+                                  self.term(grammar, &formula.term)]]))
+    }
+
+    fn term(&self, grammar: &Grammar, term: &Term) -> Doc {
+        let (addr, children) = match *term {
+            Term::App(addr, ref children) => (addr, children),
+            Term::Meta(_) => {
+                let mut name = Vec::new();
+                grammar.render(self.nset, &Subst::new(), term, &mut name);
+                *name.last_mut().unwrap() &= 0x7F;
+                return Doc::Text(self.symbol(&name).to_owned());
+            }
+        };
+        let pattern = match grammar.rule_pattern(addr) {
+            Some(pattern) => pattern,
+            None => {
+                let var = grammar.float_var(addr).unwrap_or_default();
+                return Doc::Text(self.symbol(self.nset.atom_name(var)).to_owned());
+            }
+        };
+        // start a new piece at each constant between two subformulas
+        let mut pieces = Vec::new();
+        let mut piece = Vec::new();
+        let mut after_var = false;
+        for (index, &sym) in pattern.iter().enumerate() {
+            match sym {
+                RuleSym::Const(atom) => {
+                    let infix = pattern[index + 1..]
+                        .iter()
+                        .any(|sym| matches!(*sym, RuleSym::Var(..)));
+                    if after_var && infix {
+                        pieces.push(piece);
+                        piece = Vec::new();
+                    }
+                    piece.push(Doc::Text(self.symbol(self.nset.atom_name(atom)).to_owned()));
+                    after_var = false;
+                }
+                RuleSym::Var(hyp, _) => {
+                    piece.push(self.term(grammar, &children[hyp]));
+                    after_var = true;
+                }
+            }
+        }
+        pieces.push(piece);
+        Doc::Group(pieces)
+    }
+
+    // writes a line break and the indentation of a continuation line
+    fn newline(&self, start: usize, out: &mut String) -> usize {
+        let indent = start + self.indent.max(1);
+        out.push('\n');
+        out.extend((0..indent).map(|_| ' '));
+        indent
+    }
+
+    // writes a document starting at `column`, returning the column after it
+    fn layout(&self, doc: &Doc, column: usize, out: &mut String) -> usize {
+        let pieces = match *doc {
+            Doc::Text(ref text) => {
+                out.push_str(text);
+                return column + text.chars().count();
+            }
+            Doc::Group(ref pieces) => pieces,
+        };
+        let broken = self.width != 0 && column + doc.width() > self.width;
+        let mut col = column;
+        for (index, piece) in pieces.iter().enumerate() {
+            if index > 0 {
+                if broken {
+                    col = self.newline(column, out);
+                } else {
+                    out.push(' ');
+                    col += 1;
+                }
+            }
+            for (index, item) in piece.iter().enumerate() {
+                if index > 0 {
+                    out.push(' ');
+                    col += 1;
+                }
+                col = self.layout(item, col, out);
+            }
+        }
+        col
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::grammar::SymbolScope;
+    use crate::pretty::PrettyPrinter;
+    use crate::pretty::SymbolMap;
+    use crate::test_util::database;
+    use crate::test_util::PROP;
+
+    #[test]
+    fn test_pretty() {
+        let text = "th $a |- ( ( ph -> ps ) -> ( ps -> ch ) ) $.
+            $( $t althtmldef \"->\" as ' &rarr; '; althtmldef \"ph\" as '<I>&#966;</I>';
+                  althtmldef \"|-\" as '<IMG SRC=\"tstile.gif\">'; $)";
+        let mut db = database(&[PROP, text].concat());
+        let sset = db.parse_result().clone();
+        let nset = db.name_result().clone();
+        let grammar = db.grammar_result().clone();
+        let symbols = SymbolMap::from_alt_html(db.typesetting_result());
+        assert_eq!(symbols.get(b"->"), Some("\u{2192}"));
+        assert_eq!(symbols.get(b"ph"), Some("\u{3c6}"));
+        assert_eq!(symbols.get(b"|-"), None);
+
+        let stmt = sset.statement(db.statement("th").unwrap().address());
+        let tokens: Vec<_> = (0..stmt.math_len()).map(|ix| stmt.math_at(ix).slice).collect();
+        let ascii = SymbolMap::default();
+        let format = |symbols: &SymbolMap, width| {
+            let printer = PrettyPrinter {
+                nset: &nset,
+                grammar: Some(&grammar),
+                symbols,
+                width,
+                indent: 2,
+            };
+            printer.format(&mut SymbolScope::new(&sset, &nset, stmt), &tokens, 0)
+        };
+        assert_eq!(format(&symbols, 0), "|- ( ( \u{3c6} \u{2192} ps ) \u{2192} ( ps \u{2192} ch ) )");
+        assert_eq!(format(&ascii, 24),
+                   "|- ( ( ph -> ps )\n     -> ( ps -> ch ) )");
+        assert_eq!(format(&ascii, 16),
+                   "|- ( ( ph\n       -> ps )\n     -> ( ps\n          -> ch ) )");
+
+        assert_eq!(SymbolMap::parse("# comment\n\n-> \u{2192}\nph").unwrap_err(), 4);
+    }
+}
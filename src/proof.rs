@@ -7,9 +7,11 @@ use crate::parser::StatementAddress;
 use crate::parser::StatementRef;
 use crate::parser::StatementType::*;
 use crate::parser::TokenPtr;
+use crate::scopeck::Hyp;
 use crate::scopeck::ScopeResult;
 use crate::segment_set::SegmentSet;
 use std::cmp::max;
+use std::cmp::min;
 use std::cmp::Ord;
 use std::cmp::Ordering;
 use std::cmp::PartialOrd;
@@ -21,6 +23,7 @@ use std::hash::Hash;
 use std::hash::Hasher;
 use std::collections::hash_map::DefaultHasher;
 use std::ops::Range;
+use std::str::FromStr;
 use std::u16;
 use crate::util::HashMap;
 use crate::verify::ProofBuilder;
@@ -115,6 +118,75 @@ pub enum RPNStep {
     },
 }
 
+// the label referenced by a proof token, without the local label and
+// hypothesis name of the packed and explicit styles
+fn step_label(token: &[u8]) -> &[u8] {
+    match token.iter().rposition(|&c| c == b':' || c == b'=') {
+        Some(pos) => &token[pos + 1..],
+        None => token,
+    }
+}
+
+/// Finds the statements which the proof of a `$p` statement refers to by
+/// label, including hypotheses but not the mandatory hypotheses omitted from
+/// compressed proofs, in order of first reference.  This reads the text of
+/// the proof alone; the proof is not verified.
+pub fn proof_labels(nset: &Nameset, stmt: StatementRef) -> Vec<StatementAddress> {
+    let mut out = Vec::new();
+    let compressed = stmt.proof_len() > 0 && stmt.proof_slice_at(0) == b"(";
+    for index in 0..stmt.proof_len() {
+        let token = stmt.proof_slice_at(index);
+        if compressed {
+            // only the label list is relevant
+            if index == 0 {
+                continue;
+            }
+            if token == b")" {
+                break;
+            }
+        }
+        if let Some(lookup) = nset.lookup_label(step_label(token)) {
+            if !out.contains(&lookup.address) {
+                out.push(lookup.address);
+            }
+        }
+    }
+    out
+}
+
+/// The text of a proof with whitespace normalized, so that comparing two
+/// versions detects only changes to its content.  The step string of a
+/// compressed proof is joined into one token.
+pub fn proof_text(stmt: StatementRef) -> Vec<u8> {
+    let mut out = Vec::new();
+    let mut compressed = false;
+    for index in 0..stmt.proof_len() {
+        let token = stmt.proof_slice_at(index);
+        if !compressed && index > 0 {
+            out.push(b' ');
+        }
+        out.extend_from_slice(token);
+        compressed |= token == b")" && stmt.proof_slice_at(0) == b"(";
+    }
+    out
+}
+
+/// Finds the axioms and theorems which the proof of a `$p` statement refers
+/// to, in order of first reference, from the text of the proof alone.  The
+/// proof is not verified, so this also works for proofs with errors.
+pub fn proof_references(sset: &SegmentSet,
+                        nset: &Nameset,
+                        stmt: StatementRef)
+                        -> Vec<StatementAddress> {
+    proof_labels(nset, stmt)
+        .into_iter()
+        .filter(|&addr| {
+            let stype = sset.statement(addr).statement_type();
+            stype == Axiom || stype == Provable
+        })
+        .collect()
+}
+
 impl ProofTreeArray {
     /// Get the index of a proof tree in the array
     pub fn index(&self, tree: &ProofTree) -> Option<usize> {
@@ -135,6 +207,13 @@ impl ProofTreeArray {
         Ok(arr)
     }
 
+    /// Set the QED step of an array which was filled using the `ProofBuilder`
+    /// interface, and recalculate the distances to it
+    pub fn set_qed(&mut self, qed: usize) {
+        self.qed = qed;
+        self.indent = self.calc_indent();
+    }
+
     /// Get the minimum distance from each step to the QED step
     pub fn indent(&self) -> &[u16] {
         &self.indent
@@ -280,6 +359,7 @@ impl ProofTreeArray {
             arr: self,
             explicit,
             stack: vec![(self.qed, 0)],
+            done: None,
         }
     }
 }
@@ -291,22 +371,42 @@ pub struct NormalIter<'a> {
     arr: &'a ProofTreeArray,
     explicit: bool,
     stack: Vec<(usize, usize)>,
+    done: Option<Vec<bool>>,
 }
 
-impl<'a> Iterator for NormalIter<'a> {
-    type Item = RPNStep;
+impl<'a> NormalIter<'a> {
+    /// Skips the subtrees which were already produced, so that each distinct
+    /// step is produced once, after the steps it uses.
+    pub fn distinct(mut self) -> Self {
+        self.done = Some(vec![false; self.arr.trees.len()]);
+        self
+    }
 
-    fn next(&mut self) -> Option<RPNStep> {
+    /// Produces the next step as an index into the `trees` of the array,
+    /// instead of as an `RPNStep`.
+    pub fn next_index(&mut self) -> Option<usize> {
+        self.advance().map(|(ix, _)| ix)
+    }
+
+    // the next step and, for explicit proofs, the hypothesis it proves
+    fn advance(&mut self) -> Option<(usize, Option<(StatementAddress, usize)>)> {
         loop {
             let (ix, ohix) = match self.stack.last() {
                 None => return None,
                 Some(&(ix, child)) => (ix, self.arr.trees[ix].children.get(child)),
             };
             if let Some(&hix) = ohix {
-                self.stack.push((hix, 0));
+                if self.done.as_ref().is_some_and(|done| done[hix]) {
+                    self.stack.last_mut().unwrap().1 += 1;
+                } else {
+                    self.stack.push((hix, 0));
+                }
                 continue;
             }
             self.stack.pop();
+            if let Some(ref mut done) = self.done {
+                done[ix] = true;
+            }
             let hyp = if let Some(&mut (lix, ref mut i)) = self.stack.last_mut() {
                 let hyp = if self.explicit {
                     Some((self.arr.trees[lix].address, *i))
@@ -318,13 +418,22 @@ impl<'a> Iterator for NormalIter<'a> {
             } else {
                 None
             };
-            let out = RPNStep::Normal {
+            return Some((ix, hyp));
+        }
+    }
+}
+
+impl<'a> Iterator for NormalIter<'a> {
+    type Item = RPNStep;
+
+    fn next(&mut self) -> Option<RPNStep> {
+        self.advance().map(|(ix, hyp)| {
+            RPNStep::Normal {
                 fwdref: 0,
                 addr: self.arr.trees[ix].address,
                 hyp,
-            };
-            return Some(out);
-        }
+            }
+        })
     }
 }
 
@@ -361,6 +470,12 @@ impl ProofBuilder for ProofTreeArray {
             ix
         })
     }
+
+    /// Unknown steps are represented by a leaf with the null address, which
+    /// does not correspond to any statement.
+    fn unknown(&mut self) -> usize {
+        self.build(StatementAddress::default(), vec![], &[], 0..0)
+    }
 }
 
 /// List of possible proof output types.
@@ -398,6 +513,23 @@ impl ProofStyle {
     }
 }
 
+/// Parses the names used on the command line: `compressed`, `normal`,
+/// `packed`, `explicit`, and `packed-explicit`.
+impl FromStr for ProofStyle {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<ProofStyle, String> {
+        match s {
+            "compressed" => Ok(ProofStyle::Compressed),
+            "normal" => Ok(ProofStyle::Normal),
+            "packed" => Ok(ProofStyle::Packed),
+            "explicit" => Ok(ProofStyle::Explicit),
+            "packed-explicit" => Ok(ProofStyle::PackedExplicit),
+            _ => Err(format!("unknown proof style {}", s)),
+        }
+    }
+}
+
 /// A struct for storing display parameters for printing proofs.
 pub struct ProofTreePrinter<'a> {
     /// The segment set, for looking up statements
@@ -412,7 +544,8 @@ pub struct ProofTreePrinter<'a> {
     pub style: ProofStyle,
     /// The source data
     pub arr: &'a ProofTreeArray,
-    /// The initial cursor character number on the current line
+    /// The initial cursor character number on the current line; if this is
+    /// less than `indent - 1`, the first line is padded up to the indent
     pub initial_chr: u16,
     /// The amount of leading whitespace to print
     pub indent: u16,
@@ -525,18 +658,17 @@ impl<'a, 'b> ProofTreePrinterImpl<'a, 'b> {
         let mut proof_ordered_hyps = vec![];
         let mut proof_ordered: Vec<(StatementRef, usize)> = vec![];
         let frame = self.p.scope.get(self.p.thm_label).unwrap();
+        // the mandatory hypotheses are implicitly numbered first
+        let mand_hyps: Vec<StatementAddress> =
+            frame.hypotheses.iter().map(|hyp| hyp.address()).collect();
         for item in &rpn {
             if let &RPNStep::Normal { addr, .. } = item {
+                if mand_hyps.contains(&addr) {
+                    continue;
+                }
                 let stmt = self.p.sset.statement(addr);
                 let vec = match stmt.statement_type() {
-                    Floating => {
-                        let atom = self.p.nset.var_atom(stmt).unwrap();
-                        if frame.var_list[..frame.mandatory_count].contains(&atom) {
-                            continue;
-                        }
-                        &mut proof_ordered_hyps
-                    }
-                    Essential => &mut proof_ordered_hyps,
+                    Floating | Essential => &mut proof_ordered_hyps,
                     Axiom | Provable => &mut proof_ordered,
                     _ => unreachable!(),
                 };
@@ -553,7 +685,7 @@ impl<'a, 'b> ProofTreePrinterImpl<'a, 'b> {
 
         let mut sorted_by_refs = (0..proof_ordered.len()).collect::<Vec<usize>>();
         sorted_by_refs.sort_by(|&a, &b| proof_ordered[b].1.cmp(&proof_ordered[a].1));
-        let mut i = frame.mandatory_count;
+        let mut i = mand_hyps.len();
         let mut cutoff = 20;
         while cutoff <= i {
             i -= cutoff;
@@ -602,23 +734,14 @@ impl<'a, 'b> ProofTreePrinterImpl<'a, 'b> {
         for item in &rpn {
             let (is_fwdref, mut letter) = match *item {
                 RPNStep::Normal { fwdref, addr, .. } => {
-                    let stmt = self.p.sset.statement(addr);
-                    let pos = if stmt.statement_type() == Floating {
-                        let atom = self.p.nset.var_atom(stmt).unwrap();
-                        frame.var_list[..frame.mandatory_count]
-                            .iter()
-                            .position(|&a| a == atom)
-                    } else {
-                        None
-                    };
                     (fwdref != 0,
-                     pos.unwrap_or_else(|| {
-                        frame.mandatory_count +
+                     mand_hyps.iter().position(|&a| a == addr).unwrap_or_else(|| {
+                        mand_hyps.len() +
                         paren_stmt.iter().position(|s| s.address() == addr).unwrap()
                     }))
                 }
                 RPNStep::Backref { backref, .. } => {
-                    (false, frame.mandatory_count + paren_stmt.len() + backref - 1)
+                    (false, mand_hyps.len() + paren_stmt.len() + backref - 1)
                 }
             };
             let code_start = letters.len();
@@ -656,7 +779,8 @@ impl<'a, 'b> ProofTreePrinterImpl<'a, 'b> {
 
     /// todo doc
     pub fn fmt(&mut self) -> fmt::Result {
-        self.f.write_str(&self.indent[(self.p.initial_chr + 2) as usize..])?;
+        let pad = min(self.p.initial_chr + 2, self.p.indent + 1);
+        self.f.write_str(&self.indent[pad as usize..])?;
 
         match self.p.style {
             ProofStyle::Normal | ProofStyle::Explicit => {
@@ -711,85 +835,151 @@ pub fn knapsack_fit(items: &[usize], values: &[u16], mut size: usize, included:
 
 
 impl<'a> fmt::Display for ProofTreePrinter<'a> {
-    /// todo doc
+    /// Writes the proof in the selected style, followed by the closing `$.`.
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         let mut indent = "\n".to_string();
         for _ in 0..self.indent {
             indent.push(' ');
         }
-        f.write_str(&indent[(self.initial_chr + 2) as usize..])?;
-        let mut chr = self.indent - 1;
-        let parents = self.arr.count_parents();
-
-        let explicit = matches!(self.style, ProofStyle::Explicit |
-            ProofStyle::PackedExplicit);
-
-        let mut stmt_lookup: std::collections::HashMap<StatementAddress, (&str, Vec<&str>)> = std::collections::HashMap::new();
-        for tree in &self.arr.trees {
-            stmt_lookup.entry(tree.address)
-                .or_insert_with(|| {
-                    let label = self.sset.statement(tree.address).label();
-                    (as_str(label),
-                     if explicit {
-                        match self.scope.get(label) {
-                            Some(frame) => {
-                                frame.hypotheses
-                                    .iter()
-                                    .map(|hyp| as_str(self.sset.statement(hyp.address()).label()))
-                                    .collect()
-                            }
-                            None => vec![],
-                        }
-                    } else {
-                        vec![]
-                    })
-                });
+        ProofTreePrinterImpl {
+                p: self,
+                f,
+                indent,
+                chr: max(self.initial_chr, self.indent - 1),
+                stmt_lookup: HashMap::default(),
+                backref_alloc: vec![],
+                backref_max: 0,
+            }
+            .fmt()
+    }
+}
+
+/// Layouts for `ProofListing`, after the `show proof` command of
+/// metamath-exe.
+#[derive(Copy,Clone,Debug,PartialEq,Eq)]
+pub enum ListingStyle {
+    /// `/lemmon` listing: a flat list of steps, each of which refers to the
+    /// steps proving its hypotheses by number.
+    Lemmon,
+    /// The default listing: like `Lemmon`, but each step is indented by its
+    /// distance from the final step.
+    Tree,
+}
+
+/// Parses the names used on the command line: `lemmon` and `tree`.
+impl FromStr for ListingStyle {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<ListingStyle, String> {
+        match s {
+            "lemmon" => Ok(ListingStyle::Lemmon),
+            "tree" => Ok(ListingStyle::Tree),
+            _ => Err(format!("unknown listing style {}", s)),
         }
+    }
+}
 
-        let mut print_step = |item: RPNStep| -> fmt::Result {
-            let estr = |hyp| if explicit {
-                format!("{}=",
-                        match hyp {
-                            Some((stmt, i)) => stmt_lookup[&stmt].1[i],
-                            None => as_str(self.thm_label),
-                        })
-            } else {
-                String::new()
-            };
-            let fmt = match item {
-                RPNStep::Normal { fwdref, addr, hyp } => {
-                    if fwdref == 0 {
-                        format!("{}{}", estr(hyp), stmt_lookup[&addr].0)
-                    } else {
-                        format!("{}:{}{}", fwdref, estr(hyp), stmt_lookup[&addr].0)
-                    }
-                }
-                RPNStep::Backref { backref, hyp } => format!("{}{}", estr(hyp), backref),
-            };
+/// Display parameters for a human-readable listing of the steps of a proof.
+///
+/// Each distinct step is listed once, after the steps proving its
+/// hypotheses, with its step number, the numbers of those steps, the label of
+/// the assertion or hypothesis used and the full substituted expression.
+pub struct ProofListing<'a> {
+    /// The segment set, for looking up statements
+    pub sset: &'a SegmentSet,
+    /// The scoping pass, for finding the `$e` hypotheses of each step
+    pub scope: &'a ScopeResult,
+    /// The source data
+    pub arr: &'a ProofTreeArray,
+    /// The layout of the listing
+    pub style: ListingStyle,
+    /// If true, hide the steps which only build expressions, like the
+    /// `/essential` option of metamath-exe
+    pub essential: bool,
+}
 
-            if chr + (fmt.len() as u16) < self.line_width {
-                chr += (fmt.len() as u16) + 1;
-                f.write_char(' ')?;
-            } else {
-                chr = self.indent + (fmt.len() as u16);
-                f.write_str(&indent)?;
+impl<'a> ProofListing<'a> {
+    /// The children of a step which are shown in the listing.
+    fn shown_children(&self, ix: usize) -> Vec<usize> {
+        let tree = &self.arr.trees[ix];
+        if !self.essential || tree.children.is_empty() {
+            return tree.children.clone();
+        }
+        let label = self.sset.statement(tree.address).label();
+        match self.scope.get(label) {
+            Some(frame) => {
+                tree.children
+                    .iter()
+                    .zip(&*frame.hypotheses)
+                    .filter(|&(_, hyp)| matches!(*hyp, Hyp::Essential(..)))
+                    .map(|(&hix, _)| hix)
+                    .collect()
             }
-            f.write_str(&fmt)
-        };
+            None => vec![],
+        }
+    }
 
-        match self.style {
-            ProofStyle::Compressed => unimplemented!(),
-            ProofStyle::Normal | ProofStyle::Explicit => {
-                for item in self.arr.normal_iter(explicit) {
-                    print_step(item)?;
+    /// The steps to list, in order, as indexes into the `ProofTreeArray`
+    /// paired with the step numbers of their shown hypotheses.  Steps are
+    /// numbered from 1 in this order, which is that of their first
+    /// occurrence in `ProofTreeArray::normal_iter`.
+    pub fn steps(&self) -> Vec<(usize, Vec<usize>)> {
+        let mut order = Vec::new();
+        let mut iter = self.arr.normal_iter(false).distinct();
+        while let Some(ix) = iter.next_index() {
+            order.push(ix);
+        }
+        // a step is shown if the conclusion is reached from it through shown
+        // children; parents come after their children
+        let children: Vec<Vec<usize>> =
+            (0..self.arr.trees.len()).map(|ix| self.shown_children(ix)).collect();
+        let mut shown = vec![false; self.arr.trees.len()];
+        shown[self.arr.qed] = true;
+        for &ix in order.iter().rev() {
+            if shown[ix] {
+                for &hix in &children[ix] {
+                    shown[hix] = true;
                 }
             }
-            ProofStyle::Packed |
-            ProofStyle::PackedExplicit => {
-                for item in self.arr.to_rpn(&parents, explicit) {
-                    print_step(item)?;
+        }
+        let mut number = vec![0; self.arr.trees.len()];
+        let mut out = Vec::new();
+        for ix in order.into_iter().filter(|&ix| shown[ix]) {
+            number[ix] = out.len() + 1;
+            out.push((ix, children[ix].iter().map(|&hix| number[hix]).collect()));
+        }
+        out
+    }
+}
+
+impl<'a> fmt::Display for ProofListing<'a> {
+    /// Writes one line for each step of the proof.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let steps = self.steps();
+        let width = steps.len().to_string().len();
+        for (n, (ix, hyps)) in steps.into_iter().enumerate() {
+            write!(f, "{:>width$} ", n + 1, width = width)?;
+            if self.style == ListingStyle::Tree {
+                for _ in 0..self.arr.indent()[ix] {
+                    f.write_str("  ")?;
                 }
             }
+            if !hyps.is_empty() {
+                let refs: Vec<String> = hyps.iter().map(|hyp| hyp.to_string()).collect();
+                write!(f, "{} ", refs.join(","))?;
+            }
+            // unknown steps have no statement, and an empty expression
+            let expr = &self.arr.exprs[ix];
+            if expr.is_empty() {
+                writeln!(f, "?")?;
+            } else {
+                let stmt = self.sset.statement(self.arr.trees[ix].address);
+                writeln!(f,
+                         "{} {}{}",
+                         as_str(stmt.label()),
+                         as_str(stmt.math_at(0).slice),
+                         as_str(expr))?;
+            }
         }
         Ok(())
     }
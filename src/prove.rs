@@ -0,0 +1,451 @@
+//! Bounded backward-chaining proof search.
+//!
+//! Given a goal in the scope of a `$p` statement, by default the statement's
+//! own conclusion, the prover tries to close it with the `$e` hypotheses of
+//! the statement, or by applying an earlier assertion whose conclusion unifies
+//! with the goal and then proving that assertion's `$e` hypotheses as new
+//! goals.  The assertions are the candidates of the minimizer with axioms
+//! allowed, so those tagged `(New usage is discouraged.)` are never used.
+//!
+//! The search is depth-first with iterative deepening, so the proof found is
+//! one of the shallowest.  Variables of an applied assertion which do not
+//! occur in its conclusion, like `ph` in `ax-mp`, become metavariables which
+//! later goals may bind; goals which are still a bare metavariable are only
+//! matched against the hypotheses, and are solved after the goals which
+//! constrain them.  A complete proof must bind every metavariable and satisfy
+//! the `$d` conditions of each applied assertion using only the `$d`
+//! statements in scope for the theorem; otherwise the search backtracks.
+//!
+//! Each attempt to apply an assertion counts as a step, and the search gives
+//! up when the step or time budget of `ProveOptions` is exhausted.
+
+use crate::grammar::Formula;
+use crate::grammar::Grammar;
+use crate::grammar::Sym;
+use crate::grammar::SymbolScope;
+use crate::minimize::Candidates;
+use crate::minimize::MinimizeOptions;
+use crate::nameck::Atom;
+use crate::nameck::Nameset;
+use crate::parser::Comparer;
+use crate::parser::StatementAddress;
+use crate::parser::StatementRef;
+use crate::proof::ProofTreeArray;
+use crate::scopeck::Frame;
+use crate::scopeck::Hyp;
+use crate::scopeck::ScopeResult;
+use crate::segment_set::SegmentSet;
+use crate::unify::MetaVar;
+use crate::unify::Subst;
+use crate::unify::Term;
+use crate::util::HashSet;
+use crate::util::new_set;
+use crate::verify::ProofBuilder;
+use std::cmp::Ordering;
+use std::fmt;
+use std::time::Duration;
+use std::time::Instant;
+
+/// Limits for a proof search.
+#[derive(Copy,Clone,Debug)]
+pub struct ProveOptions {
+    /// The greatest number of nested assertion applications in a proof, not
+    /// counting syntax; with zero, only the hypotheses are tried.
+    pub max_depth: usize,
+    /// The number of assertion applications to try before giving up.
+    pub max_steps: usize,
+    /// The time to search before giving up, if limited.  This includes the
+    /// time to collect the candidate assertions.
+    pub time_limit: Option<Duration>,
+}
+
+impl Default for ProveOptions {
+    fn default() -> ProveOptions {
+        ProveOptions {
+            max_depth: 4,
+            max_steps: 100_000,
+            time_limit: Some(Duration::from_secs(10)),
+        }
+    }
+}
+
+/// The reasons a proof search can fail.
+#[derive(Clone,Debug,PartialEq,Eq)]
+pub enum ProveError {
+    /// A goal symbol is not a constant or a variable in scope.
+    UnknownSymbol(String),
+    /// The goal does not start with the provable typecode.
+    NotProvable,
+    /// The goal, or a hypothesis of the theorem, does not parse with the
+    /// grammar.
+    NotParsable,
+    /// No proof exists within the depth limit.
+    NotFound,
+    /// The step budget was exhausted.
+    StepLimit,
+    /// The time budget was exhausted.
+    TimeLimit,
+}
+
+impl fmt::Display for ProveError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ProveError::UnknownSymbol(ref tok) => {
+                write!(f, "{}: not a constant or a variable in scope", tok)
+            }
+            ProveError::NotProvable => write!(f, "goal must start with the provable typecode"),
+            ProveError::NotParsable => write!(f, "goal or hypotheses do not parse"),
+            ProveError::NotFound => write!(f, "no proof found within the depth limit"),
+            ProveError::StepLimit => write!(f, "step limit reached"),
+            ProveError::TimeLimit => write!(f, "time limit reached"),
+        }
+    }
+}
+
+/// How a goal was closed.
+#[derive(Clone,Debug)]
+enum Node {
+    /// By a hypothesis of the theorem.
+    Hyp(StatementAddress),
+    /// By an assertion whose variables start at a metavariable, with one slot
+    /// for each of its `$e` hypotheses.
+    Apply(StatementAddress, MetaVar, Vec<usize>),
+}
+
+struct Goal {
+    term: Term,
+    typecode: Atom,
+    slot: usize,
+    depth: usize,
+}
+
+struct Prover<'a> {
+    sset: &'a SegmentSet,
+    nset: &'a Nameset,
+    scope: &'a ScopeResult,
+    grammar: &'a Grammar,
+    cands: &'a Candidates,
+    stmt: StatementRef<'a>,
+    hyps: &'a [(StatementAddress, Formula)],
+    /// Pairs of variables which may be treated as disjoint in this proof.
+    allowed_dv: HashSet<(Atom, Atom)>,
+    options: &'a ProveOptions,
+    deadline: Option<Instant>,
+    steps: usize,
+    stopped: Option<ProveError>,
+    subst: Subst,
+    /// The goal of each proof node and how it was closed, if it has been.
+    slots: Vec<(Term, Option<Node>)>,
+}
+
+impl<'a> Prover<'a> {
+    fn frame(&self, addr: StatementAddress) -> &'a Frame {
+        self.scope.get(self.sset.statement(addr).label()).unwrap()
+    }
+
+    fn check_dv(&self, frame: &Frame, base: MetaVar) -> bool {
+        frame.mandatory_dv.iter().all(|&(left, right)| {
+            let lvars = self.grammar.variables(&self.subst.resolve(&Term::Meta(base + left)));
+            let rvars = self.grammar.variables(&self.subst.resolve(&Term::Meta(base + right)));
+            lvars.iter().all(|&x| {
+                rvars.iter().all(|&y| x != y && self.allowed_dv.contains(&(x, y)))
+            })
+        })
+    }
+
+    // all goals are closed; accept the proof if it is ground and respects $d
+    fn complete(&self) -> bool {
+        self.slots.iter().all(|(term, node)| {
+            self.subst.resolve(term).is_ground() &&
+            match *node {
+                Some(Node::Apply(addr, base, _)) => self.check_dv(self.frame(addr), base),
+                _ => true,
+            }
+        })
+    }
+
+    fn out_of_budget(&mut self) -> bool {
+        self.steps += 1;
+        if self.steps > self.options.max_steps {
+            self.stopped = Some(ProveError::StepLimit);
+        } else if self.deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+            self.stopped = Some(ProveError::TimeLimit);
+        }
+        self.stopped.is_some()
+    }
+
+    fn solve(&mut self, goals: &mut Vec<Goal>) -> bool {
+        if goals.is_empty() {
+            return self.complete();
+        }
+        let pick = goals.iter()
+            .rposition(|goal| !matches!(self.subst.resolve(&goal.term), Term::Meta(_)))
+            .unwrap_or(goals.len() - 1);
+        let goal = goals.remove(pick);
+        if self.try_goal(&goal, goals) {
+            return true;
+        }
+        goals.insert(pick, goal);
+        false
+    }
+
+    fn try_goal(&mut self, goal: &Goal, goals: &mut Vec<Goal>) -> bool {
+        let hyps = self.hyps;
+        for &(addr, ref formula) in hyps {
+            let mark = self.subst.mark();
+            if formula.typecode == goal.typecode &&
+               self.subst.unify(self.grammar, &goal.term, &formula.term) {
+                self.slots[goal.slot].1 = Some(Node::Hyp(addr));
+                if self.solve(goals) {
+                    return true;
+                }
+                self.subst.undo(mark);
+            }
+        }
+
+        let resolved = self.subst.resolve(&goal.term);
+        if goal.depth == 0 || matches!(resolved, Term::Meta(_)) {
+            return false;
+        }
+        let cands = self.cands;
+        for (address, pattern) in cands.matching(&resolved) {
+            if self.sset.order.cmp(&address, &self.stmt.address()) != Ordering::Less ||
+               pattern.target.typecode != goal.typecode {
+                continue;
+            }
+            if self.out_of_budget() {
+                return false;
+            }
+            let base = self.subst.len();
+            let mark = self.subst.mark();
+            for &typecode in &pattern.var_types {
+                self.subst.fresh(typecode);
+            }
+            if self.subst.unify(self.grammar, &goal.term, &pattern.target.term.shift(base)) {
+                let (first, pending) = (self.slots.len(), goals.len());
+                let mut children = Vec::new();
+                for (_, formula) in pattern.essentials() {
+                    let term = formula.term.shift(base);
+                    children.push(self.slots.len());
+                    goals.push(Goal {
+                        term: term.clone(),
+                        typecode: formula.typecode,
+                        slot: self.slots.len(),
+                        depth: goal.depth - 1,
+                    });
+                    self.slots.push((term, None));
+                }
+                self.slots[goal.slot].1 = Some(Node::Apply(address, base, children));
+                if self.solve(goals) {
+                    return true;
+                }
+                goals.truncate(pending);
+                self.slots.truncate(first);
+            }
+            self.subst.undo(mark);
+            self.subst.truncate(base);
+            if self.stopped.is_some() {
+                return false;
+            }
+        }
+        false
+    }
+
+    fn build(&self, arr: &mut ProofTreeArray, slot: usize) -> usize {
+        let (ref term, ref node) = self.slots[slot];
+        let (addr, children) = match *node {
+            Some(Node::Hyp(addr)) => (addr, Vec::new()),
+            Some(Node::Apply(addr, base, ref essentials)) => {
+                let mut essentials = essentials.iter();
+                let children = self.frame(addr)
+                    .hypotheses
+                    .iter()
+                    .map(|hyp| match *hyp {
+                        Hyp::Floating(_, var, _) => {
+                            let value = self.subst.resolve(&Term::Meta(base + var));
+                            self.grammar.build_tree(self.nset, arr, &value)
+                        }
+                        Hyp::Essential(..) => self.build(arr, *essentials.next().unwrap()),
+                    })
+                    .collect();
+                (addr, children)
+            }
+            None => unreachable!("complete proofs close every goal"),
+        };
+        let mut pool = Vec::new();
+        self.grammar.render(self.nset, &self.subst, term, &mut pool);
+        let len = pool.len();
+        arr.build(addr, children, &pool, 0..len)
+    }
+}
+
+// parse a goal as seen from inside the proof of a statement
+fn parse_goal(sset: &SegmentSet,
+              nset: &Nameset,
+              grammar: &Grammar,
+              stmt: StatementRef,
+              text: &str)
+              -> Result<Formula, ProveError> {
+    let mut names = SymbolScope::new(sset, nset, stmt);
+    let mut syms = Vec::new();
+    for tok in text.split_whitespace() {
+        syms.push(names.lookup(tok.as_bytes())
+            .ok_or_else(|| ProveError::UnknownSymbol(tok.to_owned()))?);
+    }
+    match syms.split_first() {
+        Some((&Sym::Const(typecode), rest)) if typecode == grammar.provable_type() => {
+            grammar.parse(typecode, rest).ok_or(ProveError::NotParsable)
+        }
+        _ => Err(ProveError::NotProvable),
+    }
+}
+
+/// Searches for a proof of a goal in the scope of a `$p` statement, or of the
+/// statement itself if no goal is given.
+pub fn prove(sset: &SegmentSet,
+             nset: &Nameset,
+             scope: &ScopeResult,
+             grammar: &Grammar,
+             stmt: StatementRef,
+             goal: Option<&str>,
+             options: &ProveOptions)
+             -> Result<ProofTreeArray, ProveError> {
+    let deadline = options.time_limit.map(|limit| Instant::now() + limit);
+    let frame = scope.get(stmt.label()).ok_or(ProveError::NotParsable)?;
+    let statement = grammar.frame_statement(nset, frame).ok_or(ProveError::NotParsable)?;
+    let goal = match goal {
+        Some(text) => parse_goal(sset, nset, grammar, stmt, text)?,
+        None => statement.target.clone(),
+    };
+    let hyps: Vec<_> = statement.essentials()
+        .map(|(ix, formula)| (frame.hypotheses[ix].address(), formula.clone()))
+        .collect();
+    let mut allowed_dv = new_set();
+    for (index, bits) in frame.optional_dv.iter().enumerate() {
+        for other in bits {
+            allowed_dv.insert((frame.var_list[index], frame.var_list[other]));
+        }
+    }
+    let cand_options = MinimizeOptions {
+        allow_axioms: true,
+        ..MinimizeOptions::default()
+    };
+    let cands = Candidates::new(sset, nset, scope, grammar, &cand_options);
+    let mut prover = Prover {
+        sset,
+        nset,
+        scope,
+        grammar,
+        cands: &cands,
+        stmt,
+        hyps: &hyps,
+        allowed_dv,
+        options,
+        deadline,
+        steps: 0,
+        stopped: None,
+        subst: Subst::new(),
+        slots: Vec::new(),
+    };
+
+    for depth in 0..=options.max_depth {
+        prover.subst = Subst::new();
+        prover.slots = vec![(goal.term.clone(), None)];
+        let mut goals = vec![Goal {
+                                 term: goal.term.clone(),
+                                 typecode: goal.typecode,
+                                 slot: 0,
+                                 depth,
+                             }];
+        if prover.solve(&mut goals) {
+            let mut arr = ProofTreeArray::default();
+            let qed = prover.build(&mut arr, 0);
+            arr.set_qed(qed);
+            return Ok(arr);
+        }
+        if let Some(err) = prover.stopped.take() {
+            return Err(err);
+        }
+    }
+    Err(ProveError::NotFound)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::database::TheoremError;
+    use crate::prove::ProveError;
+    use crate::prove::ProveOptions;
+    use crate::proof::ProofStyle;
+    use crate::test_util::database;
+    use crate::test_util::PROP;
+    use std::time::Duration;
+
+    // a1i and a2i are unproved, and a1d is discouraged
+    const THEOREMS: &str = "
+        ${
+            a1d.1 $e |- ph $.
+            $( (New usage is discouraged.) $)
+            a1d $p |- ( ps -> ph ) $= wph wps wph wi a1d.1 wph wps ax-1 ax-mp $.
+        $}
+        ${
+            a1i.1 $e |- ph $.
+            a1i $p |- ( ps -> ph ) $= ? $.
+        $}
+        ${
+            a2i.1 $e |- ph $.
+            a2i $p |- ( ch -> ( ps -> ph ) ) $= ? $.
+        $}
+    ";
+
+    #[test]
+    fn test_prove() {
+        let mut db = database(&[PROP, THEOREMS].concat());
+        let options = ProveOptions::default();
+
+        // the discouraged a1d would prove a1i in one step
+        let arr = db.prove("a1i", None, &options).unwrap();
+        let sset = db.parse_result().clone();
+        let labels: Vec<_> = arr.trees.iter().map(|tree| sset.statement(tree.address).label()).collect();
+        assert!(labels.contains(&&b"ax-mp"[..]) && !labels.contains(&&b"a1d"[..]));
+        assert_eq!(db.replace_proof("a1i", &arr, ProofStyle::Compressed), Ok(()));
+
+        // a2i needs the proof of a1i, twice
+        let arr = db.prove("a2i", None, &options).unwrap();
+        let a1i = db.statement("a1i").unwrap().address();
+        assert_eq!(arr.trees[arr.qed].address, a1i);
+        assert_eq!(db.replace_proof("a2i", &arr, ProofStyle::Compressed), Ok(()));
+
+        let arr = db.prove("a2i", Some("|- ( ps -> ph )"), &options).unwrap();
+        assert_eq!(arr.trees[arr.qed].address, a1i);
+        assert_eq!(db.prove("a2i", Some("|- ( ph -> ps )"), &options).err(),
+                   Some(TheoremError::Prove(ProveError::NotFound)));
+        assert_eq!(db.prove("a2i", Some("|- ( ph -> th )"), &options).err(),
+                   Some(TheoremError::Prove(ProveError::UnknownSymbol("th".to_owned()))));
+        assert_eq!(db.prove("a2i", Some("wff ph"), &options).err(),
+                   Some(TheoremError::Prove(ProveError::NotProvable)));
+
+        let limited = ProveOptions {
+            max_depth: 0,
+            ..options
+        };
+        assert_eq!(db.prove("a2i", None, &limited).err(),
+                   Some(TheoremError::Prove(ProveError::NotFound)));
+        let limited = ProveOptions {
+            max_steps: 1,
+            ..options
+        };
+        assert_eq!(db.prove("a2i", None, &limited).err(),
+                   Some(TheoremError::Prove(ProveError::StepLimit)));
+        let limited = ProveOptions {
+            time_limit: Some(Duration::from_secs(0)),
+            ..options
+        };
+        assert_eq!(db.prove("a2i", None, &limited).err(),
+                   Some(TheoremError::Prove(ProveError::TimeLimit)));
+
+        assert_eq!(db.prove("nosuch", None, &options).err(),
+                   Some(TheoremError::NoSuchLabel("nosuch".to_owned())));
+        assert_eq!(db.prove("ax-1", None, &options).err(),
+                   Some(TheoremError::NotProvable("ax-1".to_owned())));
+    }
+}
@@ -0,0 +1,210 @@
+//! Renaming of statement labels.
+//!
+//! Renaming a label means rewriting every reference to it: the label of the
+//! statement itself, every normal proof step and compressed proof roster
+//! which names it (including the hypothesis and step parts of packed and
+//! explicit proofs), and every `~ label` link in a comment.  The nameset keeps
+//! an index from each label to the segments which mention it, so only those
+//! segments are scanned for the exact spans, in parallel.
+//!
+//! `Database::rename_label` applies the edits to the source buffers, one
+//! splice per changed segment, and re-checks the database; the changed files
+//! can then be written back with `Database::write_sources`.
+
+use crate::diag::Diagnostic;
+use crate::diag::Notation;
+use crate::nameck::Nameset;
+use crate::parser::is_valid_label;
+use crate::parser::label_mentions;
+use crate::parser::SegmentId;
+use crate::parser::Span;
+use crate::parser::StatementAddress;
+use crate::segment_set::SegmentSet;
+use std::fmt;
+use std::sync::Arc;
+
+/// The reasons a label cannot be renamed.
+#[derive(Clone,Debug,PartialEq,Eq)]
+pub enum RenameError {
+    /// The old label is not the label of any statement.
+    NoSuchLabel(String),
+    /// The new label contains characters not allowed in labels.
+    InvalidLabel(String),
+    /// The new label is already the label of a statement.
+    LabelExists(String),
+    /// The new label is already declared as a math symbol.
+    SymbolExists(String),
+    /// The renamed database had errors which the database did not have before,
+    /// so the rename was undone.  The errors are given with the addresses of
+    /// their statements, which the rename does not change.
+    DoesNotVerify(String, Vec<(StatementAddress, Diagnostic)>),
+}
+
+impl fmt::Display for RenameError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            RenameError::NoSuchLabel(ref label) => write!(f, "{}: no such statement", label),
+            RenameError::InvalidLabel(ref label) => write!(f, "{}: not a valid label", label),
+            RenameError::LabelExists(ref label) => write!(f, "{}: label already exists", label),
+            RenameError::SymbolExists(ref label) => {
+                write!(f, "{}: label is already a math symbol", label)
+            }
+            RenameError::DoesNotVerify(ref label, _) => {
+                write!(f, "{}: renamed database does not verify, rename undone", label)
+            }
+        }
+    }
+}
+
+/// The result of a successful rename.
+pub struct RenameResult {
+    /// The number of occurrences of the label which were rewritten, including
+    /// the label of the statement itself.
+    pub references: usize,
+    /// The names of the source files which were changed, in database order.
+    pub files: Vec<String>,
+    /// The parse, scope, and verify diagnostics of the changed database.  Any
+    /// errors in them were already in the database before the rename.
+    pub diagnostics: Vec<Notation>,
+}
+
+/// Checks that the statement `old` exists and can be renamed to `new`.
+pub fn check_rename(nset: &Nameset, old: &str, new: &str) -> Result<(), RenameError> {
+    if nset.lookup_label(old.as_bytes()).is_none() {
+        return Err(RenameError::NoSuchLabel(old.to_owned()));
+    }
+    if new.is_empty() || !is_valid_label(new.as_bytes()) {
+        return Err(RenameError::InvalidLabel(new.to_owned()));
+    }
+    if nset.lookup_label(new.as_bytes()).is_some() {
+        return Err(RenameError::LabelExists(new.to_owned()));
+    }
+    if nset.lookup_symbol(new.as_bytes()).is_some() {
+        return Err(RenameError::SymbolExists(new.to_owned()));
+    }
+    Ok(())
+}
+
+/// Finds the spans of all references to a label within one segment, in order.
+pub fn segment_references(sset: &SegmentSet, id: SegmentId, label: &[u8]) -> Vec<Span> {
+    let mut out = Vec::new();
+    label_mentions(sset.segment(id), |mention, span| {
+        if mention == label {
+            out.push(span);
+        }
+    });
+    out
+}
+
+/// Finds the references to a label in the segments which the nameset indexes
+/// as mentioning it, with one job per segment.  Segments without references
+/// are omitted.
+pub fn find_references(segments: &Arc<SegmentSet>,
+                       nset: &Nameset,
+                       label: &str)
+                       -> Vec<(SegmentId, Vec<Span>)> {
+    let mut promises = Vec::new();
+    for id in nset.label_mentions(label.as_bytes()) {
+        let segments2 = segments.clone();
+        let label = label.as_bytes().to_owned();
+        promises.push(segments.exec.exec(segments.segment(id).bytes(), move || {
+            (id, segment_references(&segments2, id, &label))
+        }));
+    }
+    promises.into_iter()
+        .map(|promise| promise.wait())
+        .filter(|(_, spans)| !spans.is_empty())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::database::Database;
+    use crate::rename::RenameError;
+    use crate::test_util::database;
+    use crate::test_util::PROP;
+    use crate::test_util::source_text;
+
+    // a1i in three proof styles, and comments which link to ax-1
+    const THEOREMS: &str = "${
+  a1i.1 $e |- ph $.
+  $( Like ~ ax-1 but with ~ ax-mp . $)
+  a1i $p |- ( ps -> ph ) $= ( wi ax-1 ax-mp ) ABADCABEF $.
+  a1i2 $p |- ( ps -> ph ) $=
+    wph wps wph wi a1i.1 wph wps ax-1 ax-mp $.
+  a1i3 $p |- ( ps -> ph ) $=
+    wph=wph wph=wps wps=wph wps=wi min=a1i.1 wph=wph wps=wps maj=ax-1 ax-mp $.
+$}
+";
+
+    fn mkdb() -> Database {
+        let prop = PROP.replace("ax-1 $a", "$( Axiom ~ ax-1 , not ~ ax-10 . $)\nax-1 $a");
+        database(&[&prop, THEOREMS].concat())
+    }
+
+    #[test]
+    fn test_rename() {
+        let mut db = mkdb();
+        assert_eq!(db.rename_label("ax-1", "wi").err(),
+                   Some(RenameError::LabelExists("wi".to_owned())));
+        assert_eq!(db.rename_label("ax-1", "ph").err(),
+                   Some(RenameError::SymbolExists("ph".to_owned())));
+        assert_eq!(db.rename_label("ax-1", "ax:1").err(),
+                   Some(RenameError::InvalidLabel("ax:1".to_owned())));
+        assert_eq!(db.rename_label("ax-2", "ax-3").err(),
+                   Some(RenameError::NoSuchLabel("ax-2".to_owned())));
+
+        let result = db.rename_label("ax-1", "ax-simp").unwrap();
+        assert_eq!(result.references, 6);
+        assert_eq!(result.files, vec!["test.mm"]);
+        assert!(result.diagnostics.is_empty());
+        let renamed = source_text(&mut db);
+        assert!(renamed.contains("$( Axiom ~ ax-simp , not ~ ax-10 . $)\nax-simp $a"));
+        assert!(renamed.contains("$( Like ~ ax-simp but with ~ ax-mp . $)"));
+        assert!(renamed.contains("( wi ax-simp ax-mp ) ABADCABEF"));
+        assert!(renamed.contains("wph wps ax-simp ax-mp $."));
+        assert!(renamed.contains("wps=wps maj=ax-simp ax-mp $."));
+        assert!(db.name_result().label_mentions(b"ax-1").is_empty());
+        assert_eq!(db.name_result().label_mentions(b"ax-simp").len(), 1);
+
+        let result = db.rename_label("min", "min1").unwrap();
+        assert_eq!(result.references, 2);
+        assert!(source_text(&mut db).contains("min1 $e |- ph $."));
+        assert!(source_text(&mut db).contains("min1=a1i.1"));
+        assert!(db.statement("ax-1").is_none());
+        assert!(db.statement("ax-simp").is_some());
+    }
+
+    #[test]
+    fn test_rename_refused() {
+        let mut db = mkdb();
+        let text = source_text(&mut db);
+        assert_eq!(db.rename_label("ax-1", "wff").err(),
+                   Some(RenameError::SymbolExists("wff".to_owned())));
+        assert_eq!(source_text(&mut db), text);
+
+        // errors which were there before do not block the rename
+        let mut db = database(&[PROP, THEOREMS, "bad $p |- ph $= wps $.\n"].concat());
+        let result = db.rename_label("ax-1", "ax-simp").unwrap();
+        assert_eq!(result.references, 5);
+        assert!(!result.diagnostics.is_empty());
+        assert!(db.statement("ax-simp").is_some());
+
+        // a new error undoes the rename: the new label is also a step name
+        let packed = "${\n  pk.1 $e |- ph $.\n  \
+                      pk $p |- ( ps -> ph ) $= wph wps wph 1:wi pk.1 wph wps ax-1 ax-mp $.\n$}\n";
+        let mut db = database(&[PROP, packed].concat());
+        let text = source_text(&mut db);
+        assert!(db.verify_result().diagnostics().is_empty());
+        match db.rename_label("ax-1", "1") {
+            Err(RenameError::DoesNotVerify(label, errors)) => {
+                assert_eq!(label, "ax-1");
+                assert_eq!(errors.len(), 1);
+            }
+            _ => panic!("rename was not undone"),
+        }
+        assert_eq!(source_text(&mut db), text);
+        assert!(db.statement("ax-1").is_some());
+        assert!(db.statement("1").is_none());
+    }
+}
@@ -911,6 +911,7 @@ impl ScopeResult {
 ///
 /// Use `ScopeResult::default()` to get an initial state.
 pub fn scope_check(result: &mut ScopeResult, segments: &Arc<SegmentSet>, names: &Arc<Nameset>) {
+    let saved = (result.incremental, result.generation);
     result.incremental |= result.frame_index.is_empty();
     result.incremental &= segments.options.incremental;
     result.generation += 1;
@@ -929,6 +930,9 @@ pub fn scope_check(result: &mut ScopeResult, segments: &Arc<SegmentSet>, names:
             let id = sref.id;
             let osr = prev.get(&id).and_then(|x| x.clone());
             ssrq.push_back(segments.exec.exec(sref.bytes(), move || {
+                if segments2.exec.is_cancelled() {
+                    return None;
+                }
                 let sref = segments2.segment(id);
                 if let Some(old_res) = osr {
                     if old_res.name_usage.valid(&names) &&
@@ -954,7 +958,8 @@ pub fn scope_check(result: &mut ScopeResult, segments: &Arc<SegmentSet>, names:
         }
     }
 
-    for sref in segments.segments() {
+    let total = ssrq.len();
+    for (done, sref) in segments.segments().into_iter().enumerate() {
         match ssrq.pop_front().unwrap().wait() {
             Some(scoperes) => {
                 to_add.push(scoperes);
@@ -963,6 +968,14 @@ pub fn scope_check(result: &mut ScopeResult, segments: &Arc<SegmentSet>, names:
                 stale_ids.remove(&sref.id);
             }
         }
+        segments.exec.progress("scopeck", done + 1, total);
+    }
+
+    // a cancelled check leaves the previous result untouched
+    if segments.exec.is_cancelled() {
+        result.incremental = saved.0;
+        result.generation = saved.1;
+        return;
     }
 
     for stale_id in stale_ids {
@@ -1055,4 +1068,13 @@ impl ScopeUsage {
             Some(&(gen, _segid, _frix)) => gen <= self.generation,
         }) && self.not_found.iter().all(|name| !res.frame_index.contains_key(name))
     }
+
+    /// Combines the frames used by two readers of the same scope result, so
+    /// that work split across several readers can be tracked as one.
+    pub fn merge(&mut self, other: ScopeUsage) {
+        self.generation = self.generation.min(other.generation);
+        self.incremental &= other.incremental;
+        self.found.extend(other.found);
+        self.not_found.extend(other.not_found);
+    }
 }
@@ -0,0 +1,254 @@
+//! Search for assertions by the form of their statements.
+//!
+//! A query is a math string, such as `|- ( A = B -> ph )`, in which every
+//! variable stands for an arbitrary expression of its type; like `search ...
+//! /all` in metamath-exe, but matching by structure instead of by text.  The
+//! query is parsed with the grammar of the database, taking symbols and the
+//! types of variables from their global declarations in the nameset, and then
+//! unified with the parsed conclusion of each `$a` and `$p` statement, after
+//! renaming the variables of the assertion apart.  Optionally the `$e`
+//! hypotheses of the assertions are tried as well.
+//!
+//! Unification may specialize both sides, so an assertion more general than
+//! the query also matches.  Results are ranked by the number of syntax nodes
+//! which had to be substituted into the assertion to make it match: assertions
+//! which are instances of the query come first, with cost zero.  Assertions
+//! which the grammar cannot parse are skipped.  Segments are searched in
+//! parallel.
+
+use crate::grammar::Formula;
+use crate::grammar::Grammar;
+use crate::grammar::Sym;
+use crate::nameck::Atom;
+use crate::nameck::NameReader;
+use crate::nameck::Nameset;
+use crate::parser::SegmentId;
+use crate::parser::StatementAddress;
+use crate::parser::StatementType;
+use crate::scopeck::ScopeResult;
+use crate::segment_set::SegmentSet;
+use crate::unify::Subst;
+use crate::unify::Term;
+use crate::util::HashMap;
+use crate::util::new_map;
+use std::fmt;
+use std::sync::Arc;
+
+/// The reasons a query cannot be parsed.
+#[derive(Clone,Debug,PartialEq,Eq)]
+pub enum QueryError {
+    /// The query has no symbols after the typecode.
+    Empty,
+    /// The first symbol of the query is not a constant.
+    BadTypecode(String),
+    /// A symbol is not a constant, nor a variable with a global `$f`.
+    UnknownSymbol(String),
+    /// The query is not a well-formed expression of its type.
+    NotParsable,
+}
+
+impl fmt::Display for QueryError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            QueryError::Empty => write!(f, "query must have a typecode and an expression"),
+            QueryError::BadTypecode(ref tok) => write!(f, "{}: typecode is not a constant", tok),
+            QueryError::UnknownSymbol(ref tok) => {
+                write!(f, "{}: not a constant or a variable with a global $f", tok)
+            }
+            QueryError::NotParsable => write!(f, "query does not parse with the grammar"),
+        }
+    }
+}
+
+/// A parsed query, whose variables are metavariables numbered from zero.
+#[derive(Clone,Debug)]
+pub struct Query {
+    formula: Formula,
+    var_types: Vec<Atom>,
+}
+
+impl Query {
+    /// Parses a query from a whitespace separated math string.
+    pub fn parse(nset: &Nameset, grammar: &Grammar, text: &str) -> Result<Query, QueryError> {
+        let mut names = NameReader::new(nset);
+        let mut tokens = text.split_whitespace();
+        let typecode_tok = tokens.next().ok_or(QueryError::Empty)?;
+        let typecode = match nset.lookup_symbol(typecode_tok.as_bytes()) {
+            Some(ref symbol) if symbol.const_address.is_some() => symbol.atom,
+            _ => return Err(QueryError::BadTypecode(typecode_tok.to_owned())),
+        };
+        let mut vars: HashMap<&str, usize> = new_map();
+        let mut var_types = Vec::new();
+        let mut syms = Vec::new();
+        for tok in tokens {
+            if let Some(&var) = vars.get(tok) {
+                syms.push(Sym::Meta(var, var_types[var]));
+            } else if let Some(float) = names.lookup_float(tok.as_bytes()) {
+                vars.insert(tok, var_types.len());
+                syms.push(Sym::Meta(var_types.len(), float.typecode_atom));
+                var_types.push(float.typecode_atom);
+            } else {
+                match nset.lookup_symbol(tok.as_bytes()) {
+                    Some(ref symbol) if symbol.const_address.is_some() => {
+                        syms.push(Sym::Const(symbol.atom))
+                    }
+                    _ => return Err(QueryError::UnknownSymbol(tok.to_owned())),
+                }
+            }
+        }
+        if syms.is_empty() {
+            return Err(QueryError::Empty);
+        }
+        let formula = grammar.parse(typecode, &syms).ok_or(QueryError::NotParsable)?;
+        Ok(Query {
+            formula,
+            var_types,
+        })
+    }
+}
+
+/// An assertion which matches a query.
+#[derive(Copy,Clone,Debug,PartialEq,Eq)]
+pub struct SearchMatch {
+    /// The `$a` or `$p` statement.
+    pub address: StatementAddress,
+    /// The `$e` hypothesis which matched, if the conclusion did not.
+    pub hypothesis: Option<StatementAddress>,
+    /// The number of syntax nodes substituted into the assertion.
+    pub cost: usize,
+}
+
+// the number of syntax axiom applications in a term, ignoring metavariables
+fn app_count(term: &Term) -> usize {
+    match *term {
+        Term::App(_, ref children) => 1 + children.iter().map(app_count).sum::<usize>(),
+        Term::Meta(_) => 0,
+    }
+}
+
+fn search_segment(sset: &SegmentSet,
+                  nset: &Nameset,
+                  scope: &ScopeResult,
+                  grammar: &Grammar,
+                  query: &Query,
+                  hypotheses: bool,
+                  id: SegmentId)
+                  -> Vec<SearchMatch> {
+    let mut out = Vec::new();
+    for stmt in sset.segment(id) {
+        if stmt.statement_type() != StatementType::Axiom &&
+           stmt.statement_type() != StatementType::Provable {
+            continue;
+        }
+        let frame = match scope.get(stmt.label()) {
+            Some(frame) if frame.valid.start == stmt.address() => frame,
+            _ => continue,
+        };
+        let pattern = match grammar.frame_pattern(nset, frame) {
+            Some(pattern) => pattern,
+            None => continue,
+        };
+        let base = query.var_types.len();
+        let mut subst = Subst::new();
+        for &typecode in query.var_types.iter().chain(&pattern.var_types) {
+            subst.fresh(typecode);
+        }
+        let mut targets = vec![(None, &pattern.target)];
+        if hypotheses {
+            targets.extend(pattern.essentials()
+                .map(|(ix, formula)| (Some(frame.hypotheses[ix].address()), formula)));
+        }
+        for (hypothesis, formula) in targets {
+            if formula.typecode != query.formula.typecode {
+                continue;
+            }
+            if subst.unify(grammar, &query.formula.term, &formula.term.shift(base)) {
+                let cost = (base..subst.len())
+                    .map(|var| app_count(&subst.resolve(&Term::Meta(var))))
+                    .sum();
+                out.push(SearchMatch {
+                    address: stmt.address(),
+                    hypothesis,
+                    cost,
+                });
+                break;
+            }
+        }
+    }
+    out
+}
+
+/// Finds the assertions matching a query, best first, and in database order
+/// among those of equal cost.  An assertion whose conclusion matches ranks
+/// before one of equal cost matched by a hypothesis.
+pub fn search(segments: &Arc<SegmentSet>,
+              nset: &Arc<Nameset>,
+              scope: &Arc<ScopeResult>,
+              grammar: &Arc<Grammar>,
+              query: &Query,
+              hypotheses: bool)
+              -> Vec<SearchMatch> {
+    let mut promises = Vec::new();
+    for sref in segments.segments() {
+        let segments2 = segments.clone();
+        let nset = nset.clone();
+        let scope = scope.clone();
+        let grammar = grammar.clone();
+        let query = query.clone();
+        let id = sref.id;
+        promises.push(segments.exec.exec(sref.bytes(), move || {
+            search_segment(&segments2, &nset, &scope, &grammar, &query, hypotheses, id)
+        }));
+    }
+    let mut matches: Vec<SearchMatch> =
+        promises.into_iter().flat_map(|promise| promise.wait()).collect();
+    matches.sort_by_key(|found| (found.cost, found.hypothesis.is_some()));
+    matches
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::search::QueryError;
+    use crate::test_util::database;
+    use crate::test_util::A1I;
+    use crate::test_util::PROP;
+
+    #[test]
+    fn test_search() {
+        let text = "$c -. $.
+            wn $a wff -. ph $.
+            ax-3 $a |- ( ( -. ph -> -. ps ) -> ( ps -> ph ) ) $.
+            ${
+                a1n.1 $e |- -. ph $.
+                a1n $p |- ( ps -> -. ph ) $= wn wps wph wn a1n.1 a1i $.
+            $}";
+        let mut db = database(&[PROP, A1I, text].concat());
+        let sset = db.parse_result().clone();
+        let mut labels = |query: &str, hypotheses: bool| {
+            db.search(query, hypotheses).map(|found| {
+                found.into_iter()
+                    .map(|found| {
+                        (String::from_utf8(sset.statement(found.address).label().to_vec())
+                             .unwrap(),
+                         found.cost)
+                    })
+                    .collect::<Vec<_>>()
+            })
+        };
+        let owned = |list: &[(&str, usize)]| {
+            Ok(list.iter().map(|&(label, cost)| (label.to_owned(), cost)).collect())
+        };
+
+        assert_eq!(labels("|- ( ch -> -. ph )", false),
+                   owned(&[("a1n", 0), ("a1i", 1), ("ax-mp", 2)]));
+        assert_eq!(labels("|- ( ph -> ( ps -> ch ) )", false),
+                   owned(&[("ax-1", 0), ("ax-3", 0), ("a1i", 1), ("ax-mp", 2)]));
+        assert_eq!(labels("|- -. ph", true),
+                   owned(&[("a1n", 0), ("ax-mp", 1), ("a1i", 1)]));
+        assert_eq!(labels("|- ( ph", false), Err(QueryError::NotParsable));
+        assert_eq!(labels("|- ( ph -> th )", false),
+                   Err(QueryError::UnknownSymbol("th".to_owned())));
+        assert_eq!(labels("ph", false), Err(QueryError::BadTypecode("ph".to_owned())));
+        assert_eq!(labels("|-", false), Err(QueryError::Empty));
+    }
+}
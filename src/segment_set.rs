@@ -225,6 +225,78 @@ impl SegmentSet {
         out
     }
 
+    /// Replaces a span of text in one segment, and reparses that segment.
+    ///
+    /// `span` is relative to the segment's buffer, like the spans reported by
+    /// the parser, and must lie within the statements of the segment.  Only
+    /// the text of this segment is reparsed, and the result keeps the segment
+    /// ID, so analysis passes can reuse their results for all other segments.
+    /// The source information of every segment read from the same file is
+    /// updated to refer to the changed text, which is not written to disk; the
+    /// change will be lost at the next `read` if the file is reloaded.
+    ///
+    /// Panics if the change would add or remove a file inclusion.
+    pub fn splice(&mut self, seg_id: SegmentId, span: Span, text: &[u8]) {
+        let (seg, sinfo) = self.segments[&seg_id].clone();
+        let seg_span = seg.span();
+        assert!(seg_span.start <= span.start && span.end <= seg_span.end,
+                "splice must stay within one segment");
+
+        let mut buf = seg.buffer[seg_span.start as usize..span.start as usize].to_owned();
+        buf.extend_from_slice(text);
+        buf.extend_from_slice(&seg.buffer[span.end as usize..seg_span.end as usize]);
+        let buf = Arc::new(buf);
+        let mut parsed = parser::parse_segments(&buf);
+        // a segment which ends with an inclusion parses to itself followed by an
+        // empty segment
+        assert_eq!(parsed.len(),
+                   if seg.next_file.is_null() { 1 } else { 2 },
+                   "splice must not add or remove file inclusions");
+        let new_seg = parsed.swap_remove(0);
+        assert_eq!(new_seg.next_file.is_null(), seg.next_file.is_null(),
+                   "splice must not add or remove file inclusions");
+
+        // positions within the file
+        let base = sinfo.span.start as usize;
+        let edit_start = base + span.start as usize;
+        let edit_end = base + span.end as usize;
+        let delta = text.len() as isize - (edit_end - edit_start) as isize;
+        let shift = |pos: usize| if pos >= edit_end { (pos as isize + delta) as usize } else { pos };
+
+        let mut ftext = sinfo.text[..edit_start].to_owned();
+        ftext.extend_from_slice(text);
+        ftext.extend_from_slice(&sinfo.text[edit_end..]);
+        let ftext = Arc::new(ftext);
+
+        for (&id, entry) in self.segments.iter_mut() {
+            if id == seg_id || !Arc::ptr_eq(&entry.1.text, &sinfo.text) {
+                continue;
+            }
+            // other segments in the same slice are positioned relative to the
+            // start of the slice, but move with their own content
+            let old = &entry.1;
+            let content = old.span.start as usize + entry.0.span().start as usize;
+            let start = if content >= edit_end {
+                (old.span.start as isize + delta) as usize
+            } else {
+                old.span.start as usize
+            };
+            entry.1 = Arc::new(SourceInfo {
+                name: old.name.clone(),
+                text: ftext.clone(),
+                span: Span::new(start, shift(old.span.end as usize)),
+            });
+        }
+
+        let seg_base = base + seg_span.start as usize;
+        let new_sinfo = SourceInfo {
+            name: sinfo.name.clone(),
+            text: ftext,
+            span: Span::new(seg_base, seg_base + buf.len()),
+        };
+        self.segments.insert(seg_id, (new_seg, Arc::new(new_sinfo)));
+    }
+
     /// Replaces the content of the `SegmentSet` with data loaded from disk
     /// files or memory.
     ///
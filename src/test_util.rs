@@ -0,0 +1,50 @@
+//! Databases shared by the unit tests of several modules.
+
+use crate::database::Database;
+use crate::database::DbOptions;
+use std::env;
+use std::path::PathBuf;
+use std::process;
+
+/// Implication with `ax-1` and modus ponens, the start of most test databases.
+pub const PROP: &str = "$c ( ) -> wff |- $.
+$v ph ps ch $.
+wph $f wff ph $.
+wps $f wff ps $.
+wch $f wff ch $.
+wi $a wff ( ph -> ps ) $.
+ax-1 $a |- ( ph -> ( ps -> ph ) ) $.
+${
+  min $e |- ph $.
+  maj $e |- ( ph -> ps ) $.
+  ax-mp $a |- ps $.
+$}
+";
+
+/// The inference `a1i`, proved from `PROP` in normal style.
+pub const A1I: &str = "${
+  a1i.1 $e |- ph $.
+  a1i $p |- ( ps -> ph ) $= wph wps wph wi a1i.1 wph wps ax-1 ax-mp $.
+$}
+";
+
+/// Loads a database from the text of a single file, `test.mm`.
+pub fn database(text: &str) -> Database {
+    let mut db = Database::new(DbOptions::default());
+    db.parse("test.mm".to_owned(), vec![("test.mm".to_owned(), text.as_bytes().to_vec())]);
+    db
+}
+
+/// Returns the text of the first segment of a database, which holds all of
+/// `test.mm` for the databases of `database`.
+pub fn source_text(db: &mut Database) -> String {
+    let sset = db.parse_result().clone();
+    let text = sset.source_info(sset.segments()[0].id).text.clone();
+    String::from_utf8(text.to_vec()).unwrap()
+}
+
+/// A path in the temporary directory for a test to write to, unique to this
+/// process so that concurrent test runs do not collide.
+pub fn temp_path(name: &str) -> PathBuf {
+    env::temp_dir().join(format!("smetamath-test-{}-{}", process::id(), name))
+}
@@ -0,0 +1,330 @@
+//! Step-by-step traces of the verification of a proof.
+//!
+//! `Tracer` is a `ProofBuilder` which records every assertion applied by a
+//! proof, together with the substitution the verifier chose for its variables,
+//! the earlier results it consumed as hypotheses, and the `$d` conditions it
+//! checked.  The result is a `ProofTrace`, which is intended for tools that
+//! need to show exactly what the verifier did, such as proof visualizers.
+//!
+//! The substitution is recovered from the results matched with the `$f`
+//! hypotheses of each assertion, in the same way as the verifier builds it, so
+//! the hot path of the verifier is not slowed down by tracing.
+
+use crate::diag::Diagnostic;
+use crate::nameck::Nameset;
+use crate::parser::StatementAddress;
+use crate::parser::StatementRef;
+use crate::parser::StatementType;
+use crate::parser::SymbolType;
+use crate::parser::Token;
+use crate::scopeck::Frame;
+use crate::scopeck::Hyp::{Essential, Floating};
+use crate::scopeck::ScopeResult;
+use crate::segment_set::SegmentSet;
+use crate::verify::ProofBuilder;
+use crate::verify::verify_one;
+use std::ops::Range;
+use std::slice;
+
+/// Where a value consumed by a proof step came from.
+#[derive(Clone,Copy,Debug,Eq,PartialEq)]
+pub enum TraceSource {
+    /// A `$e` or `$f` statement referenced directly by the proof.
+    Hypothesis(StatementAddress),
+    /// The result of an earlier step, as an index into `ProofTrace::steps`.
+    Step(usize),
+    /// A `?` step, standing for an unknown subproof.
+    Unknown,
+}
+
+/// A value matched with one hypothesis of an assertion.
+#[derive(Clone,Debug,Eq,PartialEq)]
+pub struct TraceHyp {
+    /// The `$e` or `$f` hypothesis of the assertion.
+    pub hypothesis: StatementAddress,
+    /// Where the value came from.
+    pub source: TraceSource,
+    /// The value, including its typecode.
+    pub expr: Token,
+}
+
+/// A `$d` condition of an assertion, as checked after substitution.
+#[derive(Clone,Debug,Eq,PartialEq)]
+pub struct DvCheck {
+    /// The variables of the assertion which must be disjoint.
+    pub vars: (Token, Token),
+    /// The expressions substituted for them.
+    pub exprs: (Token, Token),
+    /// Every pair of variables of the two expressions, which the extended
+    /// frame of the proof must declare disjoint.
+    pub pairs: Vec<(Token, Token)>,
+}
+
+/// One application of an axiom or theorem in a proof.
+#[derive(Clone,Debug,Eq,PartialEq)]
+pub struct TraceStep {
+    /// The assertion applied.
+    pub assertion: StatementAddress,
+    /// The expression substituted for each mandatory variable, in frame order.
+    pub substitution: Vec<(Token, Token)>,
+    /// The values consumed, in the order of the hypotheses of the assertion.
+    pub hypotheses: Vec<TraceHyp>,
+    /// The `$d` conditions of the assertion.
+    pub dv_checks: Vec<DvCheck>,
+    /// The substituted conclusion, including its typecode.
+    pub result: Token,
+}
+
+/// The steps of a proof in the order the verifier executed them.
+#[derive(Clone,Debug,Default)]
+pub struct ProofTrace {
+    steps: Vec<TraceStep>,
+}
+
+impl ProofTrace {
+    /// Verifies the proof of a `$p` statement, recording its steps.
+    ///
+    /// Fails with the verifier's diagnostic if the proof is not valid; use a
+    /// `Tracer` directly to keep the steps executed before the error.
+    pub fn new(sset: &SegmentSet,
+               nset: &Nameset,
+               scopes: &ScopeResult,
+               stmt: StatementRef)
+               -> Result<ProofTrace, Diagnostic> {
+        let mut tracer = Tracer::new(sset, nset, scopes);
+        verify_one(sset, nset, scopes, &mut tracer, stmt)?;
+        Ok(tracer.into_trace())
+    }
+
+    /// All steps, in execution order; the last one proves the statement.
+    pub fn steps(&self) -> &[TraceStep] {
+        &self.steps
+    }
+
+    /// Iterate over the steps in execution order.
+    pub fn iter(&self) -> slice::Iter<'_, TraceStep> {
+        self.steps.iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a ProofTrace {
+    type Item = &'a TraceStep;
+    type IntoIter = slice::Iter<'a, TraceStep>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// A value which the verifier can push on its stack.
+#[derive(Clone,Debug)]
+struct TraceNode {
+    source: TraceSource,
+    expr: Token,
+}
+
+/// The proof builder which collects a `ProofTrace`.
+pub struct Tracer<'a> {
+    sset: &'a SegmentSet,
+    nset: &'a Nameset,
+    scopes: &'a ScopeResult,
+    nodes: Vec<TraceNode>,
+    trace: ProofTrace,
+}
+
+impl<'a> Tracer<'a> {
+    /// Creates a tracer for proofs of the given database.
+    pub fn new(sset: &'a SegmentSet, nset: &'a Nameset, scopes: &'a ScopeResult) -> Tracer<'a> {
+        Tracer {
+            sset,
+            nset,
+            scopes,
+            nodes: Vec::new(),
+            trace: ProofTrace::default(),
+        }
+    }
+
+    /// Returns the steps recorded so far.
+    pub fn into_trace(self) -> ProofTrace {
+        self.trace
+    }
+
+    // the symbols of an expression which are variables
+    fn variables(&self, expr: &[u8]) -> Vec<Token> {
+        expr.split(|&chr| chr == b' ')
+            .filter(|&sym| {
+                self.nset.lookup_symbol(sym).map(|lookup| lookup.stype) ==
+                Some(SymbolType::Variable)
+            })
+            .map(Token::from)
+            .collect()
+    }
+
+    fn dv_checks(&self, frame: &Frame, substitution: &[(Token, Token)]) -> Vec<DvCheck> {
+        let mut checks = Vec::new();
+        for &(ix1, ix2) in &*frame.mandatory_dv {
+            let (ref var1, ref expr1) = substitution[ix1];
+            let (ref var2, ref expr2) = substitution[ix2];
+            let mut pairs = Vec::new();
+            for sym1 in self.variables(expr1) {
+                for sym2 in self.variables(expr2) {
+                    pairs.push((sym1.clone(), sym2));
+                }
+            }
+            checks.push(DvCheck {
+                vars: (var1.clone(), var2.clone()),
+                exprs: (expr1.clone(), expr2.clone()),
+                pairs,
+            });
+        }
+        checks
+    }
+}
+
+// convert a math string from the verifier's format to space-separated tokens,
+// after a typecode
+fn readable(typecode: &[u8], raw: &[u8]) -> Token {
+    let mut out = typecode.to_vec();
+    out.push(b' ');
+    for &chr in raw {
+        out.push(chr & 0x7F);
+        if chr & 0x80 != 0 {
+            out.push(b' ');
+        }
+    }
+    out.pop();
+    out.into_boxed_slice()
+}
+
+// the expression after the typecode
+fn strip_typecode(expr: &[u8]) -> Token {
+    match expr.iter().position(|&chr| chr == b' ') {
+        Some(pos) => expr[pos + 1..].into(),
+        None => Token::default(),
+    }
+}
+
+impl<'a> ProofBuilder for Tracer<'a> {
+    type Item = usize;
+    type Accum = Vec<usize>;
+
+    fn push(&mut self, hyps: &mut Vec<usize>, hyp: usize) {
+        hyps.push(hyp);
+    }
+
+    fn build(&mut self,
+             addr: StatementAddress,
+             hyps: Vec<usize>,
+             pool: &[u8],
+             expr: Range<usize>)
+             -> usize {
+        let stmt = self.sset.statement(addr);
+        let expr = readable(stmt.math_at(0).slice, &pool[expr]);
+        let stype = stmt.statement_type();
+        let frame = match self.scopes.get(stmt.label()) {
+            Some(frame) if stype == StatementType::Axiom || stype == StatementType::Provable => {
+                frame
+            }
+            _ => {
+                self.nodes.push(TraceNode {
+                    source: TraceSource::Hypothesis(addr),
+                    expr,
+                });
+                return self.nodes.len() - 1;
+            }
+        };
+
+        let mut substitution = vec![Default::default(); frame.mandatory_count];
+        let mut consumed = Vec::new();
+        for (hyp, &node) in frame.hypotheses.iter().zip(&hyps) {
+            let node = &self.nodes[node];
+            match *hyp {
+                Floating(_, var_index, _) => {
+                    substitution[var_index] = (self.nset.atom_name(frame.var_list[var_index])
+                                                   .into(),
+                                               strip_typecode(&node.expr));
+                }
+                Essential(..) => {}
+            }
+            consumed.push(TraceHyp {
+                hypothesis: hyp.address(),
+                source: node.source,
+                expr: node.expr.clone(),
+            });
+        }
+        let dv_checks = self.dv_checks(frame, &substitution);
+
+        self.trace.steps.push(TraceStep {
+            assertion: addr,
+            substitution,
+            hypotheses: consumed,
+            dv_checks,
+            result: expr.clone(),
+        });
+        self.nodes.push(TraceNode {
+            source: TraceSource::Step(self.trace.steps.len() - 1),
+            expr,
+        });
+        self.nodes.len() - 1
+    }
+
+    fn unknown(&mut self) -> usize {
+        self.nodes.push(TraceNode {
+            source: TraceSource::Unknown,
+            expr: b"?"[..].into(),
+        });
+        self.nodes.len() - 1
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::database::TheoremError;
+    use crate::parser::as_str;
+    use crate::test_util::database;
+    use crate::test_util::PROP;
+    use crate::trace::TraceSource;
+
+    #[test]
+    fn test_trace() {
+        let text = "${ $d ph ps $. ax-dv $a |- ( ph -> ps ) $. $}
+            ${
+                $d ph ch $. $d ps ch $.
+                th.1 $e |- ph $.
+                th $p |- ( ( ph -> ps ) -> ch ) $= wph wps wi wch ax-dv $.
+            $}";
+        let mut db = database(&[PROP, text].concat());
+        let trace = db.proof_trace("th").unwrap();
+        let labels: Vec<_> = trace.iter()
+            .map(|step| as_str(db.parse_result().statement(step.assertion).label()).to_owned())
+            .collect();
+        assert_eq!(labels, vec!["wi", "ax-dv"]);
+
+        let step = &trace.steps()[1];
+        assert_eq!(as_str(&step.result), "|- ( ( ph -> ps ) -> ch )");
+        let subst: Vec<_> = step.substitution
+            .iter()
+            .map(|(var, expr)| (as_str(var), as_str(expr)))
+            .collect();
+        assert_eq!(subst, vec![("ph", "( ph -> ps )"), ("ps", "ch")]);
+        assert_eq!(step.hypotheses[0].source, TraceSource::Step(0));
+        assert_eq!(step.hypotheses[1].source,
+                   TraceSource::Hypothesis(db.statement("wch").unwrap().address()));
+        assert_eq!(step.dv_checks.len(), 1);
+        let pairs: Vec<_> = step.dv_checks[0]
+            .pairs
+            .iter()
+            .map(|(var1, var2)| (as_str(var1), as_str(var2)))
+            .collect();
+        assert_eq!(pairs, vec![("ph", "ch"), ("ps", "ch")]);
+    }
+
+    #[test]
+    fn test_trace_not_provable() {
+        let mut db = database(PROP);
+        assert_eq!(db.proof_trace("ax-mp").err(),
+                   Some(TheoremError::NotProvable("ax-mp".to_owned())));
+        assert_eq!(db.proof_trace("nosuch").err(),
+                   Some(TheoremError::NoSuchLabel("nosuch".to_owned())));
+    }
+}
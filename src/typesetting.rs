@@ -0,0 +1,294 @@
+//! Typesetting data from the `$t` comments of a database.
+//!
+//! A `$t` comment is a sequence of commands, each terminated by `;`:
+//!
+//! * `htmldef "sym" as "..." ;`, `althtmldef "sym" as "..." ;` and
+//!   `latexdef "sym" as "..." ;` give the rendering of a math symbol in each
+//!   output format;
+//! * any other keyword followed by a string, such as `htmltitle "..." ;`,
+//!   is recorded as a setting for the generators to interpret.
+//!
+//! Strings are quoted with `'` or `"`, a doubled quote stands for itself, and
+//! several strings can be joined with `+`.  `/* ... */` comments are allowed
+//! between tokens.  A malformed command is reported and skipped, up to the
+//! next `;`.  When a symbol or setting is defined more than once, the last
+//! definition wins.
+//!
+//! This pass is not incremental; `$t` comments are generally small and rare.
+
+use crate::diag::Diagnostic;
+use crate::parser::Span;
+use crate::parser::StatementAddress;
+use crate::parser::StatementType;
+use crate::parser::Token;
+use crate::segment_set::SegmentSet;
+use crate::util::HashMap;
+
+/// The typesetting definitions of a database.
+#[derive(Default,Debug,Clone)]
+pub struct Typesetting {
+    html: HashMap<Token, String>,
+    alt_html: HashMap<Token, String>,
+    latex: HashMap<Token, String>,
+    settings: HashMap<String, String>,
+    diagnostics: Vec<(StatementAddress, Diagnostic)>,
+}
+
+/// A lexical token of a `$t` comment, with its position in the buffer.
+#[derive(Debug,PartialEq,Eq)]
+enum Lexeme {
+    Word(Vec<u8>),
+    Str(Vec<u8>),
+    Plus,
+    Semi,
+    /// An unterminated string or comment, which ends the command list
+    Bad,
+}
+
+struct Lexer<'a> {
+    buffer: &'a [u8],
+    pos: usize,
+    end: usize,
+    /// True if the last lexeme returned was `;`
+    after_semi: bool,
+}
+
+impl<'a> Lexer<'a> {
+    fn skip_space(&mut self) -> bool {
+        loop {
+            while self.pos < self.end && self.buffer[self.pos].is_ascii_whitespace() {
+                self.pos += 1;
+            }
+            if !self.buffer[self.pos..self.end].starts_with(b"/*") {
+                return true;
+            }
+            match self.buffer[self.pos + 2..self.end].windows(2).position(|w| w == b"*/") {
+                Some(len) => self.pos += len + 4,
+                None => return false,
+            }
+        }
+    }
+
+    /// Returns the next lexeme and its starting position.
+    fn next(&mut self) -> Option<(Lexeme, usize)> {
+        let out = self.read();
+        self.after_semi = matches!(out, Some((Lexeme::Semi, _)));
+        out
+    }
+
+    fn read(&mut self) -> Option<(Lexeme, usize)> {
+        if !self.skip_space() {
+            return Some((Lexeme::Bad, self.pos));
+        }
+        if self.pos == self.end {
+            return None;
+        }
+        let start = self.pos;
+        let lexeme = match self.buffer[start] {
+            b'+' => {
+                self.pos += 1;
+                Lexeme::Plus
+            }
+            b';' => {
+                self.pos += 1;
+                Lexeme::Semi
+            }
+            quote @ b'"' | quote @ b'\'' => {
+                let mut out = Vec::new();
+                self.pos += 1;
+                loop {
+                    if self.pos == self.end {
+                        return Some((Lexeme::Bad, start));
+                    }
+                    let chr = self.buffer[self.pos];
+                    self.pos += 1;
+                    if chr == quote {
+                        if self.pos < self.end && self.buffer[self.pos] == quote {
+                            self.pos += 1;
+                        } else {
+                            break;
+                        }
+                    }
+                    out.push(chr);
+                }
+                Lexeme::Str(out)
+            }
+            _ => {
+                while self.pos < self.end && !self.buffer[self.pos].is_ascii_whitespace() &&
+                      !b";+\"'".contains(&self.buffer[self.pos]) {
+                    self.pos += 1;
+                }
+                Lexeme::Word(self.buffer[start..self.pos].to_vec())
+            }
+        };
+        Some((lexeme, start))
+    }
+}
+
+// a string, or several joined with `+`
+fn read_string(lexer: &mut Lexer) -> Option<(String, Option<(Lexeme, usize)>)> {
+    let mut out = Vec::new();
+    loop {
+        match lexer.next() {
+            Some((Lexeme::Str(text), _)) => out.extend(text),
+            _ => return None,
+        }
+        match lexer.next() {
+            Some((Lexeme::Plus, _)) => {}
+            other => return Some((String::from_utf8_lossy(&out).into_owned(), other)),
+        }
+    }
+}
+
+impl Typesetting {
+    /// Collects the typesetting definitions from every `$t` comment of a
+    /// database, in database order.
+    pub fn new(sset: &SegmentSet) -> Typesetting {
+        let mut out = Typesetting::default();
+        for sref in sset.segments() {
+            for stmt in sref {
+                if stmt.statement_type() == StatementType::TypesettingComment {
+                    let span = stmt.span();
+                    let buffer = &sref.segment.buffer;
+                    let text = span.as_ref(buffer);
+                    // skip `$(` and `$t`, and stop before `$)`
+                    let start = match text.windows(2).position(|w| w == b"$t") {
+                        Some(pos) => span.start as usize + pos + 2,
+                        None => continue,
+                    };
+                    let end = if text.ends_with(b"$)") {
+                        span.end as usize - 2
+                    } else {
+                        span.end as usize
+                    };
+                    out.read_commands(stmt.address(), buffer, start, end);
+                }
+            }
+        }
+        out
+    }
+
+    fn read_commands(&mut self,
+                     address: StatementAddress,
+                     buffer: &[u8],
+                     start: usize,
+                     end: usize) {
+        let mut lexer = Lexer {
+            buffer,
+            pos: start,
+            end,
+            after_semi: false,
+        };
+        while let Some((lexeme, start)) = lexer.next() {
+            if self.read_command(&mut lexer, lexeme) {
+                continue;
+            }
+            let span = Span::new(start, lexer.pos.max(start + 1).min(end));
+            self.diagnostics.push((address, Diagnostic::TypesettingSyntax(span)));
+            // resynchronize after the next `;`, unless the error was at one
+            while !lexer.after_semi {
+                match lexer.next() {
+                    Some((Lexeme::Semi, _)) => break,
+                    None | Some((Lexeme::Bad, _)) => return,
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    // reads one command after its keyword; false if it is malformed
+    fn read_command(&mut self, lexer: &mut Lexer, keyword: Lexeme) -> bool {
+        let keyword = match keyword {
+            Lexeme::Word(word) => String::from_utf8_lossy(&word).into_owned(),
+            _ => return false,
+        };
+        let map = match &*keyword {
+            "htmldef" => &mut self.html,
+            "althtmldef" => &mut self.alt_html,
+            "latexdef" => &mut self.latex,
+            _ => {
+                return match read_string(lexer) {
+                    Some((value, Some((Lexeme::Semi, _)))) => {
+                        self.settings.insert(keyword, value);
+                        true
+                    }
+                    _ => false,
+                };
+            }
+        };
+        let symbol = match read_string(lexer) {
+            Some((symbol, Some((Lexeme::Word(ref word), _)))) if word == b"as" => symbol,
+            _ => return false,
+        };
+        match read_string(lexer) {
+            Some((value, Some((Lexeme::Semi, _)))) => {
+                map.insert(symbol.into_bytes().into_boxed_slice(), value);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// The HTML for a math symbol, from `htmldef`.
+    pub fn html_def(&self, symbol: &[u8]) -> Option<&str> {
+        self.html.get(symbol).map(|def| &**def)
+    }
+
+    /// The alternative HTML for a math symbol, from `althtmldef`; databases
+    /// generally use this for Unicode renderings.
+    pub fn alt_html_def(&self, symbol: &[u8]) -> Option<&str> {
+        self.alt_html.get(symbol).map(|def| &**def)
+    }
+
+    /// Iterate over the symbols which have an `althtmldef`, with their HTML.
+    pub fn alt_html_defs(&self) -> impl Iterator<Item = (&[u8], &str)> {
+        self.alt_html.iter().map(|(symbol, def)| (&**symbol, &**def))
+    }
+
+    /// The LaTeX for a math symbol, from `latexdef`.
+    pub fn latex_def(&self, symbol: &[u8]) -> Option<&str> {
+        self.latex.get(symbol).map(|def| &**def)
+    }
+
+    /// The value of a setting such as `htmltitle`.
+    pub fn setting(&self, name: &str) -> Option<&str> {
+        self.settings.get(name).map(|value| &**value)
+    }
+
+    /// Report the malformed commands found in the `$t` comments.
+    pub fn diagnostics(&self) -> Vec<(StatementAddress, Diagnostic)> {
+        self.diagnostics.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::database::Database;
+    use crate::database::DbOptions;
+    use crate::diag::Diagnostic;
+
+    #[test]
+    fn test_typesetting() {
+        let text = b"$c |- ph $.
+            $( $t
+                /* comments are allowed */
+                htmltitle \"Test \" + 'database';
+                htmldef \"|-\" as '<IMG SRC=''tstile.gif''>';
+                althtmldef \"|-\" as '&#8866; ';
+                latexdef \"ph\" as \"\\varphi\";
+                htmldef \"ph\" \"<I>\";
+                htmldef \"ph\" as \"<I>&phi;</I>\";
+            $)";
+        let mut db = Database::new(DbOptions::default());
+        db.parse("test.mm".to_owned(), vec![("test.mm".to_owned(), text.to_vec())]);
+        let ts = db.typesetting_result().clone();
+        assert_eq!(ts.setting("htmltitle"), Some("Test database"));
+        assert_eq!(ts.html_def(b"|-"), Some("<IMG SRC='tstile.gif'>"));
+        assert_eq!(ts.alt_html_def(b"|-"), Some("&#8866; "));
+        assert_eq!(ts.latex_def(b"ph"), Some("\\varphi"));
+        assert_eq!(ts.html_def(b"ph"), Some("<I>&phi;</I>"));
+        let diags = ts.diagnostics();
+        assert_eq!(diags.len(), 1);
+        assert!(matches!(diags[0].1, Diagnostic::TypesettingSyntax(_)));
+    }
+}
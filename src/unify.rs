@@ -0,0 +1,235 @@
+//! First-order unification over parsed math strings.
+//!
+//! Once a math string has been parsed by the `grammar` module it is a `Term`: a
+//! tree whose interior nodes are syntax axioms, and whose leaves are either
+//! variables of the database or metavariables.  Database variables are
+//! represented by the `$f` statement which gives them their type, and are rigid
+//! for unification purposes; in the scope of a theorem `ph` is a particular
+//! formula, not a pattern.  Metavariables stand for unknown subterms: the work
+//! variables of a proof worksheet, or the variables of an assertion which is
+//! being matched against some goal after renaming them apart.
+//!
+//! A `Subst` records the bindings of the metavariables allocated so far.  All
+//! binding operations are logged, so that a failed match can be rolled back and
+//! the next candidate tried; the callers of this module are mostly searches.
+
+use crate::grammar::Grammar;
+use crate::nameck::Atom;
+use crate::parser::StatementAddress;
+
+/// Identifier for a metavariable, an index into its `Subst`.
+pub type MetaVar = usize;
+
+/// A syntax tree.
+#[derive(Clone,Debug,PartialEq,Eq,Hash)]
+pub enum Term {
+    /// An application of a syntax axiom to subterms, which are given in the
+    /// order of the axiom's hypotheses.  A database variable is represented as
+    /// an application of its `$f` statement to no subterms.
+    App(StatementAddress, Vec<Term>),
+    /// A metavariable.
+    Meta(MetaVar),
+}
+
+impl Term {
+    /// Renumbers all metavariables by adding `base`, used to instantiate a
+    /// pattern whose variables are numbered from zero.
+    pub fn shift(&self, base: MetaVar) -> Term {
+        match *self {
+            Term::App(addr, ref children) => {
+                Term::App(addr, children.iter().map(|c| c.shift(base)).collect())
+            }
+            Term::Meta(m) => Term::Meta(m + base),
+        }
+    }
+
+    /// Replaces each metavariable `m` by `values[m]`, used to instantiate a
+    /// pattern whose variables are numbered from zero.
+    pub fn substitute(&self, values: &[Term]) -> Term {
+        match *self {
+            Term::App(addr, ref children) => {
+                Term::App(addr, children.iter().map(|c| c.substitute(values)).collect())
+            }
+            Term::Meta(m) => values[m].clone(),
+        }
+    }
+
+    /// Returns true if no metavariables appear in this term.
+    pub fn is_ground(&self) -> bool {
+        match *self {
+            Term::App(_, ref children) => children.iter().all(Term::is_ground),
+            Term::Meta(_) => false,
+        }
+    }
+
+    /// Counts the nodes of this term.
+    pub fn size(&self) -> usize {
+        match *self {
+            Term::App(_, ref children) => 1 + children.iter().map(Term::size).sum::<usize>(),
+            Term::Meta(_) => 1,
+        }
+    }
+
+    /// Collects the statements which are applied to no subterms in this term,
+    /// without duplicates, in order of first appearance.  These are the `$f`
+    /// statements of variables, but also constant syntax such as `T.`.
+    pub fn leaves(&self, out: &mut Vec<StatementAddress>) {
+        match *self {
+            Term::App(addr, ref children) => {
+                if children.is_empty() {
+                    if !out.contains(&addr) {
+                        out.push(addr);
+                    }
+                } else {
+                    for child in children {
+                        child.leaves(out);
+                    }
+                }
+            }
+            Term::Meta(_) => {}
+        }
+    }
+}
+
+/// A set of metavariables, their types, and their bindings.
+#[derive(Clone,Debug,Default)]
+pub struct Subst {
+    bindings: Vec<Option<Term>>,
+    types: Vec<Atom>,
+    trail: Vec<MetaVar>,
+}
+
+impl Subst {
+    /// Creates an empty substitution.
+    pub fn new() -> Subst {
+        Subst::default()
+    }
+
+    /// Allocates a new unbound metavariable with a given typecode.
+    pub fn fresh(&mut self, typecode: Atom) -> MetaVar {
+        self.bindings.push(None);
+        self.types.push(typecode);
+        self.bindings.len() - 1
+    }
+
+    /// Number of metavariables allocated so far.
+    pub fn len(&self) -> usize {
+        self.bindings.len()
+    }
+
+    /// True if no metavariables have been allocated.
+    pub fn is_empty(&self) -> bool {
+        self.bindings.is_empty()
+    }
+
+    /// Typecode of a metavariable.
+    pub fn typecode(&self, var: MetaVar) -> Atom {
+        self.types[var]
+    }
+
+    /// Current binding of a metavariable, if any.
+    pub fn binding(&self, var: MetaVar) -> Option<&Term> {
+        self.bindings[var].as_ref()
+    }
+
+    /// Returns a token which can later be passed to `undo` to remove all
+    /// bindings made after this point.
+    pub fn mark(&self) -> usize {
+        self.trail.len()
+    }
+
+    /// Removes all bindings made since `mark` was called.
+    pub fn undo(&mut self, mark: usize) {
+        while self.trail.len() > mark {
+            let var = self.trail.pop().unwrap();
+            self.bindings[var] = None;
+        }
+    }
+
+    /// Forgets all metavariables allocated after the first `len`.  They must be
+    /// unbound and not referenced by any binding, which is the case after
+    /// undoing to a mark taken before they were allocated.
+    pub fn truncate(&mut self, len: usize) {
+        debug_assert!(self.bindings[len..].iter().all(Option::is_none));
+        self.bindings.truncate(len);
+        self.types.truncate(len);
+    }
+
+    fn walk<'a>(&'a self, mut term: &'a Term) -> &'a Term {
+        while let Term::Meta(m) = *term {
+            match self.bindings[m] {
+                Some(ref bound) => term = bound,
+                None => break,
+            }
+        }
+        term
+    }
+
+    /// Applies the substitution to a term, replacing bound metavariables by
+    /// their (recursively resolved) values.
+    pub fn resolve(&self, term: &Term) -> Term {
+        match *self.walk(term) {
+            Term::App(addr, ref children) => {
+                Term::App(addr, children.iter().map(|c| self.resolve(c)).collect())
+            }
+            Term::Meta(m) => Term::Meta(m),
+        }
+    }
+
+    fn occurs(&self, var: MetaVar, term: &Term) -> bool {
+        match *self.walk(term) {
+            Term::App(_, ref children) => children.iter().any(|c| self.occurs(var, c)),
+            Term::Meta(m) => m == var,
+        }
+    }
+
+    fn bind(&mut self, var: MetaVar, term: Term) {
+        self.bindings[var] = Some(term);
+        self.trail.push(var);
+    }
+
+    fn unify_inner(&mut self, grammar: &Grammar, left: &Term, right: &Term) -> bool {
+        let left = self.walk(left).clone();
+        let right = self.walk(right).clone();
+        match (left, right) {
+            (Term::Meta(a), Term::Meta(b)) => {
+                if a != b {
+                    if self.types[a] != self.types[b] {
+                        return false;
+                    }
+                    self.bind(a, Term::Meta(b));
+                }
+                true
+            }
+            (Term::Meta(a), term) | (term, Term::Meta(a)) => {
+                let tc = match term {
+                    Term::App(addr, _) => grammar.typecode_of(addr),
+                    Term::Meta(_) => unreachable!(),
+                };
+                if tc != Some(self.types[a]) || self.occurs(a, &term) {
+                    return false;
+                }
+                self.bind(a, term);
+                true
+            }
+            (Term::App(la, lc), Term::App(ra, rc)) => {
+                la == ra && lc.len() == rc.len() &&
+                lc.iter().zip(&rc).all(|(l, r)| self.unify_inner(grammar, l, r))
+            }
+        }
+    }
+
+    /// Attempts to make two terms equal by binding metavariables.
+    ///
+    /// Bindings must respect the typecodes of metavariables, as reported for
+    /// syntax axioms and `$f` statements by the grammar.  On failure, any
+    /// bindings made during the attempt are removed again.
+    pub fn unify(&mut self, grammar: &Grammar, left: &Term, right: &Term) -> bool {
+        let mark = self.mark();
+        let ok = self.unify_inner(grammar, left, right);
+        if !ok {
+            self.undo(mark);
+        }
+        ok
+    }
+}
@@ -25,7 +25,9 @@
 //! than it is now.
 
 use crate::bit_set::Bitset;
+use crate::database::Promise;
 use crate::diag::Diagnostic;
+use crate::diag::ProofStep;
 use crate::nameck::Atom;
 use crate::nameck::Nameset;
 use crate::parser;
@@ -38,8 +40,10 @@ use crate::parser::SegmentOrder;
 use crate::parser::SegmentRef;
 use crate::parser::Span;
 use crate::parser::StatementAddress;
+use crate::parser::StatementIndex;
 use crate::parser::StatementRef;
 use crate::parser::StatementType;
+use crate::parser::Token;
 use crate::parser::TokenPtr;
 use crate::scopeck;
 use crate::scopeck::ExprFragment;
@@ -51,10 +55,13 @@ use crate::scopeck::ScopeUsage;
 use crate::scopeck::VerifyExpr;
 use crate::segment_set::SegmentSet;
 use std::cmp::Ordering;
+use std::cmp::Reverse;
 use std::mem;
 use std::ops::Range;
 use std::result;
 use std::sync::Arc;
+use std::time::Duration;
+use std::time::Instant;
 use std::u32;
 use std::usize;
 use crate::util::copy_portion;
@@ -116,6 +123,11 @@ pub trait ProofBuilder {
              pool: &[u8],
              expr: Range<usize>)
              -> Self::Item;
+
+    /// Create a proof data node for a `?` step, which stands for an unknown
+    /// subproof.  Proofs containing such steps are never returned as valid, so
+    /// this only needs to be a placeholder.
+    fn unknown(&mut self) -> Self::Item;
 }
 
 /// The "null" proof builder, which creates no extra data. This
@@ -128,6 +140,8 @@ impl ProofBuilder for () {
     fn push(&mut self, _: &mut (), _: ()) {}
 
     fn build(&mut self, _: StatementAddress, _: (), _: &[u8], _: Range<usize>) {}
+
+    fn unknown(&mut self) {}
 }
 
 /// Working memory used by the verifier on a segment.  This expands for the
@@ -161,10 +175,162 @@ struct VerifyState<'a, P: 'a + ProofBuilder> {
     var2bit: HashMap<Atom, usize>,
     /// Disjoint variable conditions in the current extended frame
     dv_map: &'a [Bitset],
+    /// True once a `?` step has been seen in the current proof; enables the
+    /// slower checks for unknown values
+    incomplete: bool,
+    /// Statements which `?` steps in the current proof would need to prove
+    subgoals: Vec<Token>,
+    /// Number of steps executed so far in the current proof, for diagnostics
+    step: usize,
+    /// When present, `$d` conditions are recorded here instead of checked
+    dv_log: Option<DvLog>,
+    /// Bytes of math strings written by substitutions in the current proof
+    subst_bytes: usize,
+    /// Largest stack depth reached in the current proof
+    max_stack: usize,
+}
+
+/// Disjoint variable conditions encountered while replaying a proof, as
+/// variable bits of the current proof.
+#[derive(Default)]
+struct DvLog {
+    /// Pairs which must be disjoint for each step to be valid
+    needed: Vec<(usize, usize)>,
+    /// Variables occurring in any substitution
+    used: Bitset,
 }
 
 type Result<T> = result::Result<T, Diagnostic>;
 
+/// Expression range of the stack slot pushed for a `?` step, which is a
+/// wildcard of unknown type.  Also stored in `subst_info` for a variable
+/// substituted by such a step.
+const UNKNOWN_GOAL: Range<usize> = usize::MAX..usize::MAX;
+
+/// Expression range of a stack slot whose type is known, but whose content
+/// depends on an unknown step.  These are not subgoals, as they have a proof.
+const UNKNOWN_TERM: Range<usize> = usize::MAX - 1..usize::MAX - 1;
+
+fn is_unknown(expr: &Range<usize>) -> bool {
+    expr.start >= UNKNOWN_TERM.start
+}
+
+fn uses_unknown(expr: &[ExprFragment], vars: &[(Range<usize>, Bitset)]) -> bool {
+    expr.iter().any(|part| is_unknown(&vars[part.var].0))
+}
+
+// a substitution containing a `?` step, whose variables are not all known,
+// cannot be checked against $d constraints yet
+fn dv_unknown(vars: &[(Range<usize>, Bitset)], ix1: usize, ix2: usize) -> bool {
+    is_unknown(&vars[ix1].0) || is_unknown(&vars[ix2].0)
+}
+
+// write a math string for display, with `?` for unknown substitutions
+fn render_goal(nameset: &Nameset,
+               frame: &Frame,
+               expr: &VerifyExpr,
+               vars: &[(Range<usize>, Bitset)],
+               buffer: &[u8])
+               -> Token {
+    let mut raw = nameset.atom_name(expr.typecode).to_owned();
+    *raw.last_mut().unwrap() |= 0x80;
+    for part in &*expr.tail {
+        raw.extend_from_slice(&frame.const_pool[part.prefix.clone()]);
+        let value = &vars[part.var].0;
+        if is_unknown(value) {
+            raw.push(b'?' | 0x80);
+        } else {
+            raw.extend_from_slice(&buffer[value.clone()]);
+        }
+    }
+    raw.extend_from_slice(&frame.const_pool[expr.rump.clone()]);
+    readable_expr(&raw)
+}
+
+// convert a math string from the stack format to space-separated tokens
+fn readable_expr(raw: &[u8]) -> Token {
+    let mut out = Vec::with_capacity(raw.len() * 2);
+    for &chr in raw {
+        out.push(chr & 0x7F);
+        if chr & 0x80 != 0 {
+            out.push(b' ');
+        }
+    }
+    out.pop();
+    out.into_boxed_slice()
+}
+
+// write a typecode and math string for display
+fn render_expr(nameset: &Nameset, code: Atom, expr: &[u8]) -> Token {
+    let mut raw = nameset.atom_name(code).to_owned();
+    *raw.last_mut().unwrap() |= 0x80;
+    raw.extend_from_slice(expr);
+    readable_expr(&raw)
+}
+
+// write the content of a stack slot for display
+fn render_slot(nameset: &Nameset, slot: &StackSlot, buffer: &[u8]) -> Token {
+    if is_unknown(&slot.expr) {
+        render_expr(nameset, slot.code, &[b'?' | 0x80])
+    } else {
+        render_expr(nameset, slot.code, &buffer[slot.expr.clone()])
+    }
+}
+
+// write a $f hypothesis of an assertion for display
+fn render_float(nameset: &Nameset, frame: &Frame, var_index: usize, code: Atom) -> Token {
+    let mut var = nameset.atom_name(frame.var_list[var_index]).to_owned();
+    *var.last_mut().unwrap() |= 0x80;
+    render_expr(nameset, code, &var)
+}
+
+// find the name of a variable from its bit in the current proof; only used
+// when reporting errors, so a linear scan is fine
+fn bit_name<P: ProofBuilder>(state: &VerifyState<P>, bit: usize) -> Token {
+    let atom = state.var2bit
+        .iter()
+        .find(|&(_, &b)| b == bit)
+        .map(|(&atom, _)| atom)
+        .expect("variable bits are allocated by var2bit");
+    state.nameset.atom_name(atom).into()
+}
+
+// describe a $d violation: variables `var1` and `var2` occur in the
+// substitutions for the frame variables `ix1` and `ix2`
+fn dv_violation<P: ProofBuilder>(state: &VerifyState<P>,
+                                 frame: &Frame,
+                                 (ix1, ix2): (usize, usize),
+                                 (var1, var2): (usize, usize))
+                                 -> Diagnostic {
+    let expr1 = &state.stack_buffer[state.subst_info[ix1].0.clone()];
+    let expr2 = &state.stack_buffer[state.subst_info[ix2].0.clone()];
+    Diagnostic::ProofDvViolation(current_step(state, frame),
+                                 bit_name(state, var1),
+                                 bit_name(state, var2),
+                                 readable_expr(expr1),
+                                 readable_expr(expr2))
+}
+
+fn current_step<P: ProofBuilder>(state: &VerifyState<P>, frame: &Frame) -> ProofStep {
+    ProofStep {
+        index: state.step,
+        assertion: frame.valid.start,
+    }
+}
+
+/// A `?` step pushes a wildcard onto the stack.
+fn push_unknown<P: ProofBuilder>(state: &mut VerifyState<P>) {
+    state.step += 1;
+    state.incomplete = true;
+    let data = state.builder.unknown();
+    state.stack.push((data,
+                      StackSlot {
+        vars: Bitset::new(),
+        code: Atom::default(),
+        expr: UNKNOWN_GOAL,
+    }));
+}
+
 /// Variables are added lazily to the extended frame.  All variables which are
 /// associated with hypotheses or $d constraints are numbered by scopeck, but if
 /// a dummy variable is used in a proof without a $d constraint it won't be
@@ -434,17 +600,46 @@ fn process_hyp<P: ProofBuilder>(state: &mut VerifyState<P>,
     state.builder.push(datavec, data.clone());
     match *hyp {
         Floating(_addr, var_index, typecode) => {
-            try_assert!(slot.code == typecode, Diagnostic::StepFloatWrongType);
+            try_assert!(slot.code == typecode || slot.expr == UNKNOWN_GOAL,
+                        Diagnostic::StepFloatWrongType(current_step(state, frame),
+                                                       render_float(state.nameset,
+                                                                    frame,
+                                                                    var_index,
+                                                                    typecode),
+                                                       render_slot(state.nameset,
+                                                                   slot,
+                                                                   &state.stack_buffer)));
             state.subst_info[var_index] = (slot.expr.clone(), slot.vars.clone());
         }
         Essential(_addr, ref expr) => {
+            if state.incomplete &&
+               (is_unknown(&slot.expr) || uses_unknown(&expr.tail, &state.subst_info)) {
+                if slot.expr == UNKNOWN_GOAL {
+                    state.subgoals.push(render_goal(state.nameset,
+                                                    frame,
+                                                    expr,
+                                                    &state.subst_info,
+                                                    &state.stack_buffer));
+                } else {
+                    try_assert!(slot.code == expr.typecode, Diagnostic::StepEssenWrongType);
+                }
+                return Ok(());
+            }
             try_assert!(slot.code == expr.typecode, Diagnostic::StepEssenWrongType);
             try_assert!(do_substitute_eq(&state.stack_buffer[slot.expr.clone()],
                                          frame,
                                          expr,
                                          &state.subst_info,
                                          &state.stack_buffer),
-                        Diagnostic::StepEssenWrong);
+                        Diagnostic::StepEssenWrong(current_step(state, frame),
+                                                   render_goal(state.nameset,
+                                                               frame,
+                                                               expr,
+                                                               &state.subst_info,
+                                                               &state.stack_buffer),
+                                                   render_slot(state.nameset,
+                                                               slot,
+                                                               &state.stack_buffer)));
         }
     }
     Ok(())
@@ -456,6 +651,7 @@ fn execute_step<P: ProofBuilder>(state: &mut VerifyState<P>,
                                  index: usize,
                                  explicit: Option<&mut Vec<Option<TokenPtr>>>)
                                  -> Result<()> {
+    state.step += 1;
     try_assert!(index < state.prepared.len(), Diagnostic::StepOutOfRange);
     let fref = match state.prepared[index] {
         Hyp(ref vars, code, ref expr, ref data) => {
@@ -467,6 +663,7 @@ fn execute_step<P: ProofBuilder>(state: &mut VerifyState<P>,
                 code,
                 expr: expr.clone(),
             }));
+            state.max_stack = state.max_stack.max(state.stack.len());
             return Ok(());
         }
         Assert(fref) => fref,
@@ -550,11 +747,15 @@ fn execute_step<P: ProofBuilder>(state: &mut VerifyState<P>,
     // deciding whether we need to move anything would swamp any savings, anyway
     // - remember that this function is largely a branch predictor benchmark
     let tos = state.stack_buffer.len();
-    do_substitute(&mut state.stack_buffer,
-                  fref,
-                  &fref.target,
-                  &state.subst_info);
+    let unknown = state.incomplete && uses_unknown(&fref.target.tail, &state.subst_info);
+    if !unknown {
+        do_substitute(&mut state.stack_buffer,
+                      fref,
+                      &fref.target,
+                      &state.subst_info);
+    }
     let ntos = state.stack_buffer.len();
+    state.subst_bytes += ntos - tos;
 
     state.stack.truncate(sbase);
     state.stack
@@ -562,17 +763,25 @@ fn execute_step<P: ProofBuilder>(state: &mut VerifyState<P>,
                StackSlot {
             code: fref.target.typecode,
             vars: do_substitute_vars(&fref.target.tail, &state.subst_info),
-            expr: tos..ntos,
+            expr: if unknown { UNKNOWN_TERM } else { tos..ntos },
         }));
+    state.max_stack = state.max_stack.max(state.stack.len());
+
+    if state.dv_log.is_some() {
+        return log_dv(state, fref);
+    }
 
     // check $d constraints on the used assertion now that the dust has settled.
     // Remember that we might have variable indexes allocated during the proof
     // that are out of range for dv_map
     for &(ix1, ix2) in &*fref.mandatory_dv {
+        if dv_unknown(&state.subst_info, ix1, ix2) {
+            continue;
+        }
         for var1 in &state.subst_info[ix1].1 {
             for var2 in &state.subst_info[ix2].1 {
                 try_assert!(var1 < state.dv_map.len() && state.dv_map[var1].has_bit(var2),
-                            Diagnostic::ProofDvViolation);
+                            dv_violation(state, fref, (ix1, ix2), (var1, var2)));
             }
         }
     }
@@ -580,20 +789,63 @@ fn execute_step<P: ProofBuilder>(state: &mut VerifyState<P>,
     Ok(())
 }
 
+// record the $d constraints of an assertion step instead of checking them
+// against dv_map; only a variable shared by both sides is a hard error
+fn log_dv<P: ProofBuilder>(state: &mut VerifyState<P>, frame: &Frame) -> Result<()> {
+    let mut log = state.dv_log.take().expect("only called when logging");
+    for (_, vars) in &state.subst_info[..frame.mandatory_count] {
+        log.used |= vars;
+    }
+    for &(ix1, ix2) in &*frame.mandatory_dv {
+        if dv_unknown(&state.subst_info, ix1, ix2) {
+            continue;
+        }
+        for var1 in &state.subst_info[ix1].1 {
+            for var2 in &state.subst_info[ix2].1 {
+                try_assert!(var1 != var2,
+                            dv_violation(state, frame, (ix1, ix2), (var1, var2)));
+                log.needed.push((var1.min(var2), var1.max(var2)));
+            }
+        }
+    }
+    state.dv_log = Some(log);
+    Ok(())
+}
+
 fn finalize_step<P: ProofBuilder>(state: &mut VerifyState<P>) -> Result<P::Item> {
     // if we get here, it's a valid proof, but was it the _right_ valid proof?
     try_assert!(state.stack.len() <= 1, Diagnostic::ProofExcessEnd);
     let &(ref data, ref tos) = state.stack.last().ok_or(Diagnostic::ProofNoSteps)?;
 
-    try_assert!(tos.code == state.cur_frame.target.typecode,
-                Diagnostic::ProofWrongTypeEnd);
-
-    fast_clear(&mut state.temp_buffer);
-    do_substitute_raw(&mut state.temp_buffer, state.cur_frame, state.nameset);
-
-    try_assert!(state.stack_buffer[tos.expr.clone()] == state.temp_buffer[..],
-                Diagnostic::ProofWrongExprEnd);
+    if tos.expr == UNKNOWN_GOAL {
+        // the whole proof is missing
+        fast_clear(&mut state.temp_buffer);
+        fast_extend(&mut state.temp_buffer,
+                    state.nameset.atom_name(state.cur_frame.target.typecode));
+        *state.temp_buffer.last_mut().unwrap() |= 0x80;
+        do_substitute_raw(&mut state.temp_buffer, state.cur_frame, state.nameset);
+        state.subgoals.push(readable_expr(&state.temp_buffer));
+    } else {
+        try_assert!(tos.code == state.cur_frame.target.typecode,
+                    Diagnostic::ProofWrongTypeEnd);
+
+        if !is_unknown(&tos.expr) {
+            fast_clear(&mut state.temp_buffer);
+            do_substitute_raw(&mut state.temp_buffer, state.cur_frame, state.nameset);
+
+            try_assert!(state.stack_buffer[tos.expr.clone()] == state.temp_buffer[..],
+                        Diagnostic::ProofWrongExprEnd(render_expr(state.nameset,
+                                                                  tos.code,
+                                                                  &state.temp_buffer),
+                                                      render_slot(state.nameset,
+                                                                  tos,
+                                                                  &state.stack_buffer)));
+        }
+    }
 
+    if state.incomplete {
+        return Err(Diagnostic::ProofIncomplete(mem::take(&mut state.subgoals).into()));
+    }
     Ok(data.clone())
 }
 
@@ -612,6 +864,11 @@ fn verify_proof<'a, P: ProofBuilder>(state: &mut VerifyState<'a, P>,
     state.prepared.clear();
     state.var2bit.clear();
     state.dv_map = &state.cur_frame.optional_dv;
+    state.incomplete = false;
+    state.subgoals.clear();
+    state.step = 0;
+    state.subst_bytes = 0;
+    state.max_stack = 0;
     // temp_buffer is cleared before use; subst_info should be overwritten
     // before use if scopeck is working correctly
 
@@ -669,7 +926,8 @@ fn verify_proof<'a, P: ProofBuilder>(state: &mut VerifyState<'a, P>,
                     can_save = false;
                 } else if ch == b'?' {
                     try_assert!(k == 0, Diagnostic::ProofMalformedVarint);
-                    return Err(Diagnostic::ProofIncomplete);
+                    push_unknown(state);
+                    can_save = false;
                 }
             }
             i += 1;
@@ -685,7 +943,13 @@ fn verify_proof<'a, P: ProofBuilder>(state: &mut VerifyState<'a, P>,
         for i in 0..stmt.proof_len() {
             let span = stmt.proof_span(i);
             let chunk = stmt.proof_slice_at(i);
-            try_assert!(chunk != b"?", Diagnostic::ProofIncomplete);
+            if chunk == b"?" {
+                push_unknown(state);
+                if let Some(ref mut explicit_stack) = explicit_stack {
+                    explicit_stack.push(None);
+                }
+                continue;
+            }
             let step = prepare_step(state, chunk, Some(span))?;
             if let Some(label) = step.label {
                 try_assert!(step.fwdref.is_none(), Diagnostic::ChainBackref(span));
@@ -716,11 +980,66 @@ fn verify_proof<'a, P: ProofBuilder>(state: &mut VerifyState<'a, P>,
     finalize_step(state)
 }
 
+/// Cost of verifying one proof, recorded when `DbOptions::profile` is set.
+#[derive(Debug,Clone,Copy)]
+pub struct ProofProfile {
+    /// The `$p` statement whose proof was checked.
+    pub address: StatementAddress,
+    /// Time spent checking the proof.
+    pub time: Duration,
+    /// Number of proof steps executed.
+    pub steps: usize,
+    /// Bytes of math strings written while substituting into assertions.
+    pub subst_bytes: usize,
+    /// Largest number of entries on the proof stack.
+    pub max_stack: usize,
+}
+
+/// Cost of verifying one segment, recorded when `DbOptions::profile` is set.
+#[derive(Debug,Clone,Copy)]
+pub struct SegmentProfile {
+    /// The segment which was checked.
+    pub segment: SegmentId,
+    /// Sum of the estimates given to the `Executor` for the segment's jobs.
+    pub estimate: usize,
+    /// Time spent in the segment's jobs, summed over all workers.
+    pub time: Duration,
+    /// Number of proofs checked.
+    pub proofs: usize,
+    /// Number of proof steps executed.
+    pub steps: usize,
+}
+
 /// Stored result of running the verifier on a segment.
 struct VerifySegment {
     source: Arc<Segment>,
     scope_usage: ScopeUsage,
     diagnostics: HashMap<StatementAddress, Diagnostic>,
+    /// Costs of each proof, empty unless profiling
+    proofs: Vec<ProofProfile>,
+    /// Job estimate and time for the whole segment, zero unless profiling
+    estimate: usize,
+    time: Duration,
+    /// False if verification was cancelled before reaching every statement
+    complete: bool,
+}
+
+impl VerifySegment {
+    /// True if this result still applies to the current version of a segment.
+    fn is_current(&self, sref: SegmentRef, nset: &Nameset, scope: &ScopeResult) -> bool {
+        self.complete && self.scope_usage.valid(nset, scope) &&
+        ptr_eq::<Segment>(&self.source, &sref)
+    }
+
+    /// Adds the results of another chunk of the same segment.
+    fn merge(&mut self, other: VerifySegment) {
+        self.scope_usage.merge(other.scope_usage);
+        self.diagnostics.extend(other.diagnostics);
+        self.proofs.extend(other.proofs);
+        self.estimate += other.estimate;
+        self.time += other.time;
+        self.complete &= other.complete;
+    }
 }
 
 /// Analysis pass result for the verifier.
@@ -740,15 +1059,82 @@ impl VerifyResult {
         }
         out
     }
+
+    /// Report the cost of each proof checked by the last verification, most
+    /// expensive first.  This is empty unless `DbOptions::profile` was set.
+    ///
+    /// Segments which were reused from an earlier verification keep the costs
+    /// measured at that time.
+    pub fn proof_profiles(&self) -> Vec<ProofProfile> {
+        let mut out: Vec<ProofProfile> = self.segments
+            .values()
+            .flat_map(|vsr| vsr.proofs.iter().cloned())
+            .collect();
+        out.sort_by_key(|profile| Reverse(profile.time));
+        out
+    }
+
+    /// Report the total cost of each segment checked by the last verification,
+    /// most expensive first.  This is empty unless `DbOptions::profile` was set.
+    pub fn segment_profiles(&self) -> Vec<SegmentProfile> {
+        let mut out: Vec<SegmentProfile> = self.segments
+            .iter()
+            .filter(|&(_, vsr)| vsr.estimate > 0 || !vsr.proofs.is_empty())
+            .map(|(&id, vsr)| {
+                SegmentProfile {
+                    segment: id,
+                    estimate: vsr.estimate,
+                    time: vsr.time,
+                    proofs: vsr.proofs.len(),
+                    steps: vsr.proofs.iter().map(|proof| proof.steps).sum(),
+                }
+            })
+            .collect();
+        out.sort_by_key(|profile| Reverse(profile.time));
+        out
+    }
 }
 
-/// Driver which verifies each statement in a segment.
-fn verify_segment(sset: &SegmentSet,
-                  nset: &Nameset,
-                  scopes: &ScopeResult,
-                  sid: SegmentId)
-                  -> VerifySegment {
+/// Verification of a segment is split into jobs for runs of statements with
+/// about this many bytes of source, so that one large segment can use several
+/// workers.
+const CHUNK_BYTES: usize = 1 << 16;
+
+/// Divide the statements of a segment into runs for `verify_chunk`.
+fn segment_chunks(sref: SegmentRef) -> Vec<(Range<StatementIndex>, usize)> {
+    let mut chunks = Vec::new();
+    let mut start = 0;
+    let mut bytes = 0;
+    let mut end = 0;
+    for stmt in sref {
+        let span = stmt.span_full();
+        end = stmt.index() + 1;
+        bytes += (span.end - span.start) as usize;
+        if bytes >= CHUNK_BYTES {
+            chunks.push((start..end, bytes));
+            start = end;
+            bytes = 0;
+        }
+    }
+    if start < end || chunks.is_empty() {
+        chunks.push((start..end, bytes));
+    }
+    chunks
+}
+
+/// Driver which verifies each statement in a run of statements from a
+/// segment.
+fn verify_chunk(sset: &SegmentSet,
+                nset: &Nameset,
+                scopes: &ScopeResult,
+                sid: SegmentId,
+                range: Range<StatementIndex>,
+                estimate: usize)
+                -> VerifySegment {
+    let profile = sset.options.profile;
+    let chunk_start = Instant::now();
     let mut diagnostics = new_map();
+    let mut proofs = Vec::new();
     let dummy_frame = Frame::default();
     let sref = sset.segment(sid);
     let mut state = VerifyState {
@@ -765,29 +1151,59 @@ fn verify_segment(sset: &SegmentSet,
         subst_info: Vec::new(),
         var2bit: new_map(),
         dv_map: &dummy_frame.optional_dv,
+        incomplete: false,
+        subgoals: Vec::new(),
+        step: 0,
+        dv_log: None,
+        subst_bytes: 0,
+        max_stack: 0,
     };
+    let mut complete = true;
     // use the _same_ VerifyState so that memory can be reused
-    for stmt in sref {
+    for index in range {
+        if sset.exec.is_cancelled() {
+            complete = false;
+            break;
+        }
+        let stmt = sref.statement(index);
         // only intend to check $p statements
         if stmt.statement_type() == StatementType::Provable {
             // no valid frame -> no use checking
             // may wish to record a secondary error?
             if let Some(frame) = state.scoper.get(stmt.label()) {
                 state.cur_frame = frame;
+                let start = if profile { Some(Instant::now()) } else { None };
                 if let Err(diag) = verify_proof(&mut state, stmt) {
                     diagnostics.insert(stmt.address(), diag);
                 }
+                if let Some(start) = start {
+                    proofs.push(ProofProfile {
+                        address: stmt.address(),
+                        time: start.elapsed(),
+                        steps: state.step,
+                        subst_bytes: state.subst_bytes,
+                        max_stack: state.max_stack,
+                    });
+                }
             }
         }
     }
     VerifySegment {
         source: (*sref).clone(),
-        diagnostics,
         scope_usage: state.scoper.into_usage(),
+        diagnostics,
+        proofs,
+        estimate: if profile { estimate } else { 0 },
+        time: if profile { chunk_start.elapsed() } else { Duration::default() },
+        complete,
     }
 }
 
 /// Calculates or updates the verification result for a database.
+///
+/// Each segment which has changed, or which uses frames that have changed, is
+/// verified again in chunks of statements running as separate jobs; the
+/// chunks are then merged into a single result for the segment.
 pub fn verify(result: &mut VerifyResult,
               segments: &Arc<SegmentSet>,
               nset: &Arc<Nameset>,
@@ -795,31 +1211,96 @@ pub fn verify(result: &mut VerifyResult,
     let old = mem::replace(&mut result.segments, new_map());
     let mut ssrq = Vec::new();
     for sref in segments.segments() {
-        let segments2 = segments.clone();
-        let nset = nset.clone();
-        let scope = scope.clone();
         let id = sref.id;
-        let old_res_o = old.get(&id).cloned();
-        ssrq.push(segments.exec.exec(sref.bytes(), move || {
-            let sref = segments2.segment(id);
-            if let Some(old_res) = old_res_o {
-                if old_res.scope_usage.valid(&nset, &scope) &&
-                   ptr_eq::<Segment>(&old_res.source, &sref) {
-                    return (id, old_res);
-                }
+        if let Some(old_res) = old.get(&id) {
+            if old_res.is_current(sref, nset, scope) {
+                ssrq.push((id, Promise::new(old_res.clone())));
+                continue;
             }
-            if segments2.options.trace_recalc {
-                println!("verify({:?})", parser::guess_buffer_name(&sref.buffer));
+        }
+        if segments.options.trace_recalc {
+            println!("verify({:?})", parser::guess_buffer_name(&sref.buffer));
+        }
+        let mut chunks = Vec::new();
+        for (range, bytes) in segment_chunks(sref) {
+            let segments2 = segments.clone();
+            let nset = nset.clone();
+            let scope = scope.clone();
+            chunks.push(segments.exec.exec(bytes, move || {
+                verify_chunk(&segments2, &nset, &scope, id, range, bytes)
+            }));
+        }
+        ssrq.push((id,
+                   Promise::join(chunks).map(|chunks| {
+            let mut chunks = chunks.into_iter();
+            let mut vsr = chunks.next().expect("at least one chunk");
+            for chunk in chunks {
+                vsr.merge(chunk);
             }
-            (id, Arc::new(verify_segment(&segments2, &nset, &scope, id)))
-        }))
+            Arc::new(vsr)
+        })));
     }
 
     result.segments.clear();
-    for promise in ssrq {
-        let (id, arc) = promise.wait();
-        result.segments.insert(id, arc);
+    let total = ssrq.len();
+    for (done, (id, promise)) in ssrq.into_iter().enumerate() {
+        result.segments.insert(id, promise.wait());
+        segments.exec.progress("verify", done + 1, total);
+    }
+}
+
+/// Verifies only the given `$p` statements, returning their diagnostics.
+///
+/// Statements in segments for which `result` is still current take their
+/// diagnostics from it; the others are checked with `verify_one`, using one
+/// job per segment.  `result` is not updated.
+pub fn verify_selected(result: &VerifyResult,
+                       segments: &Arc<SegmentSet>,
+                       nset: &Arc<Nameset>,
+                       scope: &Arc<ScopeResult>,
+                       addrs: &[StatementAddress])
+                       -> Vec<(StatementAddress, Diagnostic)> {
+    let mut by_segment: HashMap<SegmentId, Vec<StatementAddress>> = new_map();
+    for &addr in addrs {
+        by_segment.entry(addr.segment_id).or_default().push(addr);
+    }
+
+    let mut out = Vec::new();
+    let mut promises = Vec::new();
+    for (id, addrs) in by_segment {
+        let sref = segments.segment(id);
+        if let Some(cached) = result.segments.get(&id) {
+            if cached.is_current(sref, nset, scope) {
+                out.extend(addrs.iter()
+                    .filter_map(|addr| cached.diagnostics.get(addr).map(|d| (*addr, d.clone()))));
+                continue;
+            }
+        }
+        let segments2 = segments.clone();
+        let nset = nset.clone();
+        let scope = scope.clone();
+        let estimate = addrs.iter()
+            .map(|&addr| sref.statement(addr.index).proof_len() as usize)
+            .sum();
+        promises.push(segments.exec.exec(estimate, move || {
+            let mut diags = Vec::new();
+            for addr in addrs {
+                let stmt = segments2.statement(addr);
+                if stmt.statement_type() != StatementType::Provable ||
+                   scope.get(stmt.label()).is_none() {
+                    continue;
+                }
+                if let Err(diag) = verify_one(&segments2, &nset, &scope, &mut (), stmt) {
+                    diags.push((addr, diag));
+                }
+            }
+            diags
+        }));
+    }
+    for promise in promises {
+        out.extend(promise.wait());
     }
+    out
 }
 
 /// Parse a single $p statement, returning the result of the given
@@ -845,6 +1326,12 @@ pub fn verify_one<P: ProofBuilder>(sset: &SegmentSet,
         subst_info: Vec::new(),
         var2bit: new_map(),
         dv_map: &dummy_frame.optional_dv,
+        incomplete: false,
+        subgoals: Vec::new(),
+        step: 0,
+        dv_log: None,
+        subst_bytes: 0,
+        max_stack: 0,
     };
 
     assert!(stmt.statement_type() == StatementType::Provable);
@@ -852,3 +1339,231 @@ pub fn verify_one<P: ProofBuilder>(sset: &SegmentSet,
     state.cur_frame = frame;
     verify_proof(&mut state, stmt)
 }
+
+/// Disjoint variable conditions of a proof compared with the `$d` statements
+/// in scope, as calculated by `verify_dv`.  Each pair is sorted, and the lists
+/// are in order.
+#[derive(Debug,Default,Clone,Eq,PartialEq)]
+pub struct DvReport {
+    /// Pairs of variables which the proof needs to be disjoint, but which are
+    /// not declared so.
+    pub missing: Vec<(Token, Token)>,
+    /// Pairs of variables occurring in the proof which are declared disjoint,
+    /// but which the proof does not need.
+    pub excess: Vec<(Token, Token)>,
+}
+
+/// Replay the proof of a single $p statement, collecting the `$d` conditions
+/// that its steps need instead of requiring them to be declared.  Fails with
+/// the first error which is not a missing `$d`.
+pub fn verify_dv(sset: &SegmentSet,
+                 nset: &Nameset,
+                 scopes: &ScopeResult,
+                 stmt: StatementRef)
+                 -> result::Result<DvReport, Diagnostic> {
+    let dummy_frame = Frame::default();
+    let mut state = VerifyState {
+        this_seg: stmt.segment(),
+        scoper: ScopeReader::new(scopes),
+        nameset: nset,
+        builder: &mut (),
+        order: &sset.order,
+        cur_frame: &dummy_frame,
+        stack: Vec::new(),
+        stack_buffer: Vec::new(),
+        prepared: Vec::new(),
+        temp_buffer: Vec::new(),
+        subst_info: Vec::new(),
+        var2bit: new_map(),
+        dv_map: &dummy_frame.optional_dv,
+        incomplete: false,
+        subgoals: Vec::new(),
+        step: 0,
+        dv_log: Some(DvLog::default()),
+        subst_bytes: 0,
+        max_stack: 0,
+    };
+
+    assert!(stmt.statement_type() == StatementType::Provable);
+    let frame = state.scoper.get(stmt.label()).unwrap();
+    state.cur_frame = frame;
+    verify_proof(&mut state, stmt)?;
+
+    let mut log = state.dv_log.take().unwrap();
+    log.needed.sort_unstable();
+    log.needed.dedup();
+    let mut names = vec![Atom::default(); state.var2bit.len()];
+    for (&atom, &bit) in &state.var2bit {
+        names[bit] = atom;
+    }
+    let pair = |var1: usize, var2: usize| {
+        let (name1, name2) = (nset.atom_name(names[var1]), nset.atom_name(names[var2]));
+        (copy_token(name1.min(name2)), copy_token(name1.max(name2)))
+    };
+    let declared = |var1: usize, var2: usize| {
+        var1 < state.dv_map.len() && state.dv_map[var1].has_bit(var2)
+    };
+    let used = |var: usize| var < frame.mandatory_count || log.used.has_bit(var);
+
+    let mut report = DvReport::default();
+    for &(var1, var2) in &log.needed {
+        if !declared(var1, var2) {
+            report.missing.push(pair(var1, var2));
+        }
+    }
+    for var1 in 0..state.dv_map.len() {
+        for var2 in &state.dv_map[var1] {
+            if var1 < var2 && used(var1) && used(var2) &&
+               log.needed.binary_search(&(var1, var2)).is_err() {
+                report.excess.push(pair(var1, var2));
+            }
+        }
+    }
+    report.missing.sort();
+    report.excess.sort();
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::database::Database;
+    use crate::database::DbOptions;
+    use crate::diag::Diagnostic;
+    use crate::diag::DiagnosticClass;
+    use crate::test_util::database;
+    use crate::test_util::PROP;
+
+    fn with_proof(proof: &str) -> Database {
+        let text = format!("${{
+                $d ph ps $.
+                ax-dv $a |- ( ph -> ps ) $.
+            $}}
+            ${{
+                a1i.1 $e |- ph $.
+                a1i $p |- ( ps -> ph ) $= {} $.
+            $}}",
+                           proof);
+        database(&[PROP, &text].concat())
+    }
+
+    fn verify(proof: &str) -> Vec<Diagnostic> {
+        with_proof(proof).verify_result().diagnostics().into_iter().map(|(_, diag)| diag).collect()
+    }
+
+    // the message arguments of the single verify error, without the
+    // statement label which every diagnostic would share
+    fn report(proof: &str) -> Vec<(&'static str, String)> {
+        let notes = with_proof(proof).diag_notations(vec![DiagnosticClass::Verify]);
+        assert_eq!(notes.len(), 1);
+        notes[0].args.iter().filter(|&&(name, _)| name != "label").cloned().collect()
+    }
+
+    fn args(list: &[(&'static str, &str)]) -> Vec<(&'static str, String)> {
+        list.iter().map(|&(name, value)| (name, value.to_owned())).collect()
+    }
+
+    fn incomplete(goals: &[&str]) -> Vec<Diagnostic> {
+        let goals = goals.iter().map(|goal| goal.as_bytes().into()).collect();
+        vec![Diagnostic::ProofIncomplete(goals)]
+    }
+
+    #[test]
+    fn test_unknown_hypothesis() {
+        assert_eq!(verify("wph wps wph wi a1i.1 wph wps ax-1 ax-mp"), vec![]);
+        assert_eq!(verify("wph wps wph wi ? wph wps ax-1 ax-mp"), incomplete(&["|- ph"]));
+        assert_eq!(verify("( wi ax-1 ax-mp ) ABAD?ABEF"), incomplete(&["|- ph"]));
+        assert_eq!(verify("wph wps wph wi ? ? ax-mp"),
+                   incomplete(&["|- ph", "|- ( ph -> ( ps -> ph ) )"]));
+    }
+
+    #[test]
+    fn test_unknown_proof() {
+        assert_eq!(verify("?"), incomplete(&["|- ( ps -> ph )"]));
+    }
+
+    #[test]
+    fn test_unknown_syntax() {
+        // the major premise depends on the unknown wff, so it cannot be checked
+        assert_eq!(verify("wph ? wph wi a1i.1 wph wps ax-1 ax-mp"), incomplete(&[]));
+        assert_eq!(verify("wph wps wph wi a1i.1 ? ax-mp"),
+                   incomplete(&["|- ( ph -> ( ps -> ph ) )"]));
+    }
+
+    #[test]
+    fn test_unknown_with_error() {
+        assert!(matches!(verify("wph wps wph wi ? wph wph ax-1 ax-mp")[..],
+                         [Diagnostic::StepEssenWrong(step, _, _)] if step.index == 9));
+        assert_eq!(verify("wph wps wph wi ? wph ax-1 ax-mp"),
+                   vec![Diagnostic::ProofUnderflow]);
+    }
+
+    #[test]
+    fn test_essential_report() {
+        assert_eq!(report("wph wps wph wi a1i.1 wph wph ax-1 ax-mp"),
+                   args(&[("step", "9"),
+                          ("assertion", "ax-mp"),
+                          ("expected", "|- ( ph -> ( ps -> ph ) )"),
+                          ("actual", "|- ( ph -> ( ph -> ph ) )")]));
+    }
+
+    #[test]
+    fn test_float_report() {
+        assert_eq!(report("a1i.1 wps wi"),
+                   args(&[("step", "3"),
+                          ("assertion", "wi"),
+                          ("expected", "wff ph"),
+                          ("actual", "|- ph")]));
+    }
+
+    #[test]
+    fn test_dv_report() {
+        assert_eq!(report("wps wph ax-dv"),
+                   args(&[("step", "3"),
+                          ("assertion", "ax-dv"),
+                          ("var1", "ps"),
+                          ("var2", "ph"),
+                          ("expr1", "ps"),
+                          ("expr2", "ph")]));
+    }
+
+    #[test]
+    fn test_wrong_end_report() {
+        assert_eq!(report("a1i.1"),
+                   args(&[("expected", "|- ( ps -> ph )"), ("actual", "|- ph")]));
+    }
+
+    #[test]
+    fn test_chunked_segment() {
+        // large enough to be split into several chunks
+        let mut text = "$c |- wff $. $v ph $. wph $f wff ph $. ax $a |- ph $.\n".to_owned();
+        for i in 0..5000 {
+            let proof = if i == 4321 { "ax" } else { "wph ax" };
+            text.push_str(&format!("th{} $p |- ph $= {} $.\n", i, proof));
+        }
+        let mut db = Database::new(DbOptions { jobs: 4, ..DbOptions::default() });
+        db.parse("test.mm".to_owned(), vec![("test.mm".to_owned(), text.into_bytes())]);
+        assert!(db.parse_result().segments()[0].bytes() > 2 * super::CHUNK_BYTES);
+        let diags = db.verify_result().diagnostics();
+        assert_eq!(diags.len(), 1);
+        assert_eq!(db.parse_result().statement(diags[0].0).label(), b"th4321");
+    }
+
+    #[test]
+    fn test_profile() {
+        let text = b"$c |- wff ( ) -> $. $v ph ps $. wph $f wff ph $. wps $f wff ps $.
+            wi $a wff ( ph -> ps ) $.
+            ax $a |- ph $.
+            th $p |- ( ph -> ph ) $= wph wph wi ax $.";
+        let mut db = Database::new(DbOptions { profile: true, ..DbOptions::default() });
+        db.parse("test.mm".to_owned(), vec![("test.mm".to_owned(), text.to_vec())]);
+        let result = db.verify_result().clone();
+        let proofs = result.proof_profiles();
+        assert_eq!(proofs.len(), 1);
+        assert_eq!((proofs[0].steps, proofs[0].max_stack), (4, 2));
+        // `( ph -> ph )` is written twice, without separators
+        assert_eq!(proofs[0].subst_bytes, 16);
+        let segments = result.segment_profiles();
+        assert_eq!(segments.len(), 1);
+        assert_eq!((segments[0].proofs, segments[0].steps), (1, 4));
+    }
+}